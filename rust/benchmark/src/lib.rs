@@ -0,0 +1,61 @@
+//! What the lexer and parser benchmarks share: an allocator that counts
+//! allocations, the source they run on and how a run is timed.
+//!
+//! Both benchmarks take this crate as a dev-dependency, so they measure the
+//! same input the same way. It installs the global allocator, so it is only
+//! for benchmarks.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const ITERATIONS: usize = 20;
+
+pub fn source() -> String {
+    let mut source = String::new();
+    for i in 0..5_000 {
+        source.push_str(&format!(
+            "let value_{i} = fn(left, right) {{ if (left < right) {{ return left * {i}; }} else {{ right - left }} }};\n"
+        ));
+    }
+    source
+}
+
+/// Times `run`, which returns how many tokens it went through, and prints
+/// the time and allocations per token. A first, untimed run warms up.
+pub fn measure(name: &str, mut run: impl FnMut() -> usize) {
+    let tokens = run();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(run());
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    let per_run = (tokens * ITERATIONS) as f64;
+    println!(
+        "{:<24} {:>8.2} ns/token {:>8.3} allocations/token",
+        name,
+        elapsed.as_nanos() as f64 / per_run,
+        allocations as f64 / per_run
+    );
+}
@@ -0,0 +1,225 @@
+//! Functions implemented in Rust and callable from Monkey.
+//!
+//! An identifier that no `let` or parameter binds is looked up here, so a
+//! script can shadow a builtin with its own binding.
+
+use std::{collections::HashMap, fmt, io::Write, rc::Rc};
+
+use lexer::symbol::Symbol;
+
+use crate::object::Object;
+
+/// How many arguments a builtin takes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arity {
+    Exact(usize),
+    /// Any number, including none.
+    Any,
+}
+
+/// The Rust side of a builtin: it gets the arguments and the evaluator's
+/// output, and fails with a message.
+pub type NativeFn = dyn Fn(&[Object], &mut dyn Write) -> Result<Object, String>;
+
+pub struct Builtin {
+    name: String,
+    arity: Arity,
+    function: Box<NativeFn>,
+}
+
+impl Builtin {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn arity(&self) -> Arity {
+        self.arity
+    }
+
+    /// Checks the number of arguments, then runs the function.
+    pub fn call(&self, arguments: &[Object], output: &mut dyn Write) -> Result<Object, String> {
+        if let Arity::Exact(expected) = self.arity {
+            if arguments.len() != expected {
+                return Err(format!(
+                    "Wrong number of arguments to '{}': expected {}, found {}",
+                    self.name,
+                    expected,
+                    arguments.len()
+                ));
+            }
+        }
+        (self.function)(arguments, output)
+    }
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Builtin")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A set of builtins by name. `Default` is empty; `standard` has `len`,
+/// `first`, `last`, `rest`, `push` and `puts`.
+#[derive(Clone, Default)]
+pub struct Builtins {
+    functions: HashMap<Symbol, Rc<Builtin>>,
+}
+
+impl Builtins {
+    pub fn standard() -> Self {
+        let mut builtins = Builtins::default();
+        builtins.register("len", Arity::Exact(1), |args, _| match &args[0] {
+            Object::String(s) => Ok(Object::Integer(s.chars().count() as i64)),
+            Object::Array(elements) => Ok(Object::Integer(elements.len() as i64)),
+            Object::Hash(pairs) => Ok(Object::Integer(pairs.len() as i64)),
+            other => Err(expected("len", "a string, an array or a hash", other)),
+        });
+        builtins.register("first", Arity::Exact(1), |args, _| {
+            let elements = array("first", &args[0])?;
+            Ok(elements.first().cloned().unwrap_or(Object::Null))
+        });
+        builtins.register("last", Arity::Exact(1), |args, _| {
+            let elements = array("last", &args[0])?;
+            Ok(elements.last().cloned().unwrap_or(Object::Null))
+        });
+        builtins.register("rest", Arity::Exact(1), |args, _| {
+            match array("rest", &args[0])?.split_first() {
+                Some((_, rest)) => Ok(Object::from(rest.to_vec())),
+                None => Ok(Object::Null),
+            }
+        });
+        builtins.register("push", Arity::Exact(2), |args, _| {
+            let mut elements = array("push", &args[0])?.to_vec();
+            elements.push(args[1].clone());
+            Ok(Object::from(elements))
+        });
+        builtins.register("puts", Arity::Any, |args, output| {
+            for arg in args {
+                writeln!(output, "{}", arg)
+                    .map_err(|e| format!("Could not write output: {}", e))?;
+            }
+            Ok(Object::Null)
+        });
+        builtins
+    }
+
+    /// Adds `function` as `name`, replacing any builtin by that name.
+    pub fn register(
+        &mut self,
+        name: &str,
+        arity: Arity,
+        function: impl Fn(&[Object], &mut dyn Write) -> Result<Object, String> + 'static,
+    ) {
+        let builtin = Builtin {
+            name: name.to_string(),
+            arity,
+            function: Box::new(function),
+        };
+        self.functions
+            .insert(Symbol::intern(name), Rc::new(builtin));
+    }
+
+    pub fn get(&self, name: Symbol) -> Option<&Rc<Builtin>> {
+        self.functions.get(&name)
+    }
+
+    /// The names of all builtins, in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.functions.values().map(|builtin| builtin.name())
+    }
+}
+
+fn expected(function: &str, what: &str, found: &Object) -> String {
+    format!(
+        "'{}' expects {}, found {}",
+        function,
+        what,
+        found.type_name()
+    )
+}
+
+fn array<'o>(function: &str, value: &'o Object) -> Result<&'o [Object], String> {
+    match value {
+        Object::Array(elements) => Ok(elements),
+        other => Err(expected(function, "an array", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, args: &[Object]) -> Result<Object, String> {
+        let builtins = Builtins::standard();
+        let builtin = builtins.get(Symbol::intern(name)).expect("no such builtin");
+        builtin.call(args, &mut Vec::new())
+    }
+
+    fn array(values: &[i64]) -> Object {
+        Object::from(values.iter().map(|v| Object::from(*v)).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_standard() {
+        assert_eq!(call("len", &["héllo".into()]), Ok(Object::from(5)));
+        assert_eq!(call("len", &[array(&[1, 2])]), Ok(Object::from(2)));
+        assert_eq!(call("first", &[array(&[1, 2])]), Ok(Object::from(1)));
+        assert_eq!(call("last", &[array(&[1, 2])]), Ok(Object::from(2)));
+        assert_eq!(call("first", &[array(&[])]), Ok(Object::Null));
+        assert_eq!(call("rest", &[array(&[1, 2, 3])]), Ok(array(&[2, 3])));
+        assert_eq!(call("rest", &[array(&[])]), Ok(Object::Null));
+
+        let original = array(&[1]);
+        assert_eq!(
+            call("push", &[original.clone(), Object::from(2)]),
+            Ok(array(&[1, 2]))
+        );
+        assert_eq!(original, array(&[1]));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            call("len", &[Object::from(1)]),
+            Err("'len' expects a string, an array or a hash, found int".to_string())
+        );
+        assert_eq!(
+            call("push", &[Object::from("s"), Object::from(1)]),
+            Err("'push' expects an array, found string".to_string())
+        );
+        assert_eq!(
+            call("len", &[]),
+            Err("Wrong number of arguments to 'len': expected 1, found 0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_puts_and_register() {
+        let mut builtins = Builtins::standard();
+        builtins.register("double", Arity::Exact(1), |args, _| match &args[0] {
+            Object::Integer(n) => Ok(Object::from(n * 2)),
+            other => Err(format!("cannot double {}", other.type_name())),
+        });
+        let double = builtins.get(Symbol::intern("double")).unwrap();
+        assert_eq!(
+            double.call(&[Object::from(4)], &mut Vec::new()),
+            Ok(8.into())
+        );
+
+        let mut output = Vec::new();
+        let puts = builtins.get(Symbol::intern("puts")).unwrap();
+        let result = puts.call(&["a".into(), array(&[1]), Object::Null], &mut output);
+        assert_eq!(result, Ok(Object::Null));
+        assert_eq!(String::from_utf8(output).unwrap(), "a\n[1]\nnull\n");
+
+        let mut names: Vec<&str> = builtins.names().collect();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            vec!["double", "first", "last", "len", "push", "puts", "rest"]
+        );
+    }
+}
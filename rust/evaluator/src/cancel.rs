@@ -0,0 +1,37 @@
+//! Stopping an evaluation from outside it.
+//!
+//! The evaluator looks at its `CancelHandle` every `CHECK_INTERVAL` steps,
+//! so a script stops soon after `cancel` is called, whatever thread calls
+//! it, without the cost of an atomic load on every expression.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Steps between two looks at the cancellation flag and the clock.
+pub(crate) const CHECK_INTERVAL: u64 = 256;
+
+/// Cancels the `eval` in progress on the evaluator it came from. Clones
+/// share the flag, and can be sent to other threads.
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelHandle {
+    /// Asks the running `eval` to stop with `ErrorKind::Cancelled`, or the
+    /// next one to start if none is running. The request is used up when
+    /// the `eval` it stopped returns.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+}
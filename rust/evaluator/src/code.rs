@@ -0,0 +1,291 @@
+//! The program as the evaluator runs it.
+//!
+//! `ast` nodes borrow the source they were parsed from, but closures must
+//! outlive it: a function defined by one `eval` can be called by the next.
+//! So programs are first copied into this owned tree, where function bodies
+//! are shared so creating a closure does not copy its code.
+//!
+//! Every expression keeps the span of source it was parsed from, as the
+//! parser recorded it, for errors.
+
+use std::{ops::Range, rc::Rc};
+
+use lexer::{
+    symbol::Symbol,
+    tokens::{unescape, Token, TokenKind},
+};
+use parser::ast::{Block, Expression, Program, Statement};
+
+use crate::{EvalError, Limit};
+
+#[derive(Debug)]
+pub(crate) enum Stmt {
+    Let {
+        name: Symbol,
+        value: Expr,
+    },
+    Return(Expr),
+    /// `span` covers `throw` and the value.
+    Throw {
+        value: Expr,
+        span: Range<usize>,
+    },
+    While {
+        condition: Expr,
+        body: Vec<Stmt>,
+    },
+    For {
+        variable: Pattern,
+        iterable: Expr,
+        body: Vec<Stmt>,
+    },
+    Break(Range<usize>),
+    Continue(Range<usize>),
+    Expression(Expr),
+}
+
+/// What a `for` binds each item to.
+#[derive(Debug)]
+pub(crate) enum Pattern {
+    Name(Symbol),
+    /// `[a, b]`: the item is an array with one element per name.
+    Array(Vec<Symbol>),
+}
+
+#[derive(Debug)]
+pub(crate) struct Function {
+    /// The name of the `let` the function was defined by, if any.
+    pub name: Option<Symbol>,
+    pub parameters: Vec<Symbol>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Expr {
+    pub kind: ExprKind,
+    /// Byte range of the source the expression was parsed from.
+    pub span: Range<usize>,
+}
+
+#[derive(Debug)]
+pub(crate) enum ExprKind {
+    Identifier(Symbol),
+    Integer(i64),
+    Boolean(bool),
+    String(Rc<str>),
+    Prefix {
+        operator: TokenKind,
+        right: Box<Expr>,
+    },
+    Infix {
+        operator: TokenKind,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    If {
+        condition: Box<Expr>,
+        consequence: Vec<Stmt>,
+        alternative: Option<Vec<Stmt>>,
+    },
+    Try {
+        body: Vec<Stmt>,
+        parameter: Symbol,
+        handler: Vec<Stmt>,
+    },
+    Function(Rc<Function>),
+    Call {
+        function: Box<Expr>,
+        arguments: Vec<Expr>,
+    },
+    Array(Vec<Expr>),
+    Hash(Vec<(Expr, Expr)>),
+    Index {
+        left: Box<Expr>,
+        index: Box<Expr>,
+    },
+}
+
+/// Lowers `program`, failing with `Limit::Nesting` where expressions and
+/// blocks nest more than `max_nesting` levels deep.
+pub(crate) fn lower_program(program: &Program, max_nesting: usize) -> Result<Vec<Stmt>, EvalError> {
+    let lower = Lower { max_nesting };
+    program
+        .statements
+        .iter()
+        .map(|stmt| lower.statement(stmt, 0))
+        .collect()
+}
+
+fn name(expr: &Expression) -> Symbol {
+    match expr {
+        Expression::Identifier(Token::Ident { raw, .. }) => *raw,
+        other => Symbol::intern(&other.to_string()),
+    }
+}
+
+/// Lowering of one program, which tracks how deep it is in the tree so a
+/// hand-built `ast` cannot make it overflow the stack.
+struct Lower {
+    max_nesting: usize,
+}
+
+impl Lower {
+    /// Runs `lower` one level below `depth`, failing at `span` if that is
+    /// past `max_nesting`.
+    fn nested<T>(
+        &self,
+        depth: usize,
+        span: impl FnOnce() -> Range<usize>,
+        lower: impl FnOnce(usize) -> Result<T, EvalError>,
+    ) -> Result<T, EvalError> {
+        if depth == self.max_nesting {
+            return Err(EvalError::limit(Limit::Nesting, self.max_nesting, span()));
+        }
+        lower(depth + 1)
+    }
+
+    fn block(&self, block: &Block, depth: usize) -> Result<Vec<Stmt>, EvalError> {
+        self.nested(
+            depth,
+            || block.span(),
+            |depth| {
+                block
+                    .statements
+                    .iter()
+                    .map(|stmt| self.statement(stmt, depth))
+                    .collect()
+            },
+        )
+    }
+
+    fn function(
+        &self,
+        name: Option<Symbol>,
+        f: &parser::ast::FunctionLiteral,
+        depth: usize,
+    ) -> Result<Rc<Function>, EvalError> {
+        Ok(Rc::new(Function {
+            name,
+            parameters: f.parameters.iter().map(self::name).collect(),
+            body: self.block(&f.body, depth)?,
+        }))
+    }
+
+    fn statement(&self, stmt: &Statement, depth: usize) -> Result<Stmt, EvalError> {
+        Ok(match stmt {
+            Statement::Let(l) => {
+                let name = name(&l.identifier);
+                let value = match &l.value {
+                    Expression::Function(f) => Expr {
+                        kind: ExprKind::Function(self.nested(
+                            depth,
+                            || l.value.span(),
+                            |depth| self.function(Some(name), f, depth),
+                        )?),
+                        span: l.value.span(),
+                    },
+                    value => self.expression(value, depth)?,
+                };
+                Stmt::Let { name, value }
+            }
+            Statement::Return(r) => Stmt::Return(self.expression(&r.return_value, depth)?),
+            Statement::Throw(t) => Stmt::Throw {
+                value: self.expression(&t.value, depth)?,
+                span: stmt.span(),
+            },
+            Statement::While(w) => Stmt::While {
+                condition: self.expression(&w.condition, depth)?,
+                body: self.block(&w.body, depth)?,
+            },
+            Statement::For(f) => Stmt::For {
+                variable: match &f.variable {
+                    Expression::Array(a) => Pattern::Array(a.elements.iter().map(name).collect()),
+                    variable => Pattern::Name(name(variable)),
+                },
+                iterable: self.expression(&f.iterable, depth)?,
+                body: self.block(&f.body, depth)?,
+            },
+            Statement::Break(_) => Stmt::Break(stmt.span()),
+            Statement::Continue(_) => Stmt::Continue(stmt.span()),
+            Statement::Expression(e) => Stmt::Expression(self.expression(&e.expression, depth)?),
+        })
+    }
+
+    fn expressions<'e, 'a: 'e>(
+        &self,
+        exprs: impl IntoIterator<Item = &'e Expression<'a>>,
+        depth: usize,
+    ) -> Result<Vec<Expr>, EvalError> {
+        exprs
+            .into_iter()
+            .map(|expr| self.expression(expr, depth))
+            .collect()
+    }
+
+    fn expression(&self, expr: &Expression, depth: usize) -> Result<Expr, EvalError> {
+        self.nested(
+            depth,
+            || expr.span(),
+            |depth| self.expression_inner(expr, depth),
+        )
+    }
+
+    fn expression_inner(&self, expr: &Expression, depth: usize) -> Result<Expr, EvalError> {
+        let kind = match expr {
+            Expression::Identifier(_) => ExprKind::Identifier(name(expr)),
+            Expression::Integer(token) => match token {
+                Token::Int { value, .. } => ExprKind::Integer(*value),
+                _ => ExprKind::Integer(0),
+            },
+            Expression::Boolean(token) => ExprKind::Boolean(matches!(token, Token::True { .. })),
+            Expression::String(token) => match token {
+                Token::String { raw, .. } => ExprKind::String(unescape(raw).into()),
+                _ => ExprKind::String("".into()),
+            },
+            Expression::Prefix(p) => ExprKind::Prefix {
+                operator: p.token.kind(),
+                right: Box::new(self.expression(&p.right, depth)?),
+            },
+            Expression::Infix(i) => ExprKind::Infix {
+                operator: i.token.kind(),
+                left: Box::new(self.expression(&i.left, depth)?),
+                right: Box::new(self.expression(&i.right, depth)?),
+            },
+            Expression::If(i) => ExprKind::If {
+                condition: Box::new(self.expression(&i.condition, depth)?),
+                consequence: self.block(&i.consequence, depth)?,
+                alternative: match &i.alternative {
+                    Some(alternative) => Some(self.block(alternative, depth)?),
+                    None => None,
+                },
+            },
+            Expression::Try(t) => ExprKind::Try {
+                body: self.block(&t.body, depth)?,
+                parameter: name(&t.parameter),
+                handler: self.block(&t.handler, depth)?,
+            },
+            Expression::Function(f) => ExprKind::Function(self.function(None, f, depth)?),
+            Expression::Call(c) => ExprKind::Call {
+                function: Box::new(self.expression(&c.function, depth)?),
+                arguments: self.expressions(&c.arguments, depth)?,
+            },
+            Expression::Array(a) => ExprKind::Array(self.expressions(&a.elements, depth)?),
+            Expression::Hash(h) => ExprKind::Hash(
+                h.pairs
+                    .iter()
+                    .map(|(key, value)| {
+                        Ok((self.expression(key, depth)?, self.expression(value, depth)?))
+                    })
+                    .collect::<Result<_, EvalError>>()?,
+            ),
+            Expression::Index(i) => ExprKind::Index {
+                left: Box::new(self.expression(&i.left, depth)?),
+                index: Box::new(self.expression(&i.index, depth)?),
+            },
+        };
+        Ok(Expr {
+            kind,
+            span: expr.span(),
+        })
+    }
+}
@@ -0,0 +1,39 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use lexer::symbol::Symbol;
+
+use crate::object::Object;
+
+pub type Env = Rc<RefCell<Environment>>;
+
+/// The bindings of one function call, or of the top level, falling back to
+/// those of the environment the function was created in.
+#[derive(Default)]
+pub struct Environment {
+    store: HashMap<Symbol, Object>,
+    outer: Option<Env>,
+}
+
+impl Environment {
+    pub fn new() -> Env {
+        Rc::new(RefCell::new(Environment::default()))
+    }
+
+    pub fn enclosed(outer: Env) -> Env {
+        Rc::new(RefCell::new(Environment {
+            store: HashMap::new(),
+            outer: Some(outer),
+        }))
+    }
+
+    pub fn get(&self, name: Symbol) -> Option<Object> {
+        match self.store.get(&name) {
+            Some(value) => Some(value.clone()),
+            None => self.outer.as_ref()?.borrow().get(name),
+        }
+    }
+
+    pub fn set(&mut self, name: Symbol, value: Object) {
+        self.store.insert(name, value);
+    }
+}
@@ -0,0 +1,264 @@
+//! Monkey as a scripting language for Rust applications.
+//!
+//! An `Interpreter` takes source text rather than a parsed program, and
+//! converts values in both directions: `Into<Object>` for what goes in and
+//! `TryFrom<Object>` for what comes out. Type annotations are only checked
+//! when type checking is turned on with `set_type_check`.
+
+use std::{fmt, time::Duration};
+
+use lexer::Lexer;
+use parser::{Parser, ParserError};
+use typecheck::TypeError;
+
+use crate::{Arity, CancelHandle, ConversionError, EvalError, Evaluator, Limits, Object};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    Parse(Vec<ParserError>),
+    /// Type checking was on and found the script ill-typed, so it did not
+    /// run.
+    Type(Vec<TypeError>),
+    Eval(EvalError),
+    /// The script ran, but its value had the wrong type for `eval_as`.
+    Conversion(ConversionError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(errors) => {
+                for (i, error) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", error)?;
+                }
+                Ok(())
+            }
+            Error::Type(errors) => {
+                for (i, error) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", error.message)?;
+                }
+                Ok(())
+            }
+            Error::Eval(error) => write!(f, "{}", error),
+            Error::Conversion(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<EvalError> for Error {
+    fn from(error: EvalError) -> Self {
+        Error::Eval(error)
+    }
+}
+
+impl From<ConversionError> for Error {
+    fn from(error: ConversionError) -> Self {
+        Error::Conversion(error)
+    }
+}
+
+/// Runs Monkey source with globals and functions supplied by the host.
+/// Bindings persist from one `eval` to the next.
+#[derive(Default)]
+pub struct Interpreter {
+    evaluator: Evaluator,
+    type_check: bool,
+}
+
+impl Interpreter {
+    /// An interpreter with the standard builtins, printing to stdout.
+    pub fn new() -> Self {
+        Interpreter::default()
+    }
+
+    /// An interpreter with the standard builtins, printing to `output`.
+    pub fn with_output(output: impl std::io::Write + 'static) -> Self {
+        Interpreter {
+            evaluator: Evaluator::with_output(output),
+            type_check: false,
+        }
+    }
+
+    /// Makes `function` callable from scripts as `name`, with any number of
+    /// arguments. An `Err` becomes an evaluation error with that message.
+    pub fn register_fn<R: Into<Object>>(
+        &mut self,
+        name: &str,
+        function: impl Fn(&[Object]) -> Result<R, String> + 'static,
+    ) {
+        self.evaluator
+            .builtins_mut()
+            .register(name, Arity::Any, move |args, _| {
+                function(args).map(Into::into)
+            });
+    }
+
+    pub fn set_global(&mut self, name: &str, value: impl Into<Object>) {
+        self.evaluator.set_global(name, value.into());
+    }
+
+    pub fn global(&self, name: &str) -> Option<Object> {
+        self.evaluator.global(name)
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.evaluator.set_limits(limits);
+    }
+
+    /// A handle that stops the `eval` in progress, or the next one, from any
+    /// thread.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.evaluator.cancel_handle()
+    }
+
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.evaluator.set_timeout(timeout);
+    }
+
+    /// When `enabled`, each `eval` first type checks its source, type
+    /// annotations included, and fails with `Error::Type` instead of running
+    /// it if the check finds errors. Names the source does not bind, such as
+    /// globals and host functions, can have any type.
+    pub fn set_type_check(&mut self, enabled: bool) {
+        self.type_check = enabled;
+    }
+
+    pub fn evaluator_mut(&mut self) -> &mut Evaluator {
+        &mut self.evaluator
+    }
+
+    /// Parses and runs `source`, returning the value of its last statement.
+    pub fn eval(&mut self, source: &str) -> Result<Object, Error> {
+        let mut parser = Parser::new(Lexer::new(source));
+        let program = parser.parse_program().expect("Failed to parse program");
+        let errors = parser.errors();
+        if !errors.is_empty() {
+            return Err(Error::Parse(errors));
+        }
+        if self.type_check {
+            let errors = typecheck::check(source, &program).errors;
+            if !errors.is_empty() {
+                return Err(Error::Type(errors));
+            }
+        }
+        Ok(self.evaluator.eval(&program)?)
+    }
+
+    /// Like `eval`, converting the value to `T`.
+    pub fn eval_as<T>(&mut self, source: &str) -> Result<T, Error>
+    where
+        T: TryFrom<Object>,
+        ConversionError: From<T::Error>,
+    {
+        let value = self.eval(source)?;
+        Ok(T::try_from(value).map_err(ConversionError::from)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_host_functions_and_globals() {
+        let mut interpreter = Interpreter::with_output(std::io::sink());
+        interpreter.register_fn("now", |_| Ok(1_700_000_000));
+        interpreter.register_fn("sum", |args| {
+            args.iter()
+                .map(|arg| i64::try_from(arg.clone()).map_err(|e| e.to_string()))
+                .sum::<Result<i64, String>>()
+        });
+        interpreter.set_global("limits", HashMap::from([("max", 10), ("min", 2)]));
+        interpreter.set_global("names", vec!["ann", "bob"]);
+
+        assert_eq!(interpreter.eval_as::<i64>("now() + 1"), Ok(1_700_000_001));
+        assert_eq!(
+            interpreter.eval_as::<i64>(r#"sum(limits["max"], limits["min"], 3)"#),
+            Ok(15)
+        );
+        assert_eq!(
+            interpreter.eval_as::<String>("names[1]"),
+            Ok("bob".to_string())
+        );
+        assert_eq!(
+            interpreter.eval("sum(1, true)"),
+            Err(Error::Eval(EvalError::new(
+                "Expected int, found bool".to_string(),
+                0..12
+            )))
+        );
+
+        interpreter
+            .eval(r#"let config = {"debug": true, "retries": 3 > 2};"#)
+            .unwrap();
+        assert_eq!(
+            interpreter
+                .global("config")
+                .map(HashMap::<String, bool>::try_from),
+            Some(Ok(HashMap::from([
+                ("debug".to_string(), true),
+                ("retries".to_string(), true),
+            ])))
+        );
+        // looking up a name does not intern it
+        assert_eq!(interpreter.global("never bound"), None);
+        assert_eq!(lexer::symbol::Symbol::lookup("never bound"), None);
+        assert_eq!(
+            interpreter.eval_as::<Vec<String>>("push(names, \"cy\")"),
+            Ok(vec!["ann".to_string(), "bob".to_string(), "cy".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut interpreter = Interpreter::with_output(std::io::sink());
+        let Err(Error::Parse(errors)) = interpreter.eval("let = 1;") else {
+            panic!("expected a parse error");
+        };
+        assert_eq!(errors.len(), 1);
+        let Err(Error::Parse(errors)) = interpreter.eval("let n = 99999999999999999999;") else {
+            panic!("expected a parse error");
+        };
+        assert_eq!(
+            errors[0].to_string(),
+            "Integer literal 99999999999999999999 is too large"
+        );
+        assert_eq!(
+            interpreter.eval_as::<bool>("1").unwrap_err().to_string(),
+            "Expected bool, found int"
+        );
+    }
+
+    #[test]
+    fn test_type_check() {
+        let mut interpreter = Interpreter::with_output(std::io::sink());
+        let source = "let x: int = \"five\"; 1";
+        assert_eq!(interpreter.eval_as::<i64>(source), Ok(1));
+
+        interpreter.set_type_check(true);
+        let Err(Error::Type(errors)) = interpreter.eval(source) else {
+            panic!("expected a type error");
+        };
+        assert_eq!(errors.len(), 1);
+        assert_eq!(&source[errors[0].span.clone()], "\"five\"");
+        interpreter.set_global("n", 1);
+        assert_eq!(
+            interpreter.eval_as::<i64>("let f = fn(a: int) -> int { a + n }; f(2)"),
+            Ok(3)
+        );
+        assert!(matches!(
+            interpreter.eval("let g = fn(a: int) { a }; g(true)"),
+            Err(Error::Type(_))
+        ));
+    }
+}
@@ -0,0 +1,1357 @@
+//! A tree-walking evaluator for Monkey programs.
+//!
+//! Bindings made by one `eval` stay visible to the next, so an `Evaluator`
+//! can back a REPL. Blocks share the environment they are in; only function
+//! calls get a new one, so a `let` in the body of a `while` rebinds the name
+//! for the next iteration and the condition, and the variable of a `for`
+//! stays bound once the loop is done. Names no binding defines are
+//! looked up among the evaluator's `Builtins`. A `try` catches runtime
+//! errors and thrown values; reaching a limit or being cancelled cannot be
+//! caught. How much a script may do is bounded by the evaluator's `Limits`,
+//! and for how long by its timeout; a `CancelHandle` stops it at any time.
+
+pub mod builtins;
+pub mod cancel;
+mod code;
+pub mod environment;
+mod interpreter;
+pub mod limits;
+pub mod object;
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, Write},
+    ops::{Range, RangeInclusive},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use lexer::{symbol::Symbol, tokens::TokenKind};
+use parser::ast::Program;
+
+use code::{Expr, ExprKind, Pattern, Stmt};
+use environment::{Env, Environment};
+
+pub use builtins::{Arity, Builtin, Builtins};
+pub use cancel::CancelHandle;
+pub use interpreter::{Error, Interpreter};
+pub use limits::{Limit, Limits};
+pub use object::{Closure, ConversionError, HashKey, Object};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The script did something invalid, like adding an int to a bool.
+    Runtime,
+    /// The script threw a value no `catch` caught.
+    Thrown,
+    /// The script went past one of the evaluator's `Limits`.
+    LimitExceeded(Limit),
+    /// The script was stopped by a `CancelHandle` or ran out of time.
+    Cancelled,
+}
+
+/// A byte range of the source being evaluated.
+pub type Span = Range<usize>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvalError {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// The expression that failed.
+    pub span: Span,
+    /// The function calls the error passed through, innermost first.
+    pub trace: Vec<Frame>,
+    /// What was thrown, for `Thrown` errors.
+    pub thrown: Option<Object>,
+}
+
+/// A call of a Monkey function that was in progress when an error happened.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// The name of the `let` the function was defined by; `None` for a
+    /// function literal that was never bound.
+    pub function: Option<Symbol>,
+    /// The call expression.
+    pub call_site: Span,
+}
+
+impl EvalError {
+    fn new(message: String, span: Span) -> Self {
+        EvalError {
+            kind: ErrorKind::Runtime,
+            message,
+            span,
+            trace: Vec::new(),
+            thrown: None,
+        }
+    }
+
+    fn limit(limit: Limit, max: impl fmt::Display, span: Span) -> Self {
+        EvalError {
+            kind: ErrorKind::LimitExceeded(limit),
+            message: format!("{} limit of {} exceeded", limit, max),
+            span,
+            trace: Vec::new(),
+            thrown: None,
+        }
+    }
+
+    fn thrown(value: Object, span: Span) -> Self {
+        EvalError {
+            kind: ErrorKind::Thrown,
+            message: format!("Uncaught exception: {}", value),
+            span,
+            trace: Vec::new(),
+            thrown: Some(value),
+        }
+    }
+
+    fn cancelled(message: String, span: Span) -> Self {
+        EvalError {
+            kind: ErrorKind::Cancelled,
+            message,
+            span,
+            trace: Vec::new(),
+            thrown: None,
+        }
+    }
+}
+
+impl EvalError {
+    /// Whether a `catch` handles the error. Exceeding a limit or being
+    /// cancelled must stop the script, so those are not caught.
+    pub fn is_catchable(&self) -> bool {
+        matches!(self.kind, ErrorKind::Runtime | ErrorKind::Thrown)
+    }
+
+    /// What a `catch` gets: the value thrown, or the message of an error
+    /// the evaluator raised.
+    pub fn value(&self) -> Object {
+        match &self.thrown {
+            Some(value) => value.clone(),
+            None => Object::from(self.message.as_str()),
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Why evaluation stopped before the end of a statement list.
+enum Unwind {
+    Return(Object),
+    /// A `break` at this span, to the innermost loop.
+    Break(Span),
+    /// A `continue` at this span, to the innermost loop.
+    Continue(Span),
+    /// Boxed to keep `Eval` results, of which every frame holds several,
+    /// small.
+    Error(Box<EvalError>),
+}
+
+impl From<EvalError> for Unwind {
+    fn from(error: EvalError) -> Self {
+        Unwind::Error(Box::new(error))
+    }
+}
+
+type Eval<T = Object> = Result<T, Unwind>;
+
+/// The error for a `break` or `continue` that reached the end of a function
+/// or program. The parser rejects them, so only a tree built some other
+/// way, such as read from JSON, can have one.
+fn outside_loop(keyword: &str, span: Span) -> EvalError {
+    EvalError::new(format!("'{}' outside of a loop", keyword), span)
+}
+
+pub struct Evaluator {
+    env: Env,
+    builtins: Builtins,
+    output: Box<dyn Write>,
+    limits: Limits,
+    /// Expressions evaluated by the current `eval`.
+    steps: u64,
+    /// Calls in progress.
+    depth: usize,
+    /// Where the stack was when the current `eval` started.
+    stack_start: usize,
+    cancel: CancelHandle,
+    timeout: Option<Duration>,
+    /// When the current `eval` runs out of time.
+    deadline: Option<Instant>,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Evaluator::new()
+    }
+}
+
+impl Evaluator {
+    /// An evaluator with the standard builtins, printing to stdout.
+    pub fn new() -> Self {
+        Evaluator::with_output(io::stdout())
+    }
+
+    /// An evaluator with the standard builtins, printing to `output`.
+    pub fn with_output(output: impl Write + 'static) -> Self {
+        Evaluator {
+            env: Environment::new(),
+            builtins: Builtins::standard(),
+            output: Box::new(output),
+            limits: Limits::default(),
+            steps: 0,
+            depth: 0,
+            stack_start: 0,
+            cancel: CancelHandle::default(),
+            timeout: None,
+            deadline: None,
+        }
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// A handle that stops this evaluator's `eval` in progress, or the next
+    /// one if none is.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Stops each `eval` that runs longer than `timeout`.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    pub fn builtins(&self) -> &Builtins {
+        &self.builtins
+    }
+
+    pub fn builtins_mut(&mut self) -> &mut Builtins {
+        &mut self.builtins
+    }
+
+    /// Binds `name` at the top level, as a `let` would.
+    pub fn set_global(&mut self, name: &str, value: Object) {
+        self.env.borrow_mut().set(Symbol::intern(name), value);
+    }
+
+    /// The top-level binding of `name`; builtins are not included.
+    pub fn global(&self, name: &str) -> Option<Object> {
+        self.env.borrow().get(Symbol::lookup(name)?)
+    }
+
+    /// Runs `program` and returns the value of its last statement, which is
+    /// `null` for a `let`, or the value of a top-level `return`.
+    pub fn eval(&mut self, program: &Program) -> Result<Object, EvalError> {
+        let statements = code::lower_program(program, self.limits.max_nesting)?;
+        let env = self.env.clone();
+        self.steps = 0;
+        self.depth = 0;
+        self.stack_start = stack_position();
+        self.deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        // a cancel from before the eval started stops it before it does
+        // anything; cleared only once it is done, so none is missed
+        let result = match self.check_cancelled(&(0..0)) {
+            Ok(()) => self.statements(&statements, &env),
+            Err(error) => Err(error.into()),
+        };
+        self.cancel.reset();
+        match result {
+            Ok(value) | Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(error)) => Err(*error),
+            Err(Unwind::Break(span)) => Err(outside_loop("break", span)),
+            Err(Unwind::Continue(span)) => Err(outside_loop("continue", span)),
+        }
+    }
+
+    fn statements(&mut self, statements: &[Stmt], env: &Env) -> Eval {
+        let mut result = Object::Null;
+        for stmt in statements {
+            result = match stmt {
+                Stmt::Let { name, value } => {
+                    let value = self.expression(value, env)?;
+                    env.borrow_mut().set(*name, value);
+                    Object::Null
+                }
+                Stmt::Return(value) => return Err(Unwind::Return(self.expression(value, env)?)),
+                Stmt::Throw { value, span } => {
+                    let value = self.expression(value, env)?;
+                    return Err(EvalError::thrown(value, span.clone()).into());
+                }
+                Stmt::While { condition, body } => self.while_loop(condition, body, env)?,
+                Stmt::For {
+                    variable,
+                    iterable,
+                    body,
+                } => self.for_loop(variable, iterable, body, env)?,
+                Stmt::Break(span) => return Err(Unwind::Break(span.clone())),
+                Stmt::Continue(span) => return Err(Unwind::Continue(span.clone())),
+                Stmt::Expression(expr) => self.expression(expr, env)?,
+            };
+        }
+        Ok(result)
+    }
+
+    /// Runs `body` for as long as `condition` is truthy; a loop is `null`.
+    fn while_loop(&mut self, condition: &Expr, body: &[Stmt], env: &Env) -> Eval {
+        while self.expression(condition, env)?.is_truthy() {
+            match self.statements(body, env) {
+                Ok(_) | Err(Unwind::Continue(_)) => {}
+                Err(Unwind::Break(_)) => break,
+                Err(unwind) => return Err(unwind),
+            }
+        }
+        Ok(Object::Null)
+    }
+
+    /// Runs `body` once for each item of `iterable`, with `variable` bound
+    /// to it; a loop is `null`. Arrays give their elements, hashes their
+    /// keys, or `[key, value]` pairs to take apart with `[k, v]`, and
+    /// strings their characters.
+    fn for_loop(&mut self, variable: &Pattern, iterable: &Expr, body: &[Stmt], env: &Env) -> Eval {
+        let span = &iterable.span;
+        let items: Box<dyn Iterator<Item = Object>> = match &iterable.kind {
+            // counted through, rather than built as an array first
+            ExprKind::Infix {
+                operator: operator @ (TokenKind::DotDot | TokenKind::DotDotEquals),
+                left,
+                right,
+            } => {
+                let left = self.expression(left, env)?;
+                let right = self.expression(right, env)?;
+                Box::new(range(*operator, left, right, span)?.map(Object::Integer))
+            }
+            _ => match self.expression(iterable, env)? {
+                Object::Array(elements) => {
+                    Box::new((0..elements.len()).map(move |i| elements[i].clone()))
+                }
+                Object::Hash(pairs) => {
+                    let items: Vec<Object> = match variable {
+                        Pattern::Name(_) => pairs.keys().cloned().map(Object::from).collect(),
+                        Pattern::Array(_) => pairs
+                            .iter()
+                            .map(|(key, value)| {
+                                Object::from(vec![Object::from(key.clone()), value.clone()])
+                            })
+                            .collect(),
+                    };
+                    Box::new(items.into_iter())
+                }
+                Object::String(s) => {
+                    let items: Vec<Object> = s.chars().map(|c| c.to_string().into()).collect();
+                    Box::new(items.into_iter())
+                }
+                other => {
+                    return Err(EvalError::new(
+                        format!("Cannot iterate over a value of type {}", other.type_name()),
+                        span.clone(),
+                    )
+                    .into())
+                }
+            },
+        };
+        for item in items {
+            // a loop with an empty body evaluates no expressions to count
+            self.step(span)?;
+            bind(variable, item, env, span)?;
+            match self.statements(body, env) {
+                Ok(_) | Err(Unwind::Continue(_)) => {}
+                Err(Unwind::Break(_)) => break,
+                Err(unwind) => return Err(unwind),
+            }
+        }
+        Ok(Object::Null)
+    }
+
+    /// Counts a step towards the limit, and now and then checks whether
+    /// evaluation was cancelled.
+    fn step(&mut self, span: &Span) -> Result<(), EvalError> {
+        self.steps += 1;
+        if let Some(max) = self.limits.max_steps {
+            if self.steps > max {
+                return Err(EvalError::limit(Limit::Steps, max, span.clone()));
+            }
+        }
+        if self.steps.is_multiple_of(cancel::CHECK_INTERVAL) {
+            self.check_cancelled(span)?;
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, expr: &Expr, env: &Env) -> Eval {
+        self.step(&expr.span)?;
+        self.check_stack(&expr.span)?;
+        let span = &expr.span;
+        // Each kind of node is evaluated by its own method, which keeps
+        // this frame, which every level of recursion goes through, small.
+        match &expr.kind {
+            ExprKind::Identifier(name) => Ok(self.lookup(*name, env, span)?),
+            ExprKind::Integer(value) => Ok(Object::Integer(*value)),
+            ExprKind::Boolean(value) => Ok(Object::Boolean(*value)),
+            ExprKind::String(value) => Ok(Object::String(value.clone())),
+            ExprKind::Prefix { operator, right } => {
+                let right = self.expression(right, env)?;
+                Ok(prefix(*operator, right, span)?)
+            }
+            ExprKind::Infix {
+                operator,
+                left,
+                right,
+            } => self.infix_expression(*operator, left, right, env, span),
+            ExprKind::If {
+                condition,
+                consequence,
+                alternative,
+            } => self.if_expression(condition, consequence, alternative.as_deref(), env),
+            ExprKind::Try {
+                body,
+                parameter,
+                handler,
+            } => self.try_expression(body, *parameter, handler, env),
+            ExprKind::Function(code) => Ok(Object::Function(Rc::new(Closure {
+                code: code.clone(),
+                env: env.clone(),
+            }))),
+            ExprKind::Call {
+                function,
+                arguments,
+            } => self.call_expression(function, arguments, env, span),
+            ExprKind::Array(elements) => self.array(elements, env, span),
+            ExprKind::Hash(pairs) => self.hash(pairs, env, span),
+            ExprKind::Index { left, index } => {
+                let left = self.expression(left, env)?;
+                let index = self.expression(index, env)?;
+                Ok(self::index(left, index, span)?)
+            }
+        }
+    }
+
+    fn infix_expression(
+        &mut self,
+        operator: TokenKind,
+        left: &Expr,
+        right: &Expr,
+        env: &Env,
+        span: &Span,
+    ) -> Eval {
+        let left = self.expression(left, env)?;
+        let right = self.expression(right, env)?;
+        if let TokenKind::DotDot | TokenKind::DotDotEquals = operator {
+            let range = range(operator, left, right, span)?;
+            // checked before the array is built, which could be huge
+            if let Some(max) = self.limits.max_size {
+                if range.size_hint().0 > max {
+                    return Err(EvalError::limit(Limit::Size, max, span.clone()).into());
+                }
+            }
+            return Ok(Object::from(range.collect::<Vec<_>>()));
+        }
+        let value = infix(operator, left, right, span)?;
+        Ok(self.check_size(value, span)?)
+    }
+
+    fn if_expression(
+        &mut self,
+        condition: &Expr,
+        consequence: &[Stmt],
+        alternative: Option<&[Stmt]>,
+        env: &Env,
+    ) -> Eval {
+        if self.expression(condition, env)?.is_truthy() {
+            self.statements(consequence, env)
+        } else if let Some(alternative) = alternative {
+            self.statements(alternative, env)
+        } else {
+            Ok(Object::Null)
+        }
+    }
+
+    /// Runs `handler` with `parameter` bound to what `body` failed with, if
+    /// it failed with a catchable error.
+    fn try_expression(
+        &mut self,
+        body: &[Stmt],
+        parameter: Symbol,
+        handler: &[Stmt],
+        env: &Env,
+    ) -> Eval {
+        match self.statements(body, env) {
+            Err(Unwind::Error(error)) if error.is_catchable() => {
+                env.borrow_mut().set(parameter, error.value());
+                self.statements(handler, env)
+            }
+            result => result,
+        }
+    }
+
+    fn call_expression(
+        &mut self,
+        function: &Expr,
+        arguments: &[Expr],
+        env: &Env,
+        span: &Span,
+    ) -> Eval {
+        let function = self.expression(function, env)?;
+        let arguments = arguments
+            .iter()
+            .map(|argument| self.expression(argument, env))
+            .collect::<Eval<Vec<_>>>()?;
+        self.call(function, &arguments, span)
+    }
+
+    fn array(&mut self, elements: &[Expr], env: &Env, span: &Span) -> Eval {
+        let elements = elements
+            .iter()
+            .map(|element| self.expression(element, env))
+            .collect::<Eval<Vec<_>>>()?;
+        Ok(self.check_size(Object::from(elements), span)?)
+    }
+
+    fn hash(&mut self, pairs: &[(Expr, Expr)], env: &Env, span: &Span) -> Eval {
+        let mut hash = BTreeMap::new();
+        for (key, value) in pairs {
+            let key_span = &key.span;
+            let key = self.expression(key, env)?;
+            let key = hash_key(&key, key_span)?;
+            hash.insert(key, self.expression(value, env)?);
+        }
+        Ok(self.check_size(Object::Hash(Rc::new(hash)), span)?)
+    }
+
+    fn lookup(&self, name: Symbol, env: &Env, span: &Span) -> Result<Object, EvalError> {
+        if let Some(value) = env.borrow().get(name) {
+            return Ok(value);
+        }
+        match self.builtins.get(name) {
+            Some(builtin) => Ok(Object::Builtin(builtin.clone())),
+            None => Err(EvalError::new(
+                format!("Undefined identifier '{}'", name),
+                span.clone(),
+            )),
+        }
+    }
+
+    /// Calls `function`; `span` is the call expression, for errors and
+    /// the trace of calls an error passes through.
+    fn call(&mut self, function: Object, arguments: &[Object], span: &Span) -> Eval {
+        match function {
+            Object::Function(closure) => {
+                let parameters = &closure.code.parameters;
+                if parameters.len() != arguments.len() {
+                    let name = closure
+                        .code
+                        .name
+                        .map_or_else(String::new, |name| format!(" to '{}'", name));
+                    return Err(EvalError::new(
+                        format!(
+                            "Wrong number of arguments{}: expected {}, found {}",
+                            name,
+                            parameters.len(),
+                            arguments.len()
+                        ),
+                        span.clone(),
+                    )
+                    .into());
+                }
+                if self.depth >= self.limits.max_depth {
+                    return Err(EvalError::limit(
+                        Limit::Depth,
+                        self.limits.max_depth,
+                        span.clone(),
+                    )
+                    .into());
+                }
+                let env = Environment::enclosed(closure.env.clone());
+                for (parameter, argument) in parameters.iter().zip(arguments) {
+                    env.borrow_mut().set(*parameter, argument.clone());
+                }
+                self.depth += 1;
+                let result = self.statements(&closure.code.body, &env);
+                self.depth -= 1;
+                match result {
+                    Ok(value) | Err(Unwind::Return(value)) => Ok(value),
+                    Err(Unwind::Error(mut error)) => {
+                        error.trace.push(Frame {
+                            function: closure.code.name,
+                            call_site: span.clone(),
+                        });
+                        Err(Unwind::Error(error))
+                    }
+                    Err(Unwind::Break(span)) => Err(outside_loop("break", span).into()),
+                    Err(Unwind::Continue(span)) => Err(outside_loop("continue", span).into()),
+                }
+            }
+            Object::Builtin(builtin) => {
+                let value = builtin
+                    .call(arguments, &mut self.output)
+                    .map_err(|message| EvalError::new(message, span.clone()))?;
+                Ok(self.check_size(value, span)?)
+            }
+            other => Err(EvalError::new(
+                format!("Cannot call a value of type {}", other.type_name()),
+                span.clone(),
+            )
+            .into()),
+        }
+    }
+
+    /// Fails once the `eval` has used more than `max_stack` bytes of stack.
+    /// Every call and every nested expression goes through `expression`,
+    /// which checks this, so no script can overflow the stack.
+    fn check_stack(&self, span: &Span) -> Result<(), EvalError> {
+        let max = self.limits.max_stack;
+        if self.stack_start.abs_diff(stack_position()) > max {
+            return Err(EvalError::limit(
+                Limit::Stack,
+                format!("{} bytes", max),
+                span.clone(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_cancelled(&self, span: &Span) -> Result<(), EvalError> {
+        if self.cancel.is_cancelled() {
+            return Err(EvalError::cancelled(
+                "Evaluation cancelled".to_string(),
+                span.clone(),
+            ));
+        }
+        match (self.deadline, self.timeout) {
+            (Some(deadline), Some(timeout)) if Instant::now() >= deadline => {
+                Err(EvalError::cancelled(
+                    format!("Evaluation timed out after {:?}", timeout),
+                    span.clone(),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails if `value` is a collection larger than the limits allow.
+    fn check_size(&self, value: Object, span: &Span) -> Result<Object, EvalError> {
+        let Some(max) = self.limits.max_size else {
+            return Ok(value);
+        };
+        let size = match &value {
+            Object::String(s) => s.len(),
+            Object::Array(elements) => elements.len(),
+            Object::Hash(pairs) => pairs.len(),
+            _ => 0,
+        };
+        if size > max {
+            return Err(EvalError::limit(Limit::Size, max, span.clone()));
+        }
+        Ok(value)
+    }
+}
+
+/// The address of a local in a frame just below the caller's, which tells
+/// how far the stack has grown.
+#[inline(never)]
+fn stack_position() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+fn prefix(operator: TokenKind, right: Object, span: &Span) -> Result<Object, EvalError> {
+    match (operator, right) {
+        (TokenKind::Negation, right) => Ok(Object::Boolean(!right.is_truthy())),
+        (TokenKind::Minus, Object::Integer(value)) => value
+            .checked_neg()
+            .map(Object::Integer)
+            .ok_or_else(|| EvalError::new("Integer overflow".to_string(), span.clone())),
+        (operator, right) => Err(EvalError::new(
+            format!("Unknown operator: {}{}", operator, right.type_name()),
+            span.clone(),
+        )),
+    }
+}
+
+fn infix(
+    operator: TokenKind,
+    left: Object,
+    right: Object,
+    span: &Span,
+) -> Result<Object, EvalError> {
+    let error = |message: String| EvalError::new(message, span.clone());
+    match (&left, &right) {
+        (Object::Integer(a), Object::Integer(b)) => {
+            let (a, b) = (*a, *b);
+            let checked = match operator {
+                TokenKind::Plus => a.checked_add(b),
+                TokenKind::Minus => a.checked_sub(b),
+                TokenKind::Multiply => a.checked_mul(b),
+                TokenKind::Divide if b == 0 => return Err(error("Division by zero".to_string())),
+                TokenKind::Divide => a.checked_div(b),
+                TokenKind::LessThan => return Ok(Object::Boolean(a < b)),
+                TokenKind::GreaterThan => return Ok(Object::Boolean(a > b)),
+                TokenKind::Equals => return Ok(Object::Boolean(a == b)),
+                TokenKind::NotEquals => return Ok(Object::Boolean(a != b)),
+                _ => None,
+            };
+            return checked
+                .map(Object::Integer)
+                .ok_or_else(|| error("Integer overflow".to_string()));
+        }
+        (Object::String(a), Object::String(b)) if operator == TokenKind::Plus => {
+            return Ok(Object::from(format!("{}{}", a, b)));
+        }
+        _ => {}
+    }
+    if left.type_name() != right.type_name() {
+        return Err(error(format!(
+            "Type mismatch: {} {} {}",
+            left.type_name(),
+            operator,
+            right.type_name()
+        )));
+    }
+    match operator {
+        TokenKind::Equals => Ok(Object::Boolean(left == right)),
+        TokenKind::NotEquals => Ok(Object::Boolean(left != right)),
+        _ => Err(error(format!(
+            "Unknown operator: {} {} {}",
+            left.type_name(),
+            operator,
+            right.type_name()
+        ))),
+    }
+}
+
+/// The ints from `start` up to `end`, which `..=` includes and `..` leaves
+/// out.
+fn range(
+    operator: TokenKind,
+    start: Object,
+    end: Object,
+    span: &Span,
+) -> Result<RangeInclusive<i64>, EvalError> {
+    match (start, end) {
+        (Object::Integer(start), Object::Integer(end)) if operator == TokenKind::DotDotEquals => {
+            Ok(start..=end)
+        }
+        (Object::Integer(start), Object::Integer(end)) => Ok(match end.checked_sub(1) {
+            Some(end) => start..=end,
+            // `end` is `i64::MIN`, so no int is below it
+            None => RangeInclusive::new(1, 0),
+        }),
+        (start, end) => Err(EvalError::new(
+            format!(
+                "Range bounds must be ints, found {} {} {}",
+                start.type_name(),
+                operator,
+                end.type_name()
+            ),
+            span.clone(),
+        )),
+    }
+}
+
+/// Binds `variable` to an item of a `for`; `span` is the iterable.
+fn bind(variable: &Pattern, item: Object, env: &Env, span: &Span) -> Result<(), EvalError> {
+    match (variable, item) {
+        (Pattern::Name(name), item) => env.borrow_mut().set(*name, item),
+        (Pattern::Array(names), Object::Array(elements)) if elements.len() == names.len() => {
+            for (name, element) in names.iter().zip(elements.iter()) {
+                env.borrow_mut().set(*name, element.clone());
+            }
+        }
+        (Pattern::Array(names), Object::Array(elements)) => {
+            return Err(EvalError::new(
+                format!(
+                    "Cannot destructure an array of {} elements into {} names",
+                    elements.len(),
+                    names.len()
+                ),
+                span.clone(),
+            ))
+        }
+        (Pattern::Array(_), item) => {
+            return Err(EvalError::new(
+                format!("Cannot destructure a value of type {}", item.type_name()),
+                span.clone(),
+            ))
+        }
+    }
+    Ok(())
+}
+
+fn hash_key(key: &Object, span: &Span) -> Result<HashKey, EvalError> {
+    key.hash_key().ok_or_else(|| {
+        EvalError::new(
+            format!(
+                "Cannot use a value of type {} as a hash key",
+                key.type_name()
+            ),
+            span.clone(),
+        )
+    })
+}
+
+/// Indexing past either end of an array, or with a key a hash does not
+/// have, gives `null`.
+fn index(left: Object, index: Object, span: &Span) -> Result<Object, EvalError> {
+    match (left, index) {
+        (Object::Hash(pairs), key) => {
+            let key = hash_key(&key, span)?;
+            Ok(pairs.get(&key).cloned().unwrap_or(Object::Null))
+        }
+        (Object::Array(elements), Object::Integer(i)) => Ok(usize::try_from(i)
+            .ok()
+            .and_then(|i| elements.get(i).cloned())
+            .unwrap_or(Object::Null)),
+        (Object::Array(_), index) => Err(EvalError::new(
+            format!("Array index must be an int, found {}", index.type_name()),
+            span.clone(),
+        )),
+        (left, _) => Err(EvalError::new(
+            format!("Cannot index a value of type {}", left.type_name()),
+            span.clone(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+    use lexer::Lexer;
+    use parser::Parser;
+
+    use super::*;
+
+    /// Output that tests can read after the evaluator has written it.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run(evaluator: &mut Evaluator, source: &str) -> Result<Object, EvalError> {
+        let mut parser = Parser::new(Lexer::new(source));
+        let program = parser.parse_program().expect("Failed to parse program");
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        evaluator.eval(&program)
+    }
+
+    fn eval(source: &str) -> Result<Object, EvalError> {
+        run(&mut Evaluator::with_output(io::sink()), source)
+    }
+
+    fn value(source: &str) -> String {
+        match eval(source) {
+            Ok(value) => format!("{:?}", value),
+            Err(error) => panic!("{}: {}", source, error),
+        }
+    }
+
+    #[test]
+    fn test_expressions() {
+        let tests = [
+            ("5", "5"),
+            ("-5 + 10 * 2", "15"),
+            ("(5 + 10 * 2 + 15 / 3) * 2 + -10", "50"),
+            ("!5", "false"),
+            ("!!true", "true"),
+            ("1 < 2 == true", "true"),
+            ("\"a\" == \"a\"", "true"),
+            ("[1, \"b\"] != [1, \"b\"]", "false"),
+            (r#""Hello" + " " + "World!""#, "\"Hello World!\""),
+            ("if (1 > 2) { 10 }", "null"),
+            ("if (0) { 10 } else { 20 }", "10"),
+            ("[1, 2 * 2, 3][1]", "4"),
+            ("[1, 2][2]", "null"),
+            ("[1, 2][-1]", "null"),
+            ("let x = 5; x", "5"),
+            ("let x = 5;", "null"),
+            (
+                r#"{"b": 1 + 1, true: [], 3: "c"}"#,
+                r#"{3: "c", true: [], "b": 2}"#,
+            ),
+            (r#"let h = {"a": 1, "a": 2}; h["a"]"#, "2"),
+            (r#"{"a": 1}["b"]"#, "null"),
+            ("{1: [2]} == {1: [2]}", "true"),
+        ];
+        for (source, expected) in tests {
+            assert_eq!(value(source), expected, "source: {}", source);
+        }
+    }
+
+    #[test]
+    fn test_functions_and_returns() {
+        let tests = [
+            ("let add = fn(a, b) { a + b }; add(2, add(3, 4))", "9"),
+            ("let f = fn(x) { return x * 2; 0 }; f(5)", "10"),
+            (
+                "if (true) { if (true) { return 10; } return 1; }; 5",
+                "10",
+            ),
+            (
+                "let adder = fn(x) { fn(y) { x + y } }; let add2 = adder(2); add2(3)",
+                "5",
+            ),
+            (
+                "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+                "610",
+            ),
+            (
+                "let map = fn(xs, f) { if (len(xs) == 0) { [] } else { push(map(rest(xs), f), 0) } }; len(map([1, 2, 3], fn(x) { x }))",
+                "3",
+            ),
+            ("let len = fn(x) { 42 }; len([1])", "42"),
+            ("let f = first; f([7])", "7"),
+        ];
+        for (source, expected) in tests {
+            assert_eq!(value(source), expected, "source: {}", source);
+        }
+    }
+
+    /// The message of the error `source` fails with and the source text
+    /// the error points at.
+    fn error(source: &str) -> (String, &str) {
+        let error = eval(source).unwrap_err();
+        (error.message, &source[error.span])
+    }
+
+    #[test]
+    fn test_errors() {
+        let tests = [
+            ("5 + true;", "Type mismatch: int + bool", "5 + true"),
+            ("-true", "Unknown operator: -bool", "-true"),
+            (
+                "true + false; 5",
+                "Unknown operator: bool + bool",
+                "true + false",
+            ),
+            (
+                "\"a\" - \"b\"",
+                "Unknown operator: string - string",
+                "\"a\" - \"b\"",
+            ),
+            ("foobar", "Undefined identifier 'foobar'", "foobar"),
+            ("let x = 1; x(2)", "Cannot call a value of type int", "x(2)"),
+            (
+                "let f = fn(a) { a }; f(1, 2)",
+                "Wrong number of arguments to 'f': expected 1, found 2",
+                "f(1, 2)",
+            ),
+            (
+                "len(1)",
+                "'len' expects a string, an array or a hash, found int",
+                "len(1)",
+            ),
+            ("1 / 0", "Division by zero", "1 / 0"),
+            (
+                "9223372036854775807 + 1",
+                "Integer overflow",
+                "9223372036854775807 + 1",
+            ),
+            (
+                "[1][true]",
+                "Array index must be an int, found bool",
+                "[1][true]",
+            ),
+            ("1[0]", "Cannot index a value of type int", "1[0]"),
+            (
+                "let f = fn(a) { a }; f( 1 , 2 ) + 1",
+                "Wrong number of arguments to 'f': expected 1, found 2",
+                "f( 1 , 2 )",
+            ),
+            (
+                "[ 1 ][ 0 ] + true",
+                "Type mismatch: int + bool",
+                "[ 1 ][ 0 ] + true",
+            ),
+            (
+                "{fn() {}: 1}",
+                "Cannot use a value of type function as a hash key",
+                "fn() {}",
+            ),
+            (
+                "{1: 2}[[]]",
+                "Cannot use a value of type array as a hash key",
+                "{1: 2}[[]]",
+            ),
+            (
+                "if (true) { 1 + [] }; 2",
+                "Type mismatch: int + array",
+                "1 + []",
+            ),
+            (
+                "for (x in 5) {}",
+                "Cannot iterate over a value of type int",
+                "5",
+            ),
+            (
+                "for (x in 0..true) {}",
+                "Range bounds must be ints, found int .. bool",
+                "0..true",
+            ),
+            (
+                "for ([a, b] in [[1]]) {}",
+                "Cannot destructure an array of 1 elements into 2 names",
+                "[[1]]",
+            ),
+            (
+                "for ([a] in \"x\") {}",
+                "Cannot destructure a value of type string",
+                "\"x\"",
+            ),
+        ];
+        for (source, message, span) in tests {
+            assert_eq!(
+                error(source),
+                (message.to_string(), span),
+                "source: {}",
+                source
+            );
+        }
+        assert_eq!(eval("-true").unwrap_err().kind, ErrorKind::Runtime);
+    }
+
+    #[test]
+    fn test_trace() {
+        let source = "let check = fn(x) { x + true };
+let twice = fn(x) { check(x) };
+let apply = fn(f) { f(1) };
+apply(twice);";
+        let error = eval(source).unwrap_err();
+        assert_eq!(error.message, "Type mismatch: int + bool");
+        assert_eq!(&source[error.span.clone()], "x + true");
+        let trace: Vec<(String, &str)> = error
+            .trace
+            .iter()
+            .map(|frame| {
+                let name = frame.function.map_or("?".to_string(), |n| n.to_string());
+                (name, &source[frame.call_site.clone()])
+            })
+            .collect();
+        assert_eq!(
+            trace,
+            vec![
+                ("check".to_string(), "check(x)"),
+                ("twice".to_string(), "f(1)"),
+                ("apply".to_string(), "apply(twice)"),
+            ]
+        );
+
+        let error = eval("fn(x) { x() }(1)").unwrap_err();
+        assert_eq!(error.trace.len(), 1);
+        assert_eq!(error.trace[0].function, None);
+        // errors outside any function have no trace
+        assert!(eval("1 + true").unwrap_err().trace.is_empty());
+    }
+
+    #[test]
+    fn test_exceptions() {
+        let tests = [
+            (
+                "try { 1 + true } catch (e) { e }",
+                "\"Type mismatch: int + bool\"",
+            ),
+            ("try { throw [1]; 2 } catch (e) { e }", "[1]"),
+            ("try { 1 } catch (e) { 2 }", "1"),
+            (
+                "let f = fn() { throw \"bad\"; }; try { f() } catch (e) { e + \"!\" }",
+                "\"bad!\"",
+            ),
+            (
+                "let f = fn() { try { return 1; } catch (e) { 2 }; 3 }; f()",
+                "1",
+            ),
+            (
+                "try { try { throw 1; } catch (e) { throw e + 1; } } catch (e) { e }",
+                "2",
+            ),
+        ];
+        for (source, expected) in tests {
+            assert_eq!(value(source), expected, "source: {}", source);
+        }
+
+        let source = "let check = fn(n) { if (n < 0) { throw {\"n\": n}; } n };\ncheck(-1);";
+        let error = eval(source).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Thrown);
+        assert_eq!(error.message, "Uncaught exception: {\"n\": -1}");
+        assert_eq!(&source[error.span.clone()], "throw {\"n\": n}");
+        assert_eq!(error.thrown, Some(Object::from(HashMap::from([("n", -1)]))));
+        assert_eq!(error.trace.len(), 1);
+
+        // limits stop the script even inside a `try`
+        let error = eval("let f = fn() { f() }; try { f() } catch (e) { 0 }").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::LimitExceeded(Limit::Stack));
+    }
+
+    #[test]
+    fn test_loops() {
+        let tests = [
+            ("while (false) { 1 }", "null"),
+            (
+                "let i = 0; let sum = 0; while (i < 5) { let i = i + 1; if (i == 2) { continue; } let sum = sum + i; }; sum",
+                "13",
+            ),
+            (
+                "let i = 0; while (true) { let i = i + 1; if (i == 10) { break; } }; i",
+                "10",
+            ),
+            (
+                "let n = 0; let i = 0; while (i < 3) { let i = i + 1; let j = 0; while (true) { let j = j + 1; let n = n + 1; if (j == 2) { break; } } }; n",
+                "6",
+            ),
+            (
+                "let f = fn() { let i = 0; while (true) { let i = i + 1; if (i == 4) { return i; } } }; f()",
+                "4",
+            ),
+            ("while (true) { try { break; } catch (e) { 0 } }; 1", "1"),
+            // far more iterations than calls could nest
+            (
+                "let i = 0; while (i < 100000) { let i = i + 1; }; i",
+                "100000",
+            ),
+            ("0..3", "[0, 1, 2]"),
+            ("0..=3", "[0, 1, 2, 3]"),
+            ("3..0", "[]"),
+            (
+                "let sum = 0; for (x in [1, 2, 3]) { let sum = sum + x; }; sum",
+                "6",
+            ),
+            (
+                "let s = \"\"; for (k in {\"b\": 1, \"a\": 2}) { let s = s + k; }; s",
+                "\"ab\"",
+            ),
+            (
+                "let sum = 0; for ([k, v] in {1: 10, 2: 20}) { let sum = sum + k * v; }; sum",
+                "50",
+            ),
+            (
+                "let s = \"\"; for (c in \"héllo\") { let s = c + s; }; s",
+                "\"olléh\"",
+            ),
+            (
+                "let sum = 0; for ([a, b] in [[1, 2], [3, 4]]) { let sum = sum + a * b; }; sum",
+                "14",
+            ),
+            (
+                "let n = 0; for (i in 0..10) { if (i == 2) { continue; } if (i == 5) { break; } let n = n + i; }; n",
+                "8",
+            ),
+            (
+                "for (i in 0..-9223372036854775807 - 1) { 1 }; 0..-9223372036854775807 - 1",
+                "[]",
+            ),
+            ("for (i in 0..1000000) {}; i", "999999"),
+        ];
+        for (source, expected) in tests {
+            assert_eq!(value(source), expected, "source: {}", source);
+        }
+
+        // the parser rejects this, but a tree built by hand can have it
+        let program = Program {
+            statements: vec![parser::ast::Statement::Break(lexer::tokens::Token::Break {
+                location: 0,
+            })],
+        };
+        let error = Evaluator::with_output(io::sink())
+            .eval(&program)
+            .unwrap_err();
+        assert_eq!(
+            error,
+            EvalError::new("'break' outside of a loop".to_string(), 0..5)
+        );
+    }
+
+    #[test]
+    fn test_state_and_output() {
+        let output = Shared::default();
+        let mut evaluator = Evaluator::with_output(output.clone());
+        run(
+            &mut evaluator,
+            "let greet = fn(name) { puts(\"hi \" + name) };",
+        )
+        .unwrap();
+        assert_eq!(
+            run(&mut evaluator, "greet(\"bob\"); puts(1, [\"a\"]);"),
+            Ok(Object::Null)
+        );
+        assert_eq!(
+            String::from_utf8(output.0.borrow().clone()).unwrap(),
+            "hi bob\n1\n[\"a\"]\n"
+        );
+
+        evaluator
+            .builtins_mut()
+            .register("answer", Arity::Exact(0), |_, _| Ok(Object::from(42)));
+        assert_eq!(run(&mut evaluator, "answer()"), Ok(Object::from(42)));
+        *evaluator.builtins_mut() = Builtins::default();
+        assert_eq!(
+            run(&mut evaluator, "len"),
+            Err(EvalError::new(
+                "Undefined identifier 'len'".to_string(),
+                0..3
+            ))
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut evaluator = Evaluator::with_output(io::sink());
+        evaluator.set_limits(Limits {
+            max_depth: 20,
+            ..Limits::default()
+        });
+        let error = run(&mut evaluator, "let f = fn() { f() }; f();").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::LimitExceeded(Limit::Depth));
+        assert_eq!(error.message, "Call depth limit of 20 exceeded");
+        assert_eq!(error.span, 15..18);
+        assert_eq!(error.trace.len(), 20);
+
+        evaluator.set_limits(Limits {
+            max_steps: Some(100),
+            max_size: Some(3),
+            ..Limits::default()
+        });
+        let countdown = "let f = fn(n) { if (n > 0) { f(n - 1) } else { n } };";
+        run(&mut evaluator, countdown).unwrap();
+        // each `eval` gets the whole budget
+        assert_eq!(run(&mut evaluator, "f(5)"), Ok(Object::from(0)));
+        assert_eq!(run(&mut evaluator, "f(5)"), Ok(Object::from(0)));
+        let error = run(&mut evaluator, "f(50)").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::LimitExceeded(Limit::Steps));
+        assert_eq!(error.message, "Step limit of 100 exceeded");
+
+        let tests = [
+            "[1, 2, 3, 4]",
+            "push([1, 2, 3], 4)",
+            "\"ab\" + \"cd\"",
+            "{1: 1, 2: 2, 3: 3, 4: 4}",
+            "0..4",
+        ];
+        for source in tests {
+            assert_eq!(
+                run(&mut evaluator, source),
+                Err(EvalError::limit(Limit::Size, 3, 0..source.len())),
+                "source: {}",
+                source
+            );
+        }
+        assert_eq!(run(&mut evaluator, "len([1, 2, 3])"), Ok(Object::from(3)));
+        // a range a `for` goes through is never built
+        let error = run(&mut evaluator, "for (i in 0..1000000) {}").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::LimitExceeded(Limit::Steps));
+    }
+
+    #[test]
+    fn test_stack_limit() {
+        // a spawned thread has the smallest stack the default limits allow for
+        std::thread::spawn(|| {
+            let mut evaluator = Evaluator::with_output(io::sink());
+            let nested = "let f = fn(n) { if (n == 0) { 0 } else { (1 + (1 + (1 + (1 + (1 + f(n - 1)))))) } };";
+            run(&mut evaluator, nested).unwrap();
+            match run(&mut evaluator, "f(95)") {
+                Ok(value) => assert_eq!(value, Object::from(475)),
+                Err(error) => assert_eq!(error.kind, ErrorKind::LimitExceeded(Limit::Stack)),
+            }
+            let error = run(&mut evaluator, "f(100000)").unwrap_err();
+            assert_eq!(error.kind, ErrorKind::LimitExceeded(Limit::Stack));
+            assert_eq!(error.message, "Stack limit of 1048576 bytes exceeded");
+            assert_eq!(run(&mut evaluator, "f(3)"), Ok(Object::from(15)));
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        // as deep as the parser allows, which must fit a test thread's stack
+        let n = parser::MAX_NESTING;
+        let tests = [
+            (format!("1{}", "+1".repeat(n - 1)), Object::from(n as i64)),
+            (format!("{}1", "-".repeat(n - 1)), Object::from(-1)),
+            (
+                format!(
+                    "{}1{}",
+                    "if (true) { ".repeat(n / 2 - 1),
+                    " }".repeat(n / 2 - 1)
+                ),
+                Object::from(1),
+            ),
+            (
+                format!(
+                    "{}1{}",
+                    "fn() { ".repeat(n / 3 - 1),
+                    " }()".repeat(n / 3 - 1)
+                ),
+                Object::from(1),
+            ),
+        ];
+        for (source, expected) in tests {
+            assert_eq!(eval(&source), Ok(expected), "source: {}", source);
+        }
+
+        let mut evaluator = Evaluator::with_output(io::sink());
+        evaluator.set_limits(Limits {
+            max_nesting: 10,
+            ..Limits::default()
+        });
+        assert_eq!(run(&mut evaluator, "--------1"), Ok(Object::from(1)));
+        let error = run(&mut evaluator, "----------1").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::LimitExceeded(Limit::Nesting));
+        assert_eq!(error.message, "Nesting limit of 10 exceeded");
+        assert_eq!(error.span, 10..11);
+        let error = run(&mut evaluator, "[[[[[[[[[[1]]]]]]]]]]").unwrap_err();
+        assert_eq!(error.span, 10..11);
+    }
+
+    /// Doubles the work with each level, so never finishes on its own.
+    const ENDLESS: &str = "let f = fn(n) { if (n > 0) { f(n - 1) + f(n - 1) } else { 0 } }; f(40)";
+
+    #[test]
+    fn test_cancellation() {
+        let mut evaluator = Evaluator::with_output(io::sink());
+        let handle = evaluator.cancel_handle();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            handle.cancel();
+        });
+        let error = run(&mut evaluator, ENDLESS).unwrap_err();
+        canceller.join().unwrap();
+        assert_eq!(error.kind, ErrorKind::Cancelled);
+        assert_eq!(error.message, "Evaluation cancelled");
+        // the flag is cleared for the next evaluation
+        assert_eq!(run(&mut evaluator, "f(3)"), Ok(Object::from(0)));
+
+        // a cancel before the evaluation starts is not lost
+        evaluator.cancel_handle().cancel();
+        let error = run(&mut evaluator, "f(3)").unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Cancelled);
+        assert_eq!(error.span, 0..0);
+        assert_eq!(run(&mut evaluator, "f(3)"), Ok(Object::from(0)));
+
+        evaluator.set_timeout(Some(Duration::from_millis(20)));
+        let error = run(&mut evaluator, ENDLESS).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Cancelled);
+        assert_eq!(error.message, "Evaluation timed out after 20ms");
+        assert_eq!(run(&mut evaluator, "f(3)"), Ok(Object::from(0)));
+    }
+}
@@ -0,0 +1,69 @@
+//! Bounds on the work a script may do, for running code that is not
+//! trusted. A script that goes past one fails with
+//! `ErrorKind::LimitExceeded` instead of hanging or crashing the host.
+
+use std::fmt;
+
+/// How deep calls may nest when no other depth is set. What keeps deep
+/// recursion from overflowing the Rust stack is `max_stack`; this only
+/// catches runaway recursion sooner.
+pub const DEFAULT_MAX_DEPTH: usize = 10_000;
+
+/// Bytes of Rust stack an `eval` may use when no other amount is set: half
+/// of the 2 MiB a spawned thread gets, leaving the rest to the host and to
+/// the frames between two checks.
+pub const DEFAULT_MAX_STACK: usize = 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Expressions one `eval` may evaluate.
+    pub max_steps: Option<u64>,
+    /// Function calls that may be in progress at once.
+    pub max_depth: usize,
+    /// Bytes of Rust stack one `eval` may use, counted from where it
+    /// starts. Always set, since calls and nested expressions both recurse;
+    /// this is what keeps a script from overflowing the stack of the thread
+    /// it runs on. Raise it only on a thread with a larger stack.
+    pub max_stack: usize,
+    /// Elements of an array, pairs of a hash or bytes of a string a script
+    /// may create.
+    pub max_size: Option<usize>,
+    /// Expressions and blocks that may nest inside each other. Always set,
+    /// since running a program recurses once or more per level. Programs
+    /// the parser accepts never go past `parser::MAX_NESTING`.
+    pub max_nesting: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_steps: None,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_stack: DEFAULT_MAX_STACK,
+            max_size: None,
+            max_nesting: parser::MAX_NESTING,
+        }
+    }
+}
+
+/// Which of the `Limits` a script went past.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Steps,
+    Depth,
+    Stack,
+    Size,
+    Nesting,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Steps => write!(f, "Step"),
+            Limit::Depth => write!(f, "Call depth"),
+            Limit::Stack => write!(f, "Stack"),
+            Limit::Size => write!(f, "Collection size"),
+            Limit::Nesting => write!(f, "Nesting"),
+        }
+    }
+}
@@ -0,0 +1,405 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    fmt,
+    hash::Hash,
+    rc::Rc,
+};
+
+use crate::{builtins::Builtin, code, environment::Env};
+
+/// A runtime value. Cloning is cheap: strings, arrays, hashes and
+/// functions are shared, and never changed once created.
+#[derive(Clone)]
+pub enum Object {
+    Integer(i64),
+    Boolean(bool),
+    String(Rc<str>),
+    Null,
+    Array(Rc<Vec<Object>>),
+    /// Kept sorted by key, so hashes print and iterate in a fixed order.
+    Hash(Rc<BTreeMap<HashKey, Object>>),
+    Function(Rc<Closure>),
+    Builtin(Rc<Builtin>),
+}
+
+/// The values that can be hash keys.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HashKey {
+    Integer(i64),
+    Boolean(bool),
+    String(Rc<str>),
+}
+
+/// A function literal together with the environment it was created in.
+pub struct Closure {
+    pub(crate) code: Rc<code::Function>,
+    pub(crate) env: Env,
+}
+
+impl Object {
+    /// The name of the value's type, as used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Integer(_) => "int",
+            Object::Boolean(_) => "bool",
+            Object::String(_) => "string",
+            Object::Null => "null",
+            Object::Array(_) => "array",
+            Object::Hash(_) => "hash",
+            Object::Function(_) | Object::Builtin(_) => "function",
+        }
+    }
+
+    /// Whether an `if` takes its consequence for this value: everything
+    /// but `false` and `null` is truthy.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Object::Boolean(false) | Object::Null)
+    }
+
+    /// The value as a hash key, or `None` if its type cannot be one.
+    pub fn hash_key(&self) -> Option<HashKey> {
+        match self {
+            Object::Integer(value) => Some(HashKey::Integer(*value)),
+            Object::Boolean(value) => Some(HashKey::Boolean(*value)),
+            Object::String(value) => Some(HashKey::String(value.clone())),
+            _ => None,
+        }
+    }
+
+    /// How the value is written as a literal; unlike `Display`, strings are
+    /// quoted.
+    fn write_literal(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Object::String(s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            other => fmt::Display::fmt(other, f),
+        }
+    }
+}
+
+impl From<i64> for Object {
+    fn from(value: i64) -> Self {
+        Object::Integer(value)
+    }
+}
+
+impl From<bool> for Object {
+    fn from(value: bool) -> Self {
+        Object::Boolean(value)
+    }
+}
+
+impl From<&str> for Object {
+    fn from(value: &str) -> Self {
+        Object::String(value.into())
+    }
+}
+
+impl From<String> for Object {
+    fn from(value: String) -> Self {
+        Object::String(value.into())
+    }
+}
+
+impl<T: Into<Object>> From<Vec<T>> for Object {
+    fn from(value: Vec<T>) -> Self {
+        Object::Array(Rc::new(value.into_iter().map(Into::into).collect()))
+    }
+}
+
+impl<K: Into<HashKey>, V: Into<Object>> From<HashMap<K, V>> for Object {
+    fn from(value: HashMap<K, V>) -> Self {
+        let pairs = value.into_iter().map(|(k, v)| (k.into(), v.into()));
+        Object::Hash(Rc::new(pairs.collect()))
+    }
+}
+
+impl From<HashKey> for Object {
+    fn from(key: HashKey) -> Self {
+        match key {
+            HashKey::Integer(value) => Object::Integer(value),
+            HashKey::Boolean(value) => Object::Boolean(value),
+            HashKey::String(value) => Object::String(value),
+        }
+    }
+}
+
+impl From<i64> for HashKey {
+    fn from(value: i64) -> Self {
+        HashKey::Integer(value)
+    }
+}
+
+impl From<bool> for HashKey {
+    fn from(value: bool) -> Self {
+        HashKey::Boolean(value)
+    }
+}
+
+impl From<&str> for HashKey {
+    fn from(value: &str) -> Self {
+        HashKey::String(value.into())
+    }
+}
+
+impl From<String> for HashKey {
+    fn from(value: String) -> Self {
+        HashKey::String(value.into())
+    }
+}
+
+/// Why an `Object` could not be converted to a Rust value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConversionError {
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Expected {}, found {}", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+/// Lets `Vec<Object>` and `HashMap<String, Object>` convert like other
+/// collections, through the identity conversion that cannot fail.
+impl From<Infallible> for ConversionError {
+    fn from(never: Infallible) -> Self {
+        match never {}
+    }
+}
+
+fn mismatch(expected: &'static str, found: &Object) -> ConversionError {
+    ConversionError {
+        expected,
+        found: found.type_name(),
+    }
+}
+
+impl TryFrom<Object> for i64 {
+    type Error = ConversionError;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Integer(value) => Ok(value),
+            other => Err(mismatch("int", &other)),
+        }
+    }
+}
+
+impl TryFrom<Object> for bool {
+    type Error = ConversionError;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Boolean(value) => Ok(value),
+            other => Err(mismatch("bool", &other)),
+        }
+    }
+}
+
+impl TryFrom<Object> for String {
+    type Error = ConversionError;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::String(value) => Ok(value.to_string()),
+            other => Err(mismatch("string", &other)),
+        }
+    }
+}
+
+impl<T> TryFrom<Object> for Vec<T>
+where
+    T: TryFrom<Object>,
+    ConversionError: From<T::Error>,
+{
+    type Error = ConversionError;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Array(elements) => elements
+                .iter()
+                .map(|element| Ok(T::try_from(element.clone())?))
+                .collect(),
+            other => Err(mismatch("array", &other)),
+        }
+    }
+}
+
+impl<K, V> TryFrom<Object> for HashMap<K, V>
+where
+    K: TryFrom<Object> + Eq + Hash,
+    V: TryFrom<Object>,
+    ConversionError: From<K::Error> + From<V::Error>,
+{
+    type Error = ConversionError;
+
+    fn try_from(value: Object) -> Result<Self, Self::Error> {
+        match value {
+            Object::Hash(pairs) => pairs
+                .iter()
+                .map(|(key, value)| {
+                    let key = K::try_from(Object::from(key.clone()))?;
+                    Ok((key, V::try_from(value.clone())?))
+                })
+                .collect(),
+            other => Err(mismatch("hash", &other)),
+        }
+    }
+}
+
+/// Values are equal when they have the same type and contents; functions
+/// only equal themselves.
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Object::Integer(a), Object::Integer(b)) => a == b,
+            (Object::Boolean(a), Object::Boolean(b)) => a == b,
+            (Object::String(a), Object::String(b)) => a == b,
+            (Object::Null, Object::Null) => true,
+            (Object::Array(a), Object::Array(b)) => a == b,
+            (Object::Hash(a), Object::Hash(b)) => a == b,
+            (Object::Function(a), Object::Function(b)) => Rc::ptr_eq(a, b),
+            (Object::Builtin(a), Object::Builtin(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// Every value equals itself: there are no floats and no NaN.
+impl Eq for Object {}
+
+/// Strings are written as they are, without quotes, so `puts("a")` prints
+/// `a`; inside arrays and hashes they are quoted.
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Object::Integer(value) => write!(f, "{}", value),
+            Object::Boolean(value) => write!(f, "{}", value),
+            Object::String(value) => write!(f, "{}", value),
+            Object::Null => write!(f, "null"),
+            Object::Array(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    element.write_literal(f)?;
+                }
+                write!(f, "]")
+            }
+            Object::Hash(pairs) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    Object::from(key.clone()).write_literal(f)?;
+                    write!(f, ": ")?;
+                    value.write_literal(f)?;
+                }
+                write!(f, "}}")
+            }
+            Object::Function(closure) => {
+                write!(f, "fn(")?;
+                for (i, parameter) in closure.code.parameters.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", parameter)?;
+                }
+                write!(f, ") {{ ... }}")
+            }
+            Object::Builtin(builtin) => write!(f, "builtin {}", builtin.name()),
+        }
+    }
+}
+
+impl fmt::Debug for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_literal(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let array = Object::from(vec![
+            Object::from(1),
+            Object::from("a \"b\"\n"),
+            Object::from(vec![Object::Boolean(true), Object::Null]),
+        ]);
+        assert_eq!(array.to_string(), r#"[1, "a \"b\"\n", [true, null]]"#);
+        assert_eq!(Object::from("plain").to_string(), "plain");
+        assert_eq!(format!("{:?}", Object::from("plain")), "\"plain\"");
+
+        let hash = Object::from(HashMap::from([("b", 2), ("a", 1)]));
+        assert_eq!(hash.to_string(), r#"{"a": 1, "b": 2}"#);
+    }
+
+    #[test]
+    fn test_conversions() {
+        let array = Object::from(vec![vec![1, 2], vec![]]);
+        assert_eq!(
+            Vec::<Vec<i64>>::try_from(array),
+            Ok(vec![vec![1, 2], vec![]])
+        );
+
+        let hash = Object::from(HashMap::from([("on", true)]));
+        assert_eq!(
+            HashMap::<String, bool>::try_from(hash.clone()),
+            Ok(HashMap::from([("on".to_string(), true)]))
+        );
+        assert_eq!(
+            HashMap::<String, Object>::try_from(hash.clone()).map(|h| h.len()),
+            Ok(1)
+        );
+        assert_eq!(
+            HashMap::<i64, bool>::try_from(hash)
+                .unwrap_err()
+                .to_string(),
+            "Expected int, found string"
+        );
+        assert_eq!(String::try_from(Object::from("s")), Ok("s".to_string()));
+        assert_eq!(
+            Vec::<i64>::try_from(Object::from(vec![Object::from(1), Object::Null])),
+            Err(ConversionError {
+                expected: "int",
+                found: "null"
+            })
+        );
+        assert!(bool::try_from(Object::from(1)).is_err());
+    }
+
+    #[test]
+    fn test_equality_and_truthiness() {
+        assert_eq!(
+            Object::from(vec![Object::from(1)]),
+            Object::from(vec![Object::from(1)])
+        );
+        assert_ne!(Object::from(1), Object::from(true));
+        assert_ne!(Object::from("1"), Object::from(1));
+        assert!(Object::from(0).is_truthy());
+        assert!(Object::from("").is_truthy());
+        assert!(!Object::Null.is_truthy());
+        assert!(!Object::from(false).is_truthy());
+    }
+}
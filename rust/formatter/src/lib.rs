@@ -0,0 +1,592 @@
+use std::{collections::HashMap, mem, ops::Range};
+
+use lexer::{tokens::Token, Lexer};
+use parser::{
+    ast::{Block, Expression, Program, Statement},
+    token_precedence, Parser, ParserError, Precedence,
+};
+
+const INDENT: &str = "  ";
+
+/// Parses `source` and prints it back in the canonical layout: two-space
+/// indentation, one statement per line, spaces around infix operators,
+/// terminating semicolons and opening braces on the same line. Comments and
+/// single blank lines between statements are kept. A statement with a
+/// comment inside one of its expressions, where the layout has no place for
+/// it, is kept as written.
+pub fn format(source: &str) -> Result<String, Vec<ParserError>> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse_program().expect("Failed to parse program");
+    let errors = parser.errors();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut formatter = Formatter::new(source);
+    formatter.program(&program);
+    Ok(formatter.out)
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    out: String,
+    indent: usize,
+    /// Comments in source order; everything before `next_comment` is printed.
+    comments: Vec<(usize, &'a str)>,
+    next_comment: usize,
+    /// Start and end offsets of every token, comments included.
+    spans: Vec<(usize, usize)>,
+    /// Location of each `{` mapped to the location of its matching `}`.
+    closing_braces: HashMap<usize, usize>,
+    /// Set when a block of the statement being printed has a comment
+    /// before its `{`, which the block would otherwise print inside it.
+    misplaced_comment: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(source: &'a str) -> Self {
+        let mut comments = Vec::new();
+        let mut spans = Vec::new();
+        let mut closing_braces = HashMap::new();
+        let mut open_braces = Vec::new();
+
+        let mut lex = Lexer::with_comments(source);
+        loop {
+            let token = lex.next_token();
+            let Some(location) = token.location() else {
+                break;
+            };
+            spans.push((location, lex.position()));
+            match token {
+                Token::Comment { raw, .. } => {
+                    comments.push((location, &source[location..location + raw.len()]))
+                }
+                Token::LeftBrace { .. } => open_braces.push(location),
+                Token::RightBrace { .. } => {
+                    if let Some(open) = open_braces.pop() {
+                        closing_braces.insert(open, location);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Formatter {
+            source,
+            out: String::new(),
+            indent: 0,
+            comments,
+            next_comment: 0,
+            spans,
+            closing_braces,
+            misplaced_comment: false,
+        }
+    }
+
+    fn pending_comment(&self, before: usize) -> Option<(usize, &'a str)> {
+        self.comments
+            .get(self.next_comment)
+            .filter(|(location, _)| *location < before)
+            .copied()
+    }
+
+    /// The source text between `location` and the token preceding it.
+    fn gap_before(&self, location: usize) -> &'a str {
+        let index = self.spans.partition_point(|(start, _)| *start < location);
+        let from = if index == 0 {
+            0
+        } else {
+            self.spans[index - 1].1
+        };
+        &self.source[from..location]
+    }
+
+    fn write_indent(&mut self) {
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    /// Keeps a single blank line where the source had one or more, except at
+    /// the start of the file or a block.
+    fn separate(&mut self, location: usize) {
+        let blank = self.gap_before(location).matches('\n').count() > 1;
+        if blank && !self.out.is_empty() && !self.out.ends_with("{\n") {
+            self.out.push('\n');
+        }
+    }
+
+    fn comments_before(&mut self, before: usize) {
+        while let Some((location, text)) = self.pending_comment(before) {
+            self.separate(location);
+            self.write_indent();
+            self.out.push_str(text);
+            self.out.push('\n');
+            self.next_comment += 1;
+        }
+    }
+
+    fn trailing_comment(&mut self, before: usize) {
+        if let Some((location, text)) = self.pending_comment(before) {
+            if !self.gap_before(location).contains('\n') {
+                self.out.push(' ');
+                self.out.push_str(text);
+                self.next_comment += 1;
+            }
+        }
+    }
+
+    fn program(&mut self, program: &Program) {
+        self.statements(&program.statements, self.source.len());
+    }
+
+    fn statements(&mut self, statements: &[Statement], end: usize) {
+        for (i, stmt) in statements.iter().enumerate() {
+            let start = statement_start(stmt);
+            let next = statements.get(i + 1);
+            let boundary = next.map_or(end, statement_start);
+
+            self.comments_before(start);
+            self.separate(start);
+            self.write_indent();
+            let mark = (self.out.len(), self.next_comment);
+            let outer = mem::take(&mut self.misplaced_comment);
+            self.statement(stmt, next);
+            // comments still pending inside the statement sat in one of its
+            // expressions
+            let span = stmt.span();
+            if mem::replace(&mut self.misplaced_comment, outer)
+                || self.pending_comment(span.end).is_some()
+            {
+                self.verbatim(span, mark);
+            }
+            self.trailing_comment(boundary);
+            self.out.push('\n');
+        }
+        self.comments_before(end);
+    }
+
+    /// Replaces what was printed since `mark` with the source of `span`,
+    /// ending it with a `;` if the printed statement had one.
+    fn verbatim(&mut self, span: Range<usize>, (len, next_comment): (usize, usize)) {
+        let terminated = self.out.ends_with(';');
+        self.out.truncate(len);
+        self.out.push_str(&self.source[span.clone()]);
+        if terminated {
+            self.out.push(';');
+        }
+        self.next_comment = next_comment;
+        while self.pending_comment(span.end).is_some() {
+            self.next_comment += 1;
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement, next: Option<&Statement>) {
+        match stmt {
+            Statement::Let(l) => {
+                self.out.push_str("let ");
+                self.expression(&l.identifier);
+                if let Some(annotation) = &l.annotation {
+                    self.out.push_str(&format!(": {}", annotation));
+                }
+                self.out.push_str(" = ");
+                self.expression(&l.value);
+                self.out.push(';');
+            }
+            Statement::Return(r) => {
+                self.out.push_str("return ");
+                self.expression(&r.return_value);
+                self.out.push(';');
+            }
+            Statement::Throw(t) => {
+                self.out.push_str("throw ");
+                self.expression(&t.value);
+                self.out.push(';');
+            }
+            Statement::While(w) => {
+                self.out.push_str("while (");
+                self.expression(&w.condition);
+                self.out.push_str(") ");
+                self.block(&w.body);
+            }
+            Statement::For(f) => {
+                self.out.push_str("for (");
+                self.expression(&f.variable);
+                self.out.push_str(" in ");
+                self.expression(&f.iterable);
+                self.out.push_str(") ");
+                self.block(&f.body);
+            }
+            Statement::Break(t) | Statement::Continue(t) => {
+                self.out.push_str(&format!("{};", t));
+            }
+            Statement::Expression(e) => {
+                self.expression(&e.expression);
+                // a statement-level `if` or `try` reads better without the semicolon,
+                // but only when the next statement cannot continue it as an
+                // infix, call or index expression
+                let continues = next.is_some_and(|n| {
+                    matches!(
+                        statement_token(n),
+                        Token::Minus { .. } | Token::LeftParen { .. } | Token::LeftBracket { .. }
+                    )
+                });
+                if !matches!(e.expression, Expression::If(_) | Expression::Try(_)) || continues {
+                    self.out.push(';');
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        let start = block.token.location().unwrap_or_default();
+        let end = self
+            .closing_braces
+            .get(&start)
+            .copied()
+            .unwrap_or(self.source.len());
+        if self.pending_comment(start).is_some() {
+            self.misplaced_comment = true;
+        }
+
+        if block.statements.is_empty() && self.pending_comment(end).is_none() {
+            self.out.push_str("{}");
+            return;
+        }
+
+        self.out.push_str("{\n");
+        self.indent += 1;
+        self.statements(&block.statements, end);
+        self.indent -= 1;
+        self.write_indent();
+        self.out.push('}');
+    }
+
+    fn separated(&mut self, items: &[Expression]) {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.expression(item);
+        }
+    }
+
+    /// Prints a sub-expression, parenthesized only where dropping the
+    /// parentheses would change how it parses.
+    fn operand(&mut self, expr: &Expression, parent: Precedence, right: bool) {
+        let grouped = match expr {
+            Expression::Infix(i) => {
+                let own = token_precedence(&i.token);
+                own < parent || (right && own == parent)
+            }
+            Expression::Prefix(_) => parent >= Precedence::Call,
+            _ => false,
+        };
+        if grouped {
+            self.out.push('(');
+            self.expression(expr);
+            self.out.push(')');
+        } else {
+            self.expression(expr);
+        }
+    }
+
+    fn expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Identifier(t)
+            | Expression::Integer(t)
+            | Expression::Boolean(t)
+            | Expression::String(t) => {
+                self.out.push_str(&t.to_string());
+            }
+            Expression::Prefix(p) => {
+                self.out.push_str(&p.token.to_string());
+                self.operand(&p.right, Precedence::Prefix, true);
+            }
+            Expression::Infix(i) => {
+                let precedence = token_precedence(&i.token);
+                self.operand(&i.left, precedence, false);
+                // ranges read as one unit, `0..10`
+                if precedence == Precedence::Range {
+                    self.out.push_str(&i.token.to_string());
+                } else {
+                    self.out.push_str(&format!(" {} ", i.token));
+                }
+                self.operand(&i.right, precedence, true);
+            }
+            Expression::If(i) => {
+                self.out.push_str("if (");
+                self.expression(&i.condition);
+                self.out.push_str(") ");
+                self.block(&i.consequence);
+                if let Some(alternative) = &i.alternative {
+                    self.out.push_str(" else ");
+                    self.block(alternative);
+                }
+            }
+            Expression::Try(t) => {
+                self.out.push_str("try ");
+                self.block(&t.body);
+                self.out.push_str(" catch (");
+                self.expression(&t.parameter);
+                self.out.push_str(") ");
+                self.block(&t.handler);
+            }
+            Expression::Function(f) => {
+                self.out.push_str("fn(");
+                for (i, parameter) in f.parameters.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.expression(parameter);
+                    if let Some(annotation) = f.parameter_type(i) {
+                        self.out.push_str(&format!(": {}", annotation));
+                    }
+                }
+                self.out.push(')');
+                if let Some(annotation) = &f.return_type {
+                    self.out.push_str(&format!(" -> {}", annotation));
+                }
+                self.out.push(' ');
+                self.block(&f.body);
+            }
+            Expression::Call(c) => {
+                self.operand(&c.function, Precedence::Call, false);
+                self.out.push('(');
+                self.separated(&c.arguments);
+                self.out.push(')');
+            }
+            Expression::Array(a) => {
+                self.out.push('[');
+                self.separated(&a.elements);
+                self.out.push(']');
+            }
+            Expression::Hash(h) => {
+                self.out.push('{');
+                for (i, (key, value)) in h.pairs.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.expression(key);
+                    self.out.push_str(": ");
+                    self.expression(value);
+                }
+                self.out.push('}');
+            }
+            Expression::Index(i) => {
+                self.operand(&i.left, Precedence::Index, false);
+                self.out.push('[');
+                self.expression(&i.index);
+                self.out.push(']');
+            }
+        }
+    }
+}
+
+fn statement_token<'s, 'a>(stmt: &'s Statement<'a>) -> &'s Token<'a> {
+    match stmt {
+        Statement::Let(l) => &l.token,
+        Statement::Return(r) => &r.token,
+        Statement::Throw(t) => &t.token,
+        Statement::While(w) => &w.token,
+        Statement::For(f) => &f.token,
+        Statement::Break(t) | Statement::Continue(t) => t,
+        Statement::Expression(e) => &e.token,
+    }
+}
+
+fn statement_start(stmt: &Statement) -> usize {
+    statement_token(stmt).location().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format;
+
+    fn assert_formats(input: &str, expected: &str) {
+        let formatted = format(input).expect("Failed to format");
+        assert_eq!(formatted, expected, "input: {}", input);
+        let again = format(&formatted).expect("Failed to format formatted output");
+        assert_eq!(again, formatted, "formatting is not idempotent");
+    }
+
+    #[test]
+    fn test_layout() {
+        assert_formats(
+            "let five=5;let add=fn(x,y){x+y};\n\n\n\nlet result = add(five,  10)",
+            "let five = 5;
+let add = fn(x, y) {
+  x + y;
+};
+
+let result = add(five, 10);
+",
+        );
+        assert_formats(
+            "if(5<10){return true}else{return false;};10==10;fn(){}",
+            "if (5 < 10) {
+  return true;
+} else {
+  return false;
+}
+10 == 10;
+fn() {};
+",
+        );
+    }
+
+    #[test]
+    fn test_minimal_parentheses() {
+        assert_formats("((a + b) + c);", "a + b + c;\n");
+        assert_formats("(a + (b + c));", "a + (b + c);\n");
+        assert_formats("(a + b) * -(c - d);", "(a + b) * -(c - d);\n");
+        assert_formats("(-f)(x) + (g(y));", "(-f)(x) + g(y);\n");
+        assert_formats("(5 < 10) > 5", "5 < 10 > 5;\n");
+        assert_formats("(-a)[0] + (b[1])[2];", "(-a)[0] + b[1][2];\n");
+        assert_formats(
+            "[ \"a\" ,(1+2)*3,[ ] ][0]",
+            "[\"a\", (1 + 2) * 3, []][0];\n",
+        );
+        assert_formats(
+            "{ \"a\" :(1+2), true:{ } }[\"a\"]",
+            "{\"a\": 1 + 2, true: {}}[\"a\"];\n",
+        );
+    }
+
+    #[test]
+    fn test_annotations() {
+        assert_formats(
+            "let n:int=1;let f=fn(a :[ string ],g:fn( int,bool )->int)->fn()->null{g}",
+            "let n: int = 1;
+let f = fn(a: [string], g: fn(int, bool) -> int) -> fn() -> null {
+  g;
+};
+",
+        );
+    }
+
+    #[test]
+    fn test_try() {
+        assert_formats(
+            "let r=try{f()}catch(e){throw  e}\ntry { g() } catch ( e ) {}",
+            "let r = try {
+  f();
+} catch (e) {
+  throw e;
+};
+try {
+  g();
+} catch (e) {}
+",
+        );
+    }
+
+    #[test]
+    fn test_while() {
+        assert_formats(
+            "while(i<3){if(i==1){break}\ncontinue ;}\nwhile (x) {};\n-1",
+            "while (i < 3) {
+  if (i == 1) {
+    break;
+  }
+  continue;
+}
+while (x) {}
+-1;
+",
+        );
+    }
+
+    #[test]
+    fn test_for() {
+        assert_formats(
+            "for(x in 0 .. n+1){puts(x)}\nfor ( [k,v] in {1: 2} ) {};",
+            "for (x in 0..n + 1) {
+  puts(x);
+}
+for ([k, v] in {1: 2}) {}
+",
+        );
+    }
+
+    #[test]
+    fn test_if_statement_semicolon() {
+        assert_formats(
+            "if (x) { 1 }; -5;",
+            "if (x) {
+  1;
+};
+-5;
+",
+        );
+        assert_formats(
+            "if (x) { 1 }; [2];",
+            "if (x) {
+  1;
+};
+[2];
+",
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_formats(
+            "// leading\nlet x = 5; // five\n\n// about y\nlet y = fn() {\n  // inside\n  x // trailing x\n  // last\n};\n// end\n",
+            "// leading
+let x = 5; // five
+
+// about y
+let y = fn() {
+  // inside
+  x; // trailing x
+  // last
+};
+// end
+",
+        );
+        assert_formats(
+            "let f = fn() { // nothing here\n};",
+            "let f = fn() {
+  // nothing here
+};
+",
+        );
+    }
+
+    #[test]
+    fn test_comments_inside_expressions() {
+        assert_formats(
+            "f(1, // c\n 2)\nlet  x=1;",
+            "f(1, // c
+ 2);
+let x = 1;
+",
+        );
+        assert_formats(
+            "if(x){ g( [1, // one\n  2] ) }\nlet h = {\"a\": 1 // one\n};",
+            "if (x) {
+  g( [1, // one
+  2] );
+}
+let h = {\"a\": 1 // one
+};
+",
+        );
+        assert_formats(
+            "if (x // why\n) { y }\nwhile(a){ b(1 // c\n) }",
+            "if (x // why
+) { y }
+while (a) {
+  b(1 // c
+);
+}
+",
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_source() {
+        assert!(format("let = 5;").is_err());
+    }
+}
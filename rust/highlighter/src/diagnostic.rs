@@ -0,0 +1,195 @@
+//! Error and warning messages that quote the offending source line:
+//!
+//! ```text
+//! error: Unexpected '='. Expected identifier
+//!  --> example.monke:2:5
+//!   |
+//! 2 | let = 10;
+//!   |     ^
+//! ```
+//!
+//! Notes, like the calls a runtime error happened in, follow as
+//! `  = note: ...` lines.
+
+use std::ops::Range;
+
+use lexer::{lines::LineIndex, Lexer};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// Byte range of the source the message is about.
+    pub span: Range<usize>,
+    pub notes: Vec<String>,
+}
+
+/// The span of the token starting at `location`, or an empty span at the
+/// end of `source` when there is no location.
+pub fn token_span(source: &str, location: Option<usize>) -> Range<usize> {
+    let end = source.len()..source.len();
+    let Some(location) = location else {
+        return end;
+    };
+    let mut lex = Lexer::new(source);
+    lex.seek(location);
+    lex.next_token().span().unwrap_or(end)
+}
+
+fn paint(text: &str, sgr: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[{}m{}\x1b[0m", sgr, text)
+    } else {
+        text.to_string()
+    }
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Range<usize>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn warning(message: impl Into<String>, span: Range<usize>) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            message: message.into(),
+            span,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Renders the message with the source line it points at. With `color`
+    /// the line is syntax highlighted and the markers use ANSI colors.
+    pub fn render(&self, file: &str, source: &str, color: bool) -> String {
+        let (label, sgr) = match self.severity {
+            Severity::Error => ("error", "1;31"),
+            Severity::Warning => ("warning", "1;33"),
+        };
+        let start = self.span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let line = source[line_start..line_end].trim_end_matches('\r');
+        let (line_number, column) = LineIndex::new(source).line_col(start);
+
+        let end = self.span.end.clamp(start, line_start + line.len());
+        let width = source[start..end].chars().count().max(1);
+        let number = line_number.to_string();
+        let gutter = " ".repeat(number.len());
+        let bar = paint("|", "1;34", color);
+
+        let mut out = format!("{}: {}\n", paint(label, sgr, color), self.message);
+        out.push_str(&format!(
+            "{}{} {}:{}:{}\n",
+            gutter,
+            paint("-->", "1;34", color),
+            file,
+            line_number,
+            column
+        ));
+        out.push_str(&format!("{} {}\n", gutter, bar));
+        let quoted = if color {
+            crate::ansi(line)
+        } else {
+            line.to_string()
+        };
+        out.push_str(&format!("{} {}", paint(&number, "1;34", color), bar));
+        if !line.is_empty() {
+            out.push(' ');
+            out.push_str(&quoted);
+        }
+        out.push('\n');
+        out.push_str(&format!(
+            "{} {} {}{}",
+            gutter,
+            bar,
+            " ".repeat(column - 1),
+            paint(&"^".repeat(width), sgr, color)
+        ));
+        for note in &self.notes {
+            out.push_str(&format!(
+                "\n{} {} {}: {}",
+                gutter,
+                paint("=", "1;34", color),
+                paint("note", "1", color),
+                note
+            ));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_span() {
+        let source = "let x = 10;";
+        assert_eq!(token_span(source, Some(8)), 8..10);
+        assert_eq!(token_span(source, Some(10)), 10..11);
+        assert_eq!(token_span(source, None), 11..11);
+    }
+
+    #[test]
+    fn test_render() {
+        let source = "let x = 5;\nlet = 10;\n";
+        let diagnostic = Diagnostic::error(
+            "Unexpected '='. Expected identifier",
+            token_span(source, Some(15)),
+        );
+        assert_eq!(
+            diagnostic.render("example.monke", source, false),
+            "error: Unexpected '='. Expected identifier
+ --> example.monke:2:5
+  |
+2 | let = 10;
+  |     ^"
+        );
+
+        let at_end = Diagnostic::warning("Unexpected end of input", token_span(source, None));
+        assert_eq!(
+            at_end.render("x", source, false),
+            "warning: Unexpected end of input
+ --> x:3:1
+  |
+3 |
+  | ^"
+        );
+
+        let noted = Diagnostic::error("Type mismatch: int + bool", 4..12)
+            .with_note("in 'f', called at example.monke:1:1")
+            .with_note("in an anonymous function, called at example.monke:1:1");
+        assert_eq!(
+            noted.render("example.monke", "x = 5 + true", false),
+            "error: Type mismatch: int + bool
+ --> example.monke:1:5
+  |
+1 | x = 5 + true
+  |     ^^^^^^^^
+  = note: in 'f', called at example.monke:1:1
+  = note: in an anonymous function, called at example.monke:1:1"
+        );
+
+        let colored = diagnostic.render("example.monke", source, true);
+        assert!(colored.contains("\x1b[1;31merror\x1b[0m"));
+        assert!(colored.contains("\x1b[1;35mlet\x1b[0m"));
+    }
+}
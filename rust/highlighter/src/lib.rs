@@ -0,0 +1,256 @@
+//! Syntax highlighting for Monkey source, as ANSI-colored terminal text or
+//! as HTML with one CSS class per token class.
+
+pub mod diagnostic;
+
+use std::ops::Range;
+
+use lexer::{
+    tokens::{Token, TokenKind},
+    Lexer,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Class {
+    Keyword,
+    Identifier,
+    Number,
+    String,
+    Operator,
+    Punctuation,
+    Comment,
+    Illegal,
+}
+
+impl Class {
+    /// The CSS class used in HTML output.
+    pub fn name(self) -> &'static str {
+        match self {
+            Class::Keyword => "keyword",
+            Class::Identifier => "identifier",
+            Class::Number => "number",
+            Class::String => "string",
+            Class::Operator => "operator",
+            Class::Punctuation => "punctuation",
+            Class::Comment => "comment",
+            Class::Illegal => "illegal",
+        }
+    }
+
+    /// The SGR parameters used in ANSI output; identifiers and punctuation
+    /// keep the terminal's default color.
+    fn ansi(self) -> Option<&'static str> {
+        match self {
+            Class::Keyword => Some("1;35"),
+            Class::Identifier | Class::Punctuation => None,
+            Class::Number => Some("33"),
+            Class::String => Some("32"),
+            Class::Operator => Some("36"),
+            Class::Comment => Some("90"),
+            Class::Illegal => Some("1;4;31"),
+        }
+    }
+}
+
+pub fn classify(token: &Token) -> Option<Class> {
+    let class = match token.kind() {
+        TokenKind::EOF => return None,
+        TokenKind::Illegal => Class::Illegal,
+        TokenKind::Comment => Class::Comment,
+        TokenKind::Ident => Class::Identifier,
+        TokenKind::Int => Class::Number,
+        TokenKind::String => Class::String,
+        TokenKind::Assign
+        | TokenKind::Plus
+        | TokenKind::Minus
+        | TokenKind::Multiply
+        | TokenKind::Divide
+        | TokenKind::Negation
+        | TokenKind::Equals
+        | TokenKind::NotEquals
+        | TokenKind::LessThan
+        | TokenKind::GreaterThan
+        | TokenKind::DotDot
+        | TokenKind::DotDotEquals => Class::Operator,
+        TokenKind::Comma
+        | TokenKind::Semicolon
+        | TokenKind::Colon
+        | TokenKind::Arrow
+        | TokenKind::LeftParen
+        | TokenKind::RightParen
+        | TokenKind::LeftBrace
+        | TokenKind::RightBrace
+        | TokenKind::LeftBracket
+        | TokenKind::RightBracket => Class::Punctuation,
+        TokenKind::Function
+        | TokenKind::Let
+        | TokenKind::True
+        | TokenKind::False
+        | TokenKind::If
+        | TokenKind::Else
+        | TokenKind::Return
+        | TokenKind::Throw
+        | TokenKind::Try
+        | TokenKind::Catch
+        | TokenKind::While
+        | TokenKind::Break
+        | TokenKind::Continue
+        | TokenKind::For
+        | TokenKind::In => Class::Keyword,
+    };
+    Some(class)
+}
+
+/// Splits `source` into consecutive spans covering all of it; whitespace
+/// spans have no class.
+pub fn highlight(source: &str) -> Vec<(Range<usize>, Option<Class>)> {
+    let mut spans = Vec::new();
+    let mut end = 0;
+    let mut lex = Lexer::with_comments(source);
+    loop {
+        let token = lex.next_token();
+        let Some(start) = token.location() else {
+            break;
+        };
+        if start > end {
+            spans.push((end..start, None));
+        }
+        end = match &token {
+            // comments exclude a trailing `\r`
+            Token::Comment { raw, .. } => start + raw.len(),
+            _ => lex.position(),
+        };
+        spans.push((start..end, classify(&token)));
+    }
+    if end < source.len() {
+        spans.push((end..source.len(), None));
+    }
+    spans
+}
+
+/// `source` with ANSI color escapes around every colored token.
+pub fn ansi(source: &str) -> String {
+    let mut out = String::with_capacity(source.len() * 2);
+    for (range, class) in highlight(source) {
+        let text = &source[range];
+        match class.and_then(Class::ansi) {
+            Some(sgr) => {
+                out.push_str("\x1b[");
+                out.push_str(sgr);
+                out.push('m');
+                out.push_str(text);
+                out.push_str("\x1b[0m");
+            }
+            None => out.push_str(text),
+        }
+    }
+    out
+}
+
+fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// `source` as a `<pre class="monkey">` element with every token in a
+/// `<span>` carrying its class name.
+pub fn html(source: &str) -> String {
+    let mut out = String::from("<pre class=\"monkey\"><code>");
+    for (range, class) in highlight(source) {
+        let text = &source[range];
+        match class {
+            Some(class) => {
+                out.push_str("<span class=\"");
+                out.push_str(class.name());
+                out.push_str("\">");
+                escape_html(text, &mut out);
+                out.push_str("</span>");
+            }
+            None => escape_html(text, &mut out),
+        }
+    }
+    out.push_str("</code></pre>");
+    out
+}
+
+/// The default stylesheet for `html` output.
+pub const CSS: &str = "pre.monkey { background: #fafafa; padding: 1em; }
+pre.monkey .keyword { color: #a626a4; font-weight: bold; }
+pre.monkey .number { color: #986801; }
+pre.monkey .string { color: #50a14f; }
+pre.monkey .operator { color: #0184bc; }
+pre.monkey .comment { color: #a0a1a7; font-style: italic; }
+pre.monkey .illegal { color: #e45649; text-decoration: underline wavy; }
+";
+
+/// A standalone HTML page showing `source` with the default stylesheet.
+pub fn html_page(source: &str, title: &str) -> String {
+    let mut escaped_title = String::new();
+    escape_html(title, &mut escaped_title);
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n{}\n</body>\n</html>\n",
+        escaped_title,
+        CSS,
+        html(source)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight_covers_source() {
+        let source = "let x = 5; // five\r\nif (x != $) { [x, \"s\"] }";
+        let spans = highlight(source);
+        let text: String = spans.iter().map(|(r, _)| &source[r.clone()]).collect();
+        assert_eq!(text, source);
+
+        let classes: Vec<(&str, Class)> = spans
+            .iter()
+            .filter_map(|(r, c)| c.map(|c| (&source[r.clone()], c)))
+            .collect();
+        assert_eq!(
+            &classes[..6],
+            &[
+                ("let", Class::Keyword),
+                ("x", Class::Identifier),
+                ("=", Class::Operator),
+                ("5", Class::Number),
+                (";", Class::Punctuation),
+                ("// five", Class::Comment),
+            ]
+        );
+        assert!(classes.contains(&("$", Class::Illegal)));
+        assert!(classes.contains(&("!=", Class::Operator)));
+        assert!(classes.contains(&("\"s\"", Class::String)));
+        assert!(classes.contains(&("[", Class::Punctuation)));
+    }
+
+    #[test]
+    fn test_ansi() {
+        assert_eq!(
+            ansi("let x = 5;"),
+            "\x1b[1;35mlet\x1b[0m x \x1b[36m=\x1b[0m \x1b[33m5\x1b[0m;"
+        );
+    }
+
+    #[test]
+    fn test_html() {
+        assert_eq!(
+            html("a < 1"),
+            "<pre class=\"monkey\"><code><span class=\"identifier\">a</span> <span class=\"operator\">&lt;</span> <span class=\"number\">1</span></code></pre>"
+        );
+        let page = html_page("x", "<docs>");
+        assert!(page.starts_with("<!DOCTYPE html>"));
+        assert!(page.contains("<title>&lt;docs&gt;</title>"));
+        assert!(page.contains(CSS));
+    }
+}
@@ -0,0 +1,14 @@
+//! Lexing throughput and allocations per token.
+//!
+//! Run with `cargo bench -p lexer`. Tokens borrow their text and
+//! identifiers are interned, so lexing only allocates the first time a name
+//! is seen.
+
+use std::hint::black_box;
+
+use lexer::Lexer;
+
+fn main() {
+    let source = benchmark::source();
+    benchmark::measure("lex", || Lexer::new(&source).map(black_box).count());
+}
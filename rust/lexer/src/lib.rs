@@ -1,5 +1,9 @@
+pub mod lines;
+pub mod stream;
+pub mod symbol;
 pub mod tokens;
 use std::str;
+use symbol::Symbol;
 use tokens::{lookup_ident, Token};
 
 pub struct Lexer<'a> {
@@ -7,6 +11,8 @@ pub struct Lexer<'a> {
     position: usize,
     read_position: usize,
     ch: u8,
+    emit_comments: bool,
+    finished: bool,
 }
 
 impl<'a> Lexer<'a> {
@@ -16,11 +22,35 @@ impl<'a> Lexer<'a> {
             position: 0,
             read_position: 0,
             ch: 0,
+            emit_comments: false,
+            finished: false,
         };
         result.read_char();
         result
     }
 
+    /// Like `new`, but `//` comments are returned as `Token::Comment` instead
+    /// of being skipped. Used by tooling that must preserve them.
+    pub fn with_comments(input: &'a str) -> Self {
+        let mut result = Self::new(input);
+        result.emit_comments = true;
+        result
+    }
+
+    /// Byte offset of the current character. Right after `next_token` this
+    /// is the end of the token that was just returned.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Continues lexing from byte offset `position`, which must be on a
+    /// character boundary. Locations stay relative to the whole input.
+    pub fn seek(&mut self, position: usize) {
+        self.read_position = position;
+        self.finished = false;
+        self.read_char();
+    }
+
     pub fn read_char(&mut self) {
         if self.read_position >= self.input.len() {
             self.ch = 0;
@@ -39,8 +69,15 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    pub fn next_token(&mut self) -> Token {
+    pub fn next_token(&mut self) -> Token<'a> {
         self.skip_whitespace();
+        while self.ch == b'/' && self.peek_char() == b'/' {
+            let comment = self.read_comment();
+            if self.emit_comments {
+                return comment;
+            }
+            self.skip_whitespace();
+        }
         let mut skip_read = false;
         let token = match self.ch {
             b'=' => {
@@ -68,12 +105,25 @@ impl<'a> Lexer<'a> {
             b',' => Token::Comma {
                 location: self.position,
             },
-            b'+' => Token::Plus {
+            b':' => Token::Colon {
                 location: self.position,
             },
-            b'-' => Token::Minus {
+            b'+' => Token::Plus {
                 location: self.position,
             },
+            b'-' => {
+                if self.peek_char() == b'>' {
+                    let result = Token::Arrow {
+                        location: self.position,
+                    };
+                    self.read_char();
+                    result
+                } else {
+                    Token::Minus {
+                        location: self.position,
+                    }
+                }
+            }
             b'*' => Token::Multiply {
                 location: self.position,
             },
@@ -93,6 +143,20 @@ impl<'a> Lexer<'a> {
                     }
                 }
             }
+            b'.' => {
+                if self.peek_char() == b'.' {
+                    let location = self.position;
+                    self.read_char();
+                    if self.peek_char() == b'=' {
+                        self.read_char();
+                        Token::DotDotEquals { location }
+                    } else {
+                        Token::DotDot { location }
+                    }
+                } else {
+                    self.read_illegal()
+                }
+            }
             b'<' => Token::LessThan {
                 location: self.position,
             },
@@ -105,23 +169,28 @@ impl<'a> Lexer<'a> {
             b'}' => Token::RightBrace {
                 location: self.position,
             },
+            b'[' => Token::LeftBracket {
+                location: self.position,
+            },
+            b']' => Token::RightBracket {
+                location: self.position,
+            },
+            b'"' => {
+                let token = self.read_string();
+                // an unterminated string already ends at the end of input
+                skip_read = self.ch == 0;
+                token
+            }
             0 => Token::EOF,
             a => {
                 if a.is_ascii_alphabetic() || a == b'_' {
                     skip_read = true;
-                    let ident = self.read_identifier();
-                    match lookup_ident(&ident) {
-                        Some(t) => t,
-                        None => ident,
-                    }
+                    self.read_identifier()
                 } else if a.is_ascii_digit() {
                     skip_read = true;
                     self.read_number()
                 } else {
-                    Token::Illegal {
-                        location: self.position,
-                        raw: a.to_string(),
-                    }
+                    self.read_illegal()
                 }
             }
         };
@@ -132,25 +201,90 @@ impl<'a> Lexer<'a> {
         token
     }
 
-    fn read_identifier(&mut self) -> Token {
+    fn read_identifier(&mut self) -> Token<'a> {
         let position = self.position;
         while self.ch.is_ascii_alphanumeric() || self.ch == b'_' {
             self.read_char();
         }
-        Token::Ident {
+        let ident = &self.input[position..self.position];
+        lookup_ident(ident, position).unwrap_or_else(|| Token::Ident {
             location: position,
-            raw: self.input[position..self.position].to_owned(),
+            raw: Symbol::intern(ident),
+        })
+    }
+
+    fn read_illegal(&mut self) -> Token<'a> {
+        let position = self.position;
+        // consume the whole character so multi-byte input is reported once
+        let len = self.input[position..]
+            .chars()
+            .next()
+            .map_or(1, char::len_utf8);
+        for _ in 1..len {
+            self.read_char();
+        }
+        Token::Illegal {
+            location: position,
+            raw: self.input[position..position + len].into(),
         }
     }
 
-    fn read_number(&mut self) -> Token {
+    fn read_comment(&mut self) -> Token<'a> {
         let position = self.position;
-        while self.ch.is_ascii_digit() {
+        while self.ch != b'\n' && self.ch != 0 {
             self.read_char();
         }
-        Token::Int {
+        Token::Comment {
             location: position,
-            value: self.input[position..self.position].parse::<i64>().unwrap(),
+            raw: self.input[position..self.position].trim_end_matches('\r').into(),
+        }
+    }
+
+    /// Reads up to the closing quote, leaving it as the current character.
+    /// Without one the literal runs to the end of input and is illegal.
+    fn read_string(&mut self) -> Token<'a> {
+        let position = self.position;
+        self.read_char();
+        while self.ch != b'"' && self.ch != 0 {
+            if self.ch == b'\\' {
+                self.read_char();
+                if self.ch == 0 {
+                    break;
+                }
+            }
+            self.read_char();
+        }
+        if self.ch == 0 {
+            return Token::Illegal {
+                location: position,
+                raw: self.input[position..].into(),
+            };
+        }
+        Token::String {
+            location: position,
+            raw: self.input[position + 1..self.position].into(),
+        }
+    }
+
+    /// An `Int`, or `Illegal` if the number does not fit in one.
+    fn read_number(&mut self) -> Token<'a> {
+        let position = self.position;
+        while self.ch.is_ascii_digit() {
+            self.read_char();
+        }
+        let raw = &self.input[position..self.position];
+        // digits too large for an int stay one illegal token, which the
+        // parser reports as such
+        match raw.parse::<i64>() {
+            Ok(value) => Token::Int {
+                location: position,
+                raw: raw.into(),
+                value,
+            },
+            Err(_) => Token::Illegal {
+                location: position,
+                raw: raw.into(),
+            },
         }
     }
 
@@ -161,6 +295,22 @@ impl<'a> Lexer<'a> {
     }
 }
 
+/// Yields every token up to and including `Token::EOF`, then stops.
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        if self.finished {
+            return None;
+        }
+        let token = self.next_token();
+        if token == Token::EOF {
+            self.finished = true;
+        }
+        Some(token)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -194,52 +344,54 @@ mod tests {
             Token::Let { location: 0 },
             Token::Ident {
                 location: 4,
-                raw: String::from("five"),
+                raw: "five".into(),
             },
             Token::Assign { location: 9 },
             Token::Int {
                 location: 11,
+                raw: "5".into(),
                 value: 5,
             },
             Token::Semicolon { location: 12 },
             Token::Let { location: 14 },
             Token::Ident {
                 location: 18,
-                raw: String::from("ten"),
+                raw: "ten".into(),
             },
             Token::Assign { location: 22 },
             Token::Int {
                 location: 24,
+                raw: "10".into(),
                 value: 10,
             },
             Token::Semicolon { location: 26 },
             Token::Let { location: 29 },
             Token::Ident {
                 location: 33,
-                raw: String::from("add"),
+                raw: "add".into(),
             },
             Token::Assign { location: 37 },
             Token::Function { location: 39 },
             Token::LeftParen { location: 41 },
             Token::Ident {
                 location: 42,
-                raw: String::from("x"),
+                raw: "x".into(),
             },
             Token::Comma { location: 43 },
             Token::Ident {
                 location: 45,
-                raw: String::from("y"),
+                raw: "y".into(),
             },
             Token::RightParen { location: 46 },
             Token::LeftBrace { location: 48 },
             Token::Ident {
                 location: 52,
-                raw: String::from("x"),
+                raw: "x".into(),
             },
             Token::Plus { location: 54 },
             Token::Ident {
                 location: 56,
-                raw: String::from("y"),
+                raw: "y".into(),
             },
             Token::Semicolon { location: 57 },
             Token::RightBrace { location: 59 },
@@ -247,22 +399,22 @@ mod tests {
             Token::Let { location: 63 },
             Token::Ident {
                 location: 67,
-                raw: String::from("result"),
+                raw: "result".into(),
             },
             Token::Assign { location: 74 },
             Token::Ident {
                 location: 76,
-                raw: String::from("add"),
+                raw: "add".into(),
             },
             Token::LeftParen { location: 79 },
             Token::Ident {
                 location: 80,
-                raw: String::from("five"),
+                raw: "five".into(),
             },
             Token::Comma { location: 84 },
             Token::Ident {
                 location: 86,
-                raw: String::from("ten"),
+                raw: "ten".into(),
             },
             Token::RightParen { location: 89 },
             Token::Semicolon { location: 90 },
@@ -272,21 +424,25 @@ mod tests {
             Token::Multiply { location: 95 },
             Token::Int {
                 location: 96,
+                raw: "5".into(),
                 value: 5,
             },
             Token::Semicolon { location: 97 },
             Token::Int {
                 location: 99,
+                raw: "5".into(),
                 value: 5,
             },
             Token::LessThan { location: 101 },
             Token::Int {
                 location: 103,
+                raw: "10".into(),
                 value: 10,
             },
             Token::GreaterThan { location: 106 },
             Token::Int {
                 location: 108,
+                raw: "5".into(),
                 value: 5,
             },
             Token::Semicolon { location: 109 },
@@ -294,11 +450,13 @@ mod tests {
             Token::LeftParen { location: 115 },
             Token::Int {
                 location: 116,
+                raw: "5".into(),
                 value: 5,
             },
             Token::LessThan { location: 118 },
             Token::Int {
                 location: 120,
+                raw: "10".into(),
                 value: 10,
             },
             Token::RightParen { location: 122 },
@@ -315,21 +473,25 @@ mod tests {
             Token::RightBrace { location: 166 },
             Token::Int {
                 location: 169,
+                raw: "10".into(),
                 value: 10,
             },
             Token::Equals { location: 172 },
             Token::Int {
                 location: 175,
+                raw: "10".into(),
                 value: 10,
             },
             Token::Semicolon { location: 177 },
             Token::Int {
                 location: 179,
+                raw: "10".into(),
                 value: 10,
             },
             Token::NotEquals { location: 182 },
             Token::Int {
                 location: 185,
+                raw: "9".into(),
                 value: 9,
             },
             Token::Semicolon { location: 186 },
@@ -379,11 +541,13 @@ return false;
             Token::LeftParen { location: 3 },
             Token::Int {
                 location: 4,
+                raw: "5".into(),
                 value: 5,
             },
             Token::LessThan { location: 6 },
             Token::Int {
                 location: 8,
+                raw: "10".into(),
                 value: 10,
             },
             Token::RightParen { location: 10 },
@@ -408,25 +572,140 @@ return false;
         }
     }
 
+    #[test]
+    fn test_exception_keywords() {
+        let input = "try { throw e; } catch (e) {}";
+        let tests: Vec<Token> = vec![
+            Token::Try { location: 0 },
+            Token::LeftBrace { location: 4 },
+            Token::Throw { location: 6 },
+            Token::Ident {
+                location: 12,
+                raw: Symbol::intern("e"),
+            },
+            Token::Semicolon { location: 13 },
+            Token::RightBrace { location: 15 },
+            Token::Catch { location: 17 },
+            Token::LeftParen { location: 23 },
+            Token::Ident {
+                location: 24,
+                raw: Symbol::intern("e"),
+            },
+            Token::RightParen { location: 25 },
+            Token::LeftBrace { location: 27 },
+            Token::RightBrace { location: 28 },
+            Token::EOF,
+        ];
+
+        let mut tokenizer = Lexer::new(input);
+
+        for (i, tt) in tests.iter().enumerate() {
+            let tok = tokenizer.next_token();
+            assert_eq!(&tok, tt, "test {} failed", i);
+        }
+    }
+
+    #[test]
+    fn test_loop_keywords() {
+        let input = "while (x) { break; continue; }";
+        let tests: Vec<Token> = vec![
+            Token::While { location: 0 },
+            Token::LeftParen { location: 6 },
+            Token::Ident {
+                location: 7,
+                raw: Symbol::intern("x"),
+            },
+            Token::RightParen { location: 8 },
+            Token::LeftBrace { location: 10 },
+            Token::Break { location: 12 },
+            Token::Semicolon { location: 17 },
+            Token::Continue { location: 19 },
+            Token::Semicolon { location: 27 },
+            Token::RightBrace { location: 29 },
+            Token::EOF,
+        ];
+
+        let mut tokenizer = Lexer::new(input);
+
+        for (i, tt) in tests.iter().enumerate() {
+            let tok = tokenizer.next_token();
+            assert_eq!(&tok, tt, "test {} failed", i);
+        }
+    }
+
+    #[test]
+    fn test_for_and_ranges() {
+        let input = "for (i in 0..n) {} 1..=2 . .";
+        let tests: Vec<Token> = vec![
+            Token::For { location: 0 },
+            Token::LeftParen { location: 4 },
+            Token::Ident {
+                location: 5,
+                raw: Symbol::intern("i"),
+            },
+            Token::In { location: 7 },
+            Token::Int {
+                location: 10,
+                raw: "0".into(),
+                value: 0,
+            },
+            Token::DotDot { location: 11 },
+            Token::Ident {
+                location: 13,
+                raw: Symbol::intern("n"),
+            },
+            Token::RightParen { location: 14 },
+            Token::LeftBrace { location: 16 },
+            Token::RightBrace { location: 17 },
+            Token::Int {
+                location: 19,
+                raw: "1".into(),
+                value: 1,
+            },
+            Token::DotDotEquals { location: 20 },
+            Token::Int {
+                location: 23,
+                raw: "2".into(),
+                value: 2,
+            },
+            Token::Illegal {
+                location: 25,
+                raw: ".".into(),
+            },
+            Token::Illegal {
+                location: 27,
+                raw: ".".into(),
+            },
+            Token::EOF,
+        ];
+
+        let mut tokenizer = Lexer::new(input);
+
+        for (i, tt) in tests.iter().enumerate() {
+            let tok = tokenizer.next_token();
+            assert_eq!(&tok, tt, "test {} failed", i);
+        }
+    }
+
     #[test]
     fn test_identifiers() {
         let input = "add10 apple_bottom jeans_3_boots _ignored";
         let tests: Vec<Token> = vec![
             Token::Ident {
                 location: 0,
-                raw: String::from("add10"),
+                raw: "add10".into(),
             },
             Token::Ident {
                 location: 6,
-                raw: String::from("apple_bottom"),
+                raw: "apple_bottom".into(),
             },
             Token::Ident {
                 location: 19,
-                raw: String::from("jeans_3_boots"),
+                raw: "jeans_3_boots".into(),
             },
             Token::Ident {
                 location: 33,
-                raw: String::from("_ignored"),
+                raw: "_ignored".into(),
             },
         ];
         let mut tokenizer = Lexer::new(input);
@@ -436,4 +715,213 @@ return false;
             assert_eq!(&tok, tt, "test {} failed", i);
         }
     }
+
+    #[test]
+    fn test_comments() {
+        let input = "let x = 1; // one\n// whole line\r\nx / 2";
+        let expected: Vec<Token> = vec![
+            Token::Let { location: 0 },
+            Token::Ident {
+                location: 4,
+                raw: "x".into(),
+            },
+            Token::Assign { location: 6 },
+            Token::Int {
+                location: 8,
+                raw: "1".into(),
+                value: 1,
+            },
+            Token::Semicolon { location: 9 },
+            Token::Comment {
+                location: 11,
+                raw: "// one".into(),
+            },
+            Token::Comment {
+                location: 18,
+                raw: "// whole line".into(),
+            },
+            Token::Ident {
+                location: 33,
+                raw: "x".into(),
+            },
+            Token::Divide { location: 35 },
+            Token::Int {
+                location: 37,
+                raw: "2".into(),
+                value: 2,
+            },
+            Token::EOF,
+        ];
+
+        let mut tokenizer = Lexer::with_comments(input);
+        for (i, tt) in expected.iter().enumerate() {
+            let tok = tokenizer.next_token();
+            assert_eq!(&tok, tt, "test {} failed", i);
+        }
+
+        let mut tokenizer = Lexer::new(input);
+        let skipped: Vec<Token> = expected
+            .into_iter()
+            .filter(|t| !matches!(t, Token::Comment { .. }))
+            .collect();
+        for (i, tt) in skipped.iter().enumerate() {
+            let tok = tokenizer.next_token();
+            assert_eq!(&tok, tt, "test {} failed", i);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_token_json() {
+        let tokens = vec![
+            Token::Ident {
+                location: 0,
+                raw: "five".into(),
+            },
+            Token::Int {
+                location: 5,
+                raw: "5".into(),
+                value: 5,
+            },
+            Token::EOF,
+        ];
+        let json = serde_json::to_string(&tokens).expect("Failed to serialize");
+        assert_eq!(
+            json,
+            r#"[{"kind":"Ident","location":0,"raw":"five"},{"kind":"Int","location":5,"raw":"5","value":5},{"kind":"EOF"}]"#
+        );
+        let decoded: Vec<Token> = serde_json::from_str(&json).expect("Failed to deserialize");
+        assert_eq!(decoded, tokens);
+
+        // JSON escapes can't be borrowed from the input, so are decoded into
+        // an owned payload
+        let source = r#""say \"hi\"" // done"#;
+        let tokens: Vec<Token> = Lexer::with_comments(source).collect();
+        let json = serde_json::to_string(&tokens).expect("Failed to serialize");
+        let decoded: Vec<Token> = serde_json::from_str(&json).expect("Failed to deserialize");
+        assert_eq!(decoded, tokens);
+    }
+
+    #[test]
+    fn test_iterator_ends_after_eof() {
+        let tokens: Vec<Token> = Lexer::new("let x").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Let { location: 0 },
+                Token::Ident {
+                    location: 4,
+                    raw: "x".into(),
+                },
+                Token::EOF,
+            ]
+        );
+
+        let mut lexer = Lexer::new("");
+        assert_eq!(lexer.next(), Some(Token::EOF));
+        assert_eq!(lexer.next(), None);
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn test_illegal_multibyte() {
+        let tokens: Vec<Token> = Lexer::new("é+").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Illegal {
+                    location: 0,
+                    raw: "é".into(),
+                },
+                Token::Plus { location: 2 },
+                Token::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_int_overflow() {
+        let tokens: Vec<Token> = Lexer::new("9223372036854775807 99999999999999999999").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Int {
+                    location: 0,
+                    raw: "9223372036854775807".into(),
+                    value: i64::MAX,
+                },
+                Token::Illegal {
+                    location: 20,
+                    raw: "99999999999999999999".into(),
+                },
+                Token::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strings_and_brackets() {
+        let input = r#"["a\"b", "é"] "open"#;
+        let tokens: Vec<Token> = Lexer::new(input).collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::LeftBracket { location: 0 },
+                Token::String {
+                    location: 1,
+                    raw: r#"a\"b"#.into(),
+                },
+                Token::Comma { location: 7 },
+                Token::String {
+                    location: 9,
+                    raw: "é".into(),
+                },
+                Token::RightBracket { location: 13 },
+                Token::Illegal {
+                    location: 15,
+                    raw: "\"open".into(),
+                },
+                Token::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_annotations() {
+        let tokens: Vec<Token> = Lexer::new("fn(a: int) -> int a->b - >").collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Function { location: 0 },
+                Token::LeftParen { location: 2 },
+                Token::Ident {
+                    location: 3,
+                    raw: "a".into(),
+                },
+                Token::Colon { location: 4 },
+                Token::Ident {
+                    location: 6,
+                    raw: "int".into(),
+                },
+                Token::RightParen { location: 9 },
+                Token::Arrow { location: 11 },
+                Token::Ident {
+                    location: 14,
+                    raw: "int".into(),
+                },
+                Token::Ident {
+                    location: 18,
+                    raw: "a".into(),
+                },
+                Token::Arrow { location: 19 },
+                Token::Ident {
+                    location: 21,
+                    raw: "b".into(),
+                },
+                Token::Minus { location: 23 },
+                Token::GreaterThan { location: 25 },
+                Token::EOF,
+            ]
+        );
+    }
 }
@@ -0,0 +1,89 @@
+/// Converts between byte offsets into a text, as stored in token locations,
+/// and the lines and columns editors and messages show.
+pub struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(text: &'a str) -> Self {
+        let mut line_starts = vec![0];
+        for (i, b) in text.bytes().enumerate() {
+            if b == b'\n' {
+                line_starts.push(i + 1);
+            }
+        }
+        LineIndex { text, line_starts }
+    }
+
+    /// Line and column of `offset`, both counted from 1; columns count
+    /// characters. Offsets past the end are clamped to it.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.text.len());
+        let line = self.line(offset);
+        let column = self.text[self.line_starts[line]..offset].chars().count();
+        (line + 1, column + 1)
+    }
+
+    /// Zero-based line and UTF-16 character of `offset`, as the language
+    /// server protocol counts them.
+    pub fn position(&self, offset: usize) -> (u32, u32) {
+        let offset = offset.min(self.text.len());
+        let line = self.line(offset);
+        let character = self.text[self.line_starts[line]..offset]
+            .chars()
+            .map(char::len_utf16)
+            .sum::<usize>();
+        (line as u32, character as u32)
+    }
+
+    /// The byte offset of a line and UTF-16 character, clamped to the line.
+    pub fn offset(&self, line: u32, character: u32) -> usize {
+        let Some(&start) = self.line_starts.get(line as usize) else {
+            return self.text.len();
+        };
+        let mut units = 0;
+        for (i, c) in self.text[start..].char_indices() {
+            if units >= character as usize || c == '\n' {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        self.text.len()
+    }
+
+    fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|start| *start <= offset) - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_col() {
+        let index = LineIndex::new("let a = 1;\nlet b = 2;\n");
+        assert_eq!(index.line_col(0), (1, 1));
+        assert_eq!(index.line_col(4), (1, 5));
+        assert_eq!(index.line_col(11), (2, 1));
+        assert_eq!(index.line_col(15), (2, 5));
+        assert_eq!(index.line_col(22), (3, 1));
+
+        let index = LineIndex::new("ab\néx = 1");
+        assert_eq!(index.line_col(5), (2, 2));
+        assert_eq!(index.line_col(100), (2, 7));
+    }
+
+    #[test]
+    fn test_position() {
+        let index = LineIndex::new("ab\né𝄞x\n");
+        assert_eq!(index.position(0), (0, 0));
+        assert_eq!(index.position(3), (1, 0));
+        // é is one UTF-16 unit and two bytes, 𝄞 two units and four bytes
+        assert_eq!(index.position(9), (1, 3));
+        assert_eq!(index.offset(1, 3), 9);
+        assert_eq!(index.offset(0, 10), 2);
+        assert_eq!(index.offset(5, 0), 11);
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::VecDeque;
+
+use crate::{tokens::Token, Lexer};
+
+/// A token source with unbounded lookahead. Tokens are lexed lazily as far
+/// ahead as `peek_nth` asks for; past the end of input the stream keeps
+/// reporting `Token::EOF`.
+pub struct TokenStream<'a> {
+    lexer: Lexer<'a>,
+    lookahead: VecDeque<Token<'a>>,
+    finished: bool,
+}
+
+impl<'a> TokenStream<'a> {
+    pub fn new(lexer: Lexer<'a>) -> Self {
+        TokenStream {
+            lexer,
+            lookahead: VecDeque::new(),
+            finished: false,
+        }
+    }
+
+    /// The token `next_token` would return, without consuming it.
+    pub fn peek(&mut self) -> &Token<'a> {
+        self.peek_nth(0)
+    }
+
+    /// The token `k` positions ahead; `peek_nth(0)` is the next token.
+    pub fn peek_nth(&mut self, k: usize) -> &Token<'a> {
+        while self.lookahead.len() <= k {
+            let token = self.lexer.next_token();
+            self.lookahead.push_back(token);
+        }
+        &self.lookahead[k]
+    }
+
+    pub fn next_token(&mut self) -> Token<'a> {
+        match self.lookahead.pop_front() {
+            Some(token) => token,
+            None => self.lexer.next_token(),
+        }
+    }
+}
+
+/// Like the `Lexer` iterator, ends after yielding `Token::EOF`.
+impl<'a> Iterator for TokenStream<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        if self.finished {
+            return None;
+        }
+        let token = self.next_token();
+        if token == Token::EOF {
+            self.finished = true;
+        }
+        Some(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peek_nth() {
+        let mut stream = TokenStream::new(Lexer::new("let x = 5;"));
+
+        assert_eq!(stream.peek_nth(2), &Token::Assign { location: 6 });
+        assert_eq!(stream.peek(), &Token::Let { location: 0 });
+        assert_eq!(stream.peek_nth(10), &Token::EOF);
+
+        assert_eq!(stream.next_token(), Token::Let { location: 0 });
+        assert_eq!(
+            stream.peek(),
+            &Token::Ident {
+                location: 4,
+                raw: "x".into(),
+            }
+        );
+        assert_eq!(stream.peek_nth(3), &Token::Semicolon { location: 9 });
+
+        let rest: Vec<Token> = stream.collect();
+        assert_eq!(
+            rest,
+            vec![
+                Token::Ident {
+                    location: 4,
+                    raw: "x".into(),
+                },
+                Token::Assign { location: 6 },
+                Token::Int {
+                    location: 8,
+                    raw: "5".into(),
+                    value: 5,
+                },
+                Token::Semicolon { location: 9 },
+                Token::EOF,
+            ]
+        );
+    }
+}
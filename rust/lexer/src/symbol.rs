@@ -0,0 +1,140 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+/// An interned identifier. Equal names always intern to the same symbol, so
+/// symbols can be compared and hashed as integers and resolved back to their
+/// text with `as_str`.
+///
+/// The interner is global and never frees its strings: every distinct name
+/// interned by a process is stored once and lives until it exits. Code that
+/// only looks names up, such as for a name given by the host, uses
+/// `lookup` so unknown names are not added. Each thread keeps its own copy
+/// of the names it has resolved, so `as_str` only takes the interner's lock
+/// for symbols interned since the thread last did.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+struct Interner {
+    symbols: HashMap<&'static str, Symbol>,
+    names: Vec<&'static str>,
+}
+
+fn interner() -> MutexGuard<'static, Interner> {
+    static INTERNER: OnceLock<Mutex<Interner>> = OnceLock::new();
+    INTERNER
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+thread_local! {
+    /// The start of the interner's `names`, copied the last time this
+    /// thread resolved a symbol it had not seen.
+    static NAMES: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Symbol {
+        let mut interner = interner();
+        if let Some(symbol) = interner.symbols.get(name) {
+            return *symbol;
+        }
+        let name: &'static str = Box::leak(name.into());
+        let symbol = Symbol(interner.names.len() as u32);
+        interner.names.push(name);
+        interner.symbols.insert(name, symbol);
+        symbol
+    }
+
+    /// The symbol `name` was interned as, or `None` if it never was. Unlike
+    /// `intern` this does not store `name`.
+    pub fn lookup(name: &str) -> Option<Symbol> {
+        interner().symbols.get(name).copied()
+    }
+
+    pub fn as_str(self) -> &'static str {
+        let index = self.0 as usize;
+        NAMES.with(|names| {
+            let mut names = names.borrow_mut();
+            if index >= names.len() {
+                let len = names.len();
+                names.extend_from_slice(&interner().names[len..]);
+            }
+            names[index]
+        })
+    }
+
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+/// Symbols serialize as their text, so the JSON format does not depend on
+/// the order names were interned in.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Symbol {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        Ok(Symbol::intern(&name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interning() {
+        let a = Symbol::intern("counter");
+        let b = Symbol::intern(&String::from("counter"));
+        let c = Symbol::intern("count");
+        assert_eq!(a, b);
+        assert_eq!(a.as_u32(), b.as_u32());
+        assert_ne!(a, c);
+        assert_eq!(a.as_str(), "counter");
+        assert_eq!(c.to_string(), "count");
+        assert_eq!(format!("{:?}", a), "\"counter\"");
+
+        assert_eq!(Symbol::lookup("counter"), Some(a));
+        assert_eq!(Symbol::lookup("never interned"), None);
+        assert_eq!(Symbol::lookup("never interned"), None);
+        let d = std::thread::spawn(|| Symbol::intern("from another thread"))
+            .join()
+            .unwrap();
+        assert_eq!(d.as_str(), "from another thread");
+    }
+}
@@ -1,14 +1,53 @@
+use std::{borrow::Cow, fmt, ops::Range};
+
+use crate::symbol::Symbol;
+
+/// With the `serde` feature a token serializes as a JSON object tagged by
+/// its variant name, e.g. `{"kind":"Ident","location":4,"raw":"five"}` or
+/// `{"kind":"EOF"}`. `location` is the byte offset of the token's first
+/// character; the token covers the length of its source text from there.
+/// Text payloads borrow the source they were lexed from, or the JSON they
+/// were read from unless it spells them with escapes.
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub enum Token {
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "kind")
+)]
+pub enum Token<'a> {
     //SPECIAL
-    Illegal { location: usize, raw: String },
+    Illegal {
+        location: usize,
+        #[cfg_attr(feature = "serde", serde(borrow))]
+        raw: Cow<'a, str>,
+    },
 
     EOF,
 
+    Comment {
+        location: usize,
+        #[cfg_attr(feature = "serde", serde(borrow))]
+        raw: Cow<'a, str>,
+    },
+
     // VALUES
-    Ident { location: usize, raw: String },
+    Ident { location: usize, raw: Symbol },
+
+    /// `raw` is the digits as written, which may have leading zeros.
+    Int {
+        location: usize,
+        #[cfg_attr(feature = "serde", serde(borrow))]
+        raw: Cow<'a, str>,
+        value: i64,
+    },
 
-    Int { location: usize, value: i64 },
+    /// `raw` is the text between the quotes, escapes as written; see
+    /// `unescape` for the value.
+    String {
+        location: usize,
+        #[cfg_attr(feature = "serde", serde(borrow))]
+        raw: Cow<'a, str>,
+    },
 
     // OPERATORS
     Assign { location: usize },
@@ -31,11 +70,22 @@ pub enum Token {
 
     GreaterThan { location: usize },
 
+    /// `..`, between the bounds of a range that leaves out its end.
+    DotDot { location: usize },
+
+    /// `..=`, between the bounds of a range that includes its end.
+    DotDotEquals { location: usize },
+
     // SYNTAX
     Comma { location: usize },
 
     Semicolon { location: usize },
 
+    Colon { location: usize },
+
+    /// `->`, before the result type of a function.
+    Arrow { location: usize },
+
     LeftParen { location: usize },
 
     RightParen { location: usize },
@@ -44,6 +94,10 @@ pub enum Token {
 
     RightBrace { location: usize },
 
+    LeftBracket { location: usize },
+
+    RightBracket { location: usize },
+
     // KEYWORDS
     Function { location: usize },
 
@@ -58,34 +112,422 @@ pub enum Token {
     Else { location: usize },
 
     Return { location: usize },
+
+    Throw { location: usize },
+
+    Try { location: usize },
+
+    Catch { location: usize },
+
+    While { location: usize },
+
+    Break { location: usize },
+
+    Continue { location: usize },
+
+    For { location: usize },
+
+    In { location: usize },
 }
 
-pub fn lookup_ident(ident: &Token) -> Option<Token> {
+/// The kind of a `Token` without its location or payload, for matching and
+/// for tables keyed by kind.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum TokenKind {
+    Illegal,
+    EOF,
+    Comment,
+    Ident,
+    Int,
+    String,
+    Assign,
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+    Negation,
+    Equals,
+    NotEquals,
+    LessThan,
+    GreaterThan,
+    DotDot,
+    DotDotEquals,
+    Comma,
+    Semicolon,
+    Colon,
+    Arrow,
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+    LeftBracket,
+    RightBracket,
+    Function,
+    Let,
+    True,
+    False,
+    If,
+    Else,
+    Return,
+    Throw,
+    Try,
+    Catch,
+    While,
+    Break,
+    Continue,
+    For,
+    In,
+}
+
+impl TokenKind {
+    /// The exact source text of this kind, for kinds that always look the same.
+    pub fn literal(self) -> Option<&'static str> {
+        match self {
+            TokenKind::Illegal
+            | TokenKind::EOF
+            | TokenKind::Comment
+            | TokenKind::Ident
+            | TokenKind::Int
+            | TokenKind::String => None,
+            TokenKind::Assign => Some("="),
+            TokenKind::Plus => Some("+"),
+            TokenKind::Minus => Some("-"),
+            TokenKind::Multiply => Some("*"),
+            TokenKind::Divide => Some("/"),
+            TokenKind::Negation => Some("!"),
+            TokenKind::Equals => Some("=="),
+            TokenKind::NotEquals => Some("!="),
+            TokenKind::LessThan => Some("<"),
+            TokenKind::GreaterThan => Some(">"),
+            TokenKind::DotDot => Some(".."),
+            TokenKind::DotDotEquals => Some("..="),
+            TokenKind::Comma => Some(","),
+            TokenKind::Semicolon => Some(";"),
+            TokenKind::Colon => Some(":"),
+            TokenKind::Arrow => Some("->"),
+            TokenKind::LeftParen => Some("("),
+            TokenKind::RightParen => Some(")"),
+            TokenKind::LeftBrace => Some("{"),
+            TokenKind::RightBrace => Some("}"),
+            TokenKind::LeftBracket => Some("["),
+            TokenKind::RightBracket => Some("]"),
+            TokenKind::Function => Some("fn"),
+            TokenKind::Let => Some("let"),
+            TokenKind::True => Some("true"),
+            TokenKind::False => Some("false"),
+            TokenKind::If => Some("if"),
+            TokenKind::Else => Some("else"),
+            TokenKind::Return => Some("return"),
+            TokenKind::Throw => Some("throw"),
+            TokenKind::Try => Some("try"),
+            TokenKind::Catch => Some("catch"),
+            TokenKind::While => Some("while"),
+            TokenKind::Break => Some("break"),
+            TokenKind::Continue => Some("continue"),
+            TokenKind::For => Some("for"),
+            TokenKind::In => Some("in"),
+        }
+    }
+}
+
+/// The literal text (`==`, `fn`) or, for kinds carrying a payload, a name
+/// such as `identifier`.
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::Illegal => write!(f, "illegal character"),
+            TokenKind::EOF => write!(f, "end of input"),
+            TokenKind::Comment => write!(f, "comment"),
+            TokenKind::Ident => write!(f, "identifier"),
+            TokenKind::Int => write!(f, "integer"),
+            TokenKind::String => write!(f, "string"),
+            kind => write!(f, "{}", kind.literal().unwrap_or_default()),
+        }
+    }
+}
+
+/// The keyword token spelled `ident`, if it is one.
+pub fn lookup_ident<'a>(ident: &str, location: usize) -> Option<Token<'a>> {
     match ident {
-        Token::Ident { location, raw } => match raw.as_str() {
-            "fn" => Some(Token::Function {
-                location: *location,
-            }),
-            "let" => Some(Token::Let {
-                location: *location,
-            }),
-            "true" => Some(Token::True {
-                location: *location,
-            }),
-            "false" => Some(Token::False {
-                location: *location,
-            }),
-            "if" => Some(Token::If {
-                location: *location,
-            }),
-            "else" => Some(Token::Else {
-                location: *location,
-            }),
-            "return" => Some(Token::Return {
-                location: *location,
-            }),
-            _ => None,
-        },
+        "fn" => Some(Token::Function { location }),
+        "let" => Some(Token::Let { location }),
+        "true" => Some(Token::True { location }),
+        "false" => Some(Token::False { location }),
+        "if" => Some(Token::If { location }),
+        "else" => Some(Token::Else { location }),
+        "return" => Some(Token::Return { location }),
+        "throw" => Some(Token::Throw { location }),
+        "try" => Some(Token::Try { location }),
+        "catch" => Some(Token::Catch { location }),
+        "while" => Some(Token::While { location }),
+        "break" => Some(Token::Break { location }),
+        "continue" => Some(Token::Continue { location }),
+        "for" => Some(Token::For { location }),
+        "in" => Some(Token::In { location }),
         _ => None,
     }
 }
+
+/// The value of a string literal whose text between the quotes is `raw`:
+/// `\"`, `\\`, `\n` and `\t` are replaced and other escapes kept as written.
+pub fn unescape(raw: &str) -> String {
+    let mut value = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => value.push('\n'),
+            Some('t') => value.push('\t'),
+            Some(c @ ('"' | '\\')) => value.push(c),
+            Some(c) => {
+                value.push('\\');
+                value.push(c);
+            }
+            None => value.push('\\'),
+        }
+    }
+    value
+}
+
+impl Token<'_> {
+    pub fn kind(&self) -> TokenKind {
+        match self {
+            Token::Illegal { .. } => TokenKind::Illegal,
+            Token::EOF => TokenKind::EOF,
+            Token::Comment { .. } => TokenKind::Comment,
+            Token::Ident { .. } => TokenKind::Ident,
+            Token::Int { .. } => TokenKind::Int,
+            Token::String { .. } => TokenKind::String,
+            Token::Assign { .. } => TokenKind::Assign,
+            Token::Plus { .. } => TokenKind::Plus,
+            Token::Minus { .. } => TokenKind::Minus,
+            Token::Multiply { .. } => TokenKind::Multiply,
+            Token::Divide { .. } => TokenKind::Divide,
+            Token::Negation { .. } => TokenKind::Negation,
+            Token::Equals { .. } => TokenKind::Equals,
+            Token::NotEquals { .. } => TokenKind::NotEquals,
+            Token::LessThan { .. } => TokenKind::LessThan,
+            Token::GreaterThan { .. } => TokenKind::GreaterThan,
+            Token::DotDot { .. } => TokenKind::DotDot,
+            Token::DotDotEquals { .. } => TokenKind::DotDotEquals,
+            Token::Comma { .. } => TokenKind::Comma,
+            Token::Semicolon { .. } => TokenKind::Semicolon,
+            Token::Colon { .. } => TokenKind::Colon,
+            Token::Arrow { .. } => TokenKind::Arrow,
+            Token::LeftParen { .. } => TokenKind::LeftParen,
+            Token::RightParen { .. } => TokenKind::RightParen,
+            Token::LeftBrace { .. } => TokenKind::LeftBrace,
+            Token::RightBrace { .. } => TokenKind::RightBrace,
+            Token::LeftBracket { .. } => TokenKind::LeftBracket,
+            Token::RightBracket { .. } => TokenKind::RightBracket,
+            Token::Function { .. } => TokenKind::Function,
+            Token::Let { .. } => TokenKind::Let,
+            Token::True { .. } => TokenKind::True,
+            Token::False { .. } => TokenKind::False,
+            Token::If { .. } => TokenKind::If,
+            Token::Else { .. } => TokenKind::Else,
+            Token::Return { .. } => TokenKind::Return,
+            Token::Throw { .. } => TokenKind::Throw,
+            Token::Try { .. } => TokenKind::Try,
+            Token::Catch { .. } => TokenKind::Catch,
+            Token::While { .. } => TokenKind::While,
+            Token::Break { .. } => TokenKind::Break,
+            Token::Continue { .. } => TokenKind::Continue,
+            Token::For { .. } => TokenKind::For,
+            Token::In { .. } => TokenKind::In,
+        }
+    }
+
+    /// The same token starting at `location` in `source`, which must hold
+    /// the token's text there. Used to move tokens into an edited copy of
+    /// their source without lexing them again.
+    pub fn relocate<'b>(&self, location: usize, source: &'b str) -> Token<'b> {
+        match *self {
+            Token::Illegal { ref raw, .. } => Token::Illegal {
+                location,
+                raw: source[location..location + raw.len()].into(),
+            },
+            Token::EOF => Token::EOF,
+            Token::Comment { ref raw, .. } => Token::Comment {
+                location,
+                raw: source[location..location + raw.len()].into(),
+            },
+            Token::Ident { raw, .. } => Token::Ident { location, raw },
+            Token::Int { ref raw, value, .. } => Token::Int {
+                location,
+                raw: source[location..location + raw.len()].into(),
+                value,
+            },
+            Token::String { ref raw, .. } => Token::String {
+                location,
+                raw: source[location + 1..location + 1 + raw.len()].into(),
+            },
+            Token::Assign { .. } => Token::Assign { location },
+            Token::Plus { .. } => Token::Plus { location },
+            Token::Minus { .. } => Token::Minus { location },
+            Token::Multiply { .. } => Token::Multiply { location },
+            Token::Divide { .. } => Token::Divide { location },
+            Token::Negation { .. } => Token::Negation { location },
+            Token::Equals { .. } => Token::Equals { location },
+            Token::NotEquals { .. } => Token::NotEquals { location },
+            Token::LessThan { .. } => Token::LessThan { location },
+            Token::GreaterThan { .. } => Token::GreaterThan { location },
+            Token::DotDot { .. } => Token::DotDot { location },
+            Token::DotDotEquals { .. } => Token::DotDotEquals { location },
+            Token::Comma { .. } => Token::Comma { location },
+            Token::Semicolon { .. } => Token::Semicolon { location },
+            Token::Colon { .. } => Token::Colon { location },
+            Token::Arrow { .. } => Token::Arrow { location },
+            Token::LeftParen { .. } => Token::LeftParen { location },
+            Token::RightParen { .. } => Token::RightParen { location },
+            Token::LeftBrace { .. } => Token::LeftBrace { location },
+            Token::RightBrace { .. } => Token::RightBrace { location },
+            Token::LeftBracket { .. } => Token::LeftBracket { location },
+            Token::RightBracket { .. } => Token::RightBracket { location },
+            Token::Function { .. } => Token::Function { location },
+            Token::Let { .. } => Token::Let { location },
+            Token::True { .. } => Token::True { location },
+            Token::False { .. } => Token::False { location },
+            Token::If { .. } => Token::If { location },
+            Token::Else { .. } => Token::Else { location },
+            Token::Return { .. } => Token::Return { location },
+            Token::Throw { .. } => Token::Throw { location },
+            Token::Try { .. } => Token::Try { location },
+            Token::Catch { .. } => Token::Catch { location },
+            Token::While { .. } => Token::While { location },
+            Token::Break { .. } => Token::Break { location },
+            Token::Continue { .. } => Token::Continue { location },
+            Token::For { .. } => Token::For { location },
+            Token::In { .. } => Token::In { location },
+        }
+    }
+
+    pub fn location(&self) -> Option<usize> {
+        match self {
+            Token::EOF => None,
+            Token::Illegal { location, .. }
+            | Token::Comment { location, .. }
+            | Token::Ident { location, .. }
+            | Token::Int { location, .. }
+            | Token::String { location, .. }
+            | Token::Assign { location }
+            | Token::Plus { location }
+            | Token::Minus { location }
+            | Token::Multiply { location }
+            | Token::Divide { location }
+            | Token::Negation { location }
+            | Token::Equals { location }
+            | Token::NotEquals { location }
+            | Token::LessThan { location }
+            | Token::GreaterThan { location }
+            | Token::DotDot { location }
+            | Token::DotDotEquals { location }
+            | Token::Comma { location }
+            | Token::Semicolon { location }
+            | Token::Colon { location }
+            | Token::Arrow { location }
+            | Token::LeftParen { location }
+            | Token::RightParen { location }
+            | Token::LeftBrace { location }
+            | Token::RightBrace { location }
+            | Token::LeftBracket { location }
+            | Token::RightBracket { location }
+            | Token::Function { location }
+            | Token::Let { location }
+            | Token::True { location }
+            | Token::False { location }
+            | Token::If { location }
+            | Token::Else { location }
+            | Token::Return { location }
+            | Token::Throw { location }
+            | Token::Try { location }
+            | Token::Catch { location }
+            | Token::While { location }
+            | Token::Break { location }
+            | Token::Continue { location }
+            | Token::For { location }
+            | Token::In { location } => Some(*location),
+        }
+    }
+
+    /// The bytes of source the token was lexed from, or `None` for `EOF`.
+    pub fn span(&self) -> Option<Range<usize>> {
+        let location = self.location()?;
+        let len = match self {
+            Token::Illegal { raw, .. } | Token::Comment { raw, .. } | Token::Int { raw, .. } => {
+                raw.len()
+            }
+            Token::String { raw, .. } => raw.len() + 2,
+            Token::Ident { raw, .. } => raw.as_str().len(),
+            token => token.kind().literal().map_or(0, str::len),
+        };
+        Some(location..location + len)
+    }
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Illegal { raw, .. } | Token::Comment { raw, .. } => write!(f, "{}", raw),
+            Token::String { raw, .. } => write!(f, "\"{}\"", raw),
+            Token::Ident { raw, .. } => write!(f, "{}", raw),
+            Token::EOF => Ok(()),
+            Token::Int { value, .. } => write!(f, "{}", value),
+            token => write!(f, "{}", token.kind()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_kind_display() {
+        assert_eq!(Token::Equals { location: 3 }.kind(), TokenKind::Equals);
+        assert_eq!(TokenKind::Equals.to_string(), "==");
+        assert_eq!(TokenKind::Function.to_string(), "fn");
+        assert_eq!(TokenKind::Ident.to_string(), "identifier");
+        assert_eq!(TokenKind::Ident.literal(), None);
+        assert_eq!(TokenKind::LeftBrace.literal(), Some("{"));
+
+        let kinds: HashSet<TokenKind> = [
+            Token::Plus { location: 0 },
+            Token::Plus { location: 7 },
+            Token::Int {
+                location: 2,
+                raw: "1".into(),
+                value: 1,
+            },
+        ]
+        .iter()
+        .map(Token::kind)
+        .collect();
+        assert_eq!(kinds.len(), 2);
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("hello"), "hello");
+        assert_eq!(unescape(r#"a\"b\\c\nd\te"#), "a\"b\\c\nd\te");
+        assert_eq!(unescape(r"\q"), "\\q");
+        assert_eq!(unescape(""), "");
+    }
+
+    #[test]
+    fn test_span() {
+        let source = "let é = [0, 10, 007, 999]; // note\n\"a\\\"b\" ..= fn -> x # \"open";
+        let mut lex = crate::Lexer::with_comments(source);
+        loop {
+            let token = lex.next_token();
+            let Some(span) = token.span() else {
+                break;
+            };
+            assert_eq!(span.end, lex.position(), "{:?}", token);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Which rules run, read from a config file with one setting per line:
+//!
+//! ```text
+//! # the tests compare constants on purpose
+//! constant-comparison = off
+//! empty-block = on
+//! ```
+//!
+//! Rules not mentioned stay enabled.
+
+use std::{collections::HashSet, fmt};
+
+use crate::rules;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Config {
+    disabled: HashSet<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigError {
+    /// One-based line of the bad setting.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let known: Vec<&str> = rules::all().iter().map(|rule| rule.name()).collect();
+        let mut config = Config::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| ConfigError {
+                line: i + 1,
+                message,
+            };
+            let Some((rule, setting)) = line.split_once('=') else {
+                return Err(error(format!("expected 'RULE = on|off', found '{}'", line)));
+            };
+            let rule = rule.trim();
+            if !known.contains(&rule) {
+                return Err(error(format!("unknown rule '{}'", rule)));
+            }
+            match setting.trim() {
+                "on" => config.disabled.remove(rule),
+                "off" => config.disabled.insert(rule.to_string()),
+                other => {
+                    return Err(error(format!(
+                        "expected 'on' or 'off' for '{}', found '{}'",
+                        rule, other
+                    )))
+                }
+            };
+        }
+        Ok(config)
+    }
+
+    pub fn is_enabled(&self, rule: &str) -> bool {
+        !self.disabled.contains(rule)
+    }
+
+    pub fn set(&mut self, rule: &str, enabled: bool) {
+        if enabled {
+            self.disabled.remove(rule);
+        } else {
+            self.disabled.insert(rule.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(
+            "# comment\n\nempty-block = off\n  self-assignment=off\nself-assignment = on\n",
+        )
+        .unwrap();
+        assert!(!config.is_enabled("empty-block"));
+        assert!(config.is_enabled("self-assignment"));
+        assert!(config.is_enabled("constant-comparison"));
+
+        let error = |text| Config::parse(text).unwrap_err().to_string();
+        assert_eq!(error("\nbogus = off"), "line 2: unknown rule 'bogus'");
+        assert_eq!(
+            error("empty-block"),
+            "line 1: expected 'RULE = on|off', found 'empty-block'"
+        );
+        assert_eq!(
+            error("empty-block = no"),
+            "line 1: expected 'on' or 'off' for 'empty-block', found 'no'"
+        );
+    }
+}
@@ -0,0 +1,244 @@
+//! Checks for code that parses but is probably not what was meant.
+//!
+//! Each check is a `Rule`. The linter walks the tree once and hands every
+//! statement list, block and expression to each enabled rule. Rules can be
+//! turned off in a `Config`, and a comment suppresses rules on one line:
+//!
+//! ```text
+//! 5 < 10; // lint: allow(constant-comparison)
+//! // lint: allow(empty-block, unreachable-code)
+//! if (x) {} else { return 1; 2 }
+//! ```
+//!
+//! A suppression comment alone on its line applies to the next line.
+
+pub mod config;
+pub mod rules;
+
+use std::collections::HashMap;
+
+use lexer::{tokens::Token, Lexer};
+use parser::{
+    ast::{Block, Expression, Let, Program, Statement},
+    visit::{self, Visitor},
+};
+
+pub use config::{Config, ConfigError};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lint {
+    /// Name of the rule that reported it.
+    pub rule: &'static str,
+    pub message: String,
+    /// Start of the token the lint is about.
+    pub location: usize,
+}
+
+/// Where rules report what they find.
+pub struct Context {
+    rule: &'static str,
+    lints: Vec<Lint>,
+}
+
+impl Context {
+    pub fn report(&mut self, location: Option<usize>, message: impl Into<String>) {
+        if let Some(location) = location {
+            self.lints.push(Lint {
+                rule: self.rule,
+                message: message.into(),
+                location,
+            });
+        }
+    }
+}
+
+/// A check over the syntax tree. Every method does nothing by default.
+pub trait Rule {
+    /// The name used in config files and suppression comments.
+    fn name(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    /// The statements of the program and of every block.
+    fn check_statements(&self, _statements: &[Statement], _cx: &mut Context) {}
+
+    fn check_let(&self, _stmt: &Let, _cx: &mut Context) {}
+
+    fn check_block(&self, _block: &Block, _cx: &mut Context) {}
+
+    fn check_expression(&self, _expr: &Expression, _cx: &mut Context) {}
+}
+
+pub struct Linter {
+    rules: Vec<Box<dyn Rule>>,
+}
+
+impl Linter {
+    /// The built-in rules that `config` leaves enabled.
+    pub fn new(config: &Config) -> Self {
+        let rules = rules::all()
+            .into_iter()
+            .filter(|rule| config.is_enabled(rule.name()))
+            .collect();
+        Linter { rules }
+    }
+
+    pub fn with_rules(rules: Vec<Box<dyn Rule>>) -> Self {
+        Linter { rules }
+    }
+
+    /// The lints in `program`, parsed from `source`, ordered by location.
+    /// `source` is only read for suppression comments.
+    pub fn check(&self, source: &str, program: &Program) -> Vec<Lint> {
+        let mut walker = Walker {
+            rules: &self.rules,
+            cx: Context {
+                rule: "",
+                lints: Vec::new(),
+            },
+        };
+        walker.each(|rule, cx| rule.check_statements(&program.statements, cx));
+        walker.visit_program(program);
+
+        let allowed = suppressions(source);
+        let mut lints = walker.cx.lints;
+        lints.retain(|lint| {
+            let line = source[..lint.location.min(source.len())]
+                .matches('\n')
+                .count();
+            !allowed
+                .get(&line)
+                .is_some_and(|rules| rules.iter().any(|r| *r == lint.rule || *r == "all"))
+        });
+        lints.sort_by_key(|lint| lint.location);
+        lints
+    }
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Linter::new(&Config::default())
+    }
+}
+
+struct Walker<'r> {
+    rules: &'r [Box<dyn Rule>],
+    cx: Context,
+}
+
+impl Walker<'_> {
+    fn each(&mut self, mut check: impl FnMut(&dyn Rule, &mut Context)) {
+        for rule in self.rules {
+            self.cx.rule = rule.name();
+            check(rule.as_ref(), &mut self.cx);
+        }
+    }
+}
+
+impl Visitor for Walker<'_> {
+    fn visit_let(&mut self, stmt: &Let) {
+        self.each(|rule, cx| rule.check_let(stmt, cx));
+        visit::walk_let(self, stmt)
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        self.each(|rule, cx| {
+            rule.check_block(block, cx);
+            rule.check_statements(&block.statements, cx);
+        });
+        visit::walk_block(self, block)
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        self.each(|rule, cx| rule.check_expression(expr, cx));
+        visit::walk_expression(self, expr)
+    }
+}
+
+/// Rules allowed on each zero-based line by `// lint: allow(...)` comments.
+fn suppressions(source: &str) -> HashMap<usize, Vec<&str>> {
+    let mut allowed: HashMap<usize, Vec<&str>> = HashMap::new();
+    for token in Lexer::with_comments(source) {
+        let Token::Comment { location, raw } = token else {
+            continue;
+        };
+        let raw = &source[location..location + raw.len()];
+        let Some(list) = raw[2..]
+            .trim()
+            .strip_prefix("lint: allow(")
+            .and_then(|rest| rest.strip_suffix(')'))
+        else {
+            continue;
+        };
+        let line_start = source[..location].rfind('\n').map_or(0, |i| i + 1);
+        let mut line = source[..location].matches('\n').count();
+        if source[line_start..location].trim().is_empty() {
+            line += 1;
+        }
+        allowed
+            .entry(line)
+            .or_default()
+            .extend(list.split(',').map(str::trim));
+    }
+    allowed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::Parser;
+
+    fn lint(source: &str, linter: &Linter) -> Vec<(&'static str, usize)> {
+        let mut parser = Parser::new(Lexer::new(source));
+        let program = parser.parse_program().unwrap();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        linter
+            .check(source, &program)
+            .into_iter()
+            .map(|l| (l.rule, l.location))
+            .collect()
+    }
+
+    #[test]
+    fn test_example_comparisons() {
+        // the comparisons from example.monke
+        let source =
+            "5 < 10 > 5;\nif (5 < 10) { return true; } else { return false; }\n10 == 10;\n10 != 9;";
+        let rules: Vec<&str> = lint(source, &Linter::default())
+            .into_iter()
+            .map(|(rule, _)| rule)
+            .collect();
+        assert_eq!(
+            rules,
+            vec![
+                "constant-comparison",
+                "chained-comparison",
+                "constant-comparison",
+                "constant-comparison",
+                "constant-comparison",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_suppression() {
+        let source = "5 < 10; // lint: allow(constant-comparison)
+// lint: allow(empty-block, unreachable-code)
+let f = fn() { if (true) {} else { return 1; 2 } };
+// lint: allow(all)
+let x = x;
+";
+        assert_eq!(lint(source, &Linter::default()), vec![]);
+        assert_eq!(
+            lint("1 == 1; // lint: allow(empty-block)", &Linter::default()),
+            vec![("constant-comparison", 2)]
+        );
+    }
+
+    #[test]
+    fn test_config() {
+        let config = Config::parse("# quiet\nconstant-comparison = off\n").unwrap();
+        let linter = Linter::new(&config);
+        assert_eq!(lint("1 < 2; if (x) {}", &linter), vec![("empty-block", 14)]);
+    }
+}
@@ -0,0 +1,376 @@
+//! The built-in rules.
+
+use lexer::tokens::{Token, TokenKind};
+use parser::ast::{Block, Expression, Let, Statement};
+
+use crate::{Context, Rule};
+
+/// Every built-in rule, all enabled by default.
+pub fn all() -> Vec<Box<dyn Rule>> {
+    vec![
+        Box::new(ConstantComparison),
+        Box::new(ChainedComparison),
+        Box::new(UnreachableCode),
+        Box::new(IdenticalBranches),
+        Box::new(SelfAssignment),
+        Box::new(EmptyBlock),
+    ]
+}
+
+fn is_comparison(token: &Token) -> bool {
+    matches!(
+        token.kind(),
+        TokenKind::Equals | TokenKind::NotEquals | TokenKind::LessThan | TokenKind::GreaterThan
+    )
+}
+
+fn comparison(expr: &Expression) -> bool {
+    matches!(expr, Expression::Infix(infix) if is_comparison(&infix.token))
+}
+
+#[derive(PartialEq)]
+enum Constant {
+    Int(i64),
+    Bool(bool),
+}
+
+/// The value of an expression made only of literals and operators, if it
+/// has one.
+fn constant(expr: &Expression) -> Option<Constant> {
+    match expr {
+        Expression::Integer(Token::Int { value, .. }) => Some(Constant::Int(*value)),
+        Expression::Boolean(token) => Some(Constant::Bool(token.kind() == TokenKind::True)),
+        Expression::Prefix(prefix) => match (prefix.token.kind(), constant(&prefix.right)?) {
+            (TokenKind::Minus, Constant::Int(n)) => n.checked_neg().map(Constant::Int),
+            (TokenKind::Negation, Constant::Bool(b)) => Some(Constant::Bool(!b)),
+            _ => None,
+        },
+        Expression::Infix(infix) => {
+            let left = constant(&infix.left)?;
+            let right = constant(&infix.right)?;
+            match (infix.token.kind(), left, right) {
+                (TokenKind::Plus, Constant::Int(a), Constant::Int(b)) => {
+                    a.checked_add(b).map(Constant::Int)
+                }
+                (TokenKind::Minus, Constant::Int(a), Constant::Int(b)) => {
+                    a.checked_sub(b).map(Constant::Int)
+                }
+                (TokenKind::Multiply, Constant::Int(a), Constant::Int(b)) => {
+                    a.checked_mul(b).map(Constant::Int)
+                }
+                (TokenKind::Divide, Constant::Int(a), Constant::Int(b)) => {
+                    a.checked_div(b).map(Constant::Int)
+                }
+                (TokenKind::LessThan, Constant::Int(a), Constant::Int(b)) => {
+                    Some(Constant::Bool(a < b))
+                }
+                (TokenKind::GreaterThan, Constant::Int(a), Constant::Int(b)) => {
+                    Some(Constant::Bool(a > b))
+                }
+                (TokenKind::Equals, a, b) => Some(Constant::Bool(a == b)),
+                (TokenKind::NotEquals, a, b) => Some(Constant::Bool(a != b)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// `5 < 10`: a comparison whose result is known before running.
+pub struct ConstantComparison;
+
+impl Rule for ConstantComparison {
+    fn name(&self) -> &'static str {
+        "constant-comparison"
+    }
+
+    fn description(&self) -> &'static str {
+        "comparisons between constants"
+    }
+
+    fn check_expression(&self, expr: &Expression, cx: &mut Context) {
+        let Expression::Infix(infix) = expr else {
+            return;
+        };
+        // chained comparisons are reported by their own rule
+        if !is_comparison(&infix.token) || comparison(&infix.left) {
+            return;
+        }
+        if let Some(Constant::Bool(value)) = constant(expr) {
+            cx.report(
+                infix.token.location(),
+                format!("Comparison of constants is always {}", value),
+            );
+        }
+    }
+}
+
+/// `5 < 10 > 5`, which compares the result of `5 < 10` with `5`. Comparisons
+/// written with parentheses around the inner one are reported too, since the
+/// tree does not keep them.
+pub struct ChainedComparison;
+
+impl Rule for ChainedComparison {
+    fn name(&self) -> &'static str {
+        "chained-comparison"
+    }
+
+    fn description(&self) -> &'static str {
+        "comparisons whose operand is another comparison"
+    }
+
+    fn check_expression(&self, expr: &Expression, cx: &mut Context) {
+        let Expression::Infix(infix) = expr else {
+            return;
+        };
+        if is_comparison(&infix.token) && (comparison(&infix.left) || comparison(&infix.right)) {
+            cx.report(
+                infix.token.location(),
+                format!(
+                    "Comparisons do not chain; '{}' compares the result of another comparison",
+                    infix.token
+                ),
+            );
+        }
+    }
+}
+
+/// Statements after a `return`, `throw`, `break` or `continue` in the same
+/// block.
+pub struct UnreachableCode;
+
+impl Rule for UnreachableCode {
+    fn name(&self) -> &'static str {
+        "unreachable-code"
+    }
+
+    fn description(&self) -> &'static str {
+        "statements after a return, throw, break or continue"
+    }
+
+    fn check_statements(&self, statements: &[Statement], cx: &mut Context) {
+        let Some((index, keyword)) =
+            statements
+                .iter()
+                .enumerate()
+                .find_map(|(i, stmt)| match stmt {
+                    Statement::Return(_) => Some((i, "return")),
+                    Statement::Throw(_) => Some((i, "throw")),
+                    Statement::Break(_) => Some((i, "break")),
+                    Statement::Continue(_) => Some((i, "continue")),
+                    _ => None,
+                })
+        else {
+            return;
+        };
+        if let Some(next) = statements.get(index + 1) {
+            let location = Some(next.span().start);
+            cx.report(location, format!("Unreachable code after '{}'", keyword));
+        }
+    }
+}
+
+/// `if (c) { x } else { x }`.
+pub struct IdenticalBranches;
+
+impl Rule for IdenticalBranches {
+    fn name(&self) -> &'static str {
+        "identical-branches"
+    }
+
+    fn description(&self) -> &'static str {
+        "if expressions whose branches are the same"
+    }
+
+    fn check_expression(&self, expr: &Expression, cx: &mut Context) {
+        let Expression::If(expr) = expr else {
+            return;
+        };
+        let Some(alternative) = &expr.alternative else {
+            return;
+        };
+        // Display is canonical, so equal text means equal trees
+        if expr.consequence.to_string() == alternative.to_string() {
+            cx.report(
+                expr.token.location(),
+                "Both branches of this 'if' are identical",
+            );
+        }
+    }
+}
+
+/// `let x = x;`.
+pub struct SelfAssignment;
+
+impl Rule for SelfAssignment {
+    fn name(&self) -> &'static str {
+        "self-assignment"
+    }
+
+    fn description(&self) -> &'static str {
+        "let statements that bind a name to itself"
+    }
+
+    fn check_let(&self, stmt: &Let, cx: &mut Context) {
+        if let (
+            Expression::Identifier(Token::Ident { raw: name, .. }),
+            Expression::Identifier(Token::Ident { raw: value, .. }),
+        ) = (&stmt.identifier, &stmt.value)
+        {
+            if name == value {
+                cx.report(
+                    stmt.token.location(),
+                    format!("'{}' is assigned to itself", name),
+                );
+            }
+        }
+    }
+}
+
+/// `{}`, including empty function bodies.
+pub struct EmptyBlock;
+
+impl Rule for EmptyBlock {
+    fn name(&self) -> &'static str {
+        "empty-block"
+    }
+
+    fn description(&self) -> &'static str {
+        "blocks without statements"
+    }
+
+    fn check_block(&self, block: &Block, cx: &mut Context) {
+        if block.statements.is_empty() {
+            cx.report(block.token.location(), "Empty block");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lexer::Lexer;
+    use parser::Parser;
+
+    use crate::{Lint, Linter};
+
+    fn lint(source: &str) -> Vec<Lint> {
+        let mut parser = Parser::new(Lexer::new(source));
+        let program = parser.parse_program().unwrap();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        Linter::default().check(source, &program)
+    }
+
+    fn assert_lints(source: &str, expected: &[(&str, &str, usize)]) {
+        let lints: Vec<(&str, String, usize)> = lint(source)
+            .into_iter()
+            .map(|l| (l.rule, l.message, l.location))
+            .collect();
+        let expected: Vec<(&str, String, usize)> = expected
+            .iter()
+            .map(|(rule, message, location)| (*rule, message.to_string(), *location))
+            .collect();
+        assert_eq!(lints, expected, "{}", source);
+    }
+
+    #[test]
+    fn test_constant_comparison() {
+        assert_lints(
+            "5 < 10; -1 > 2 * 3; true == !false; x < 10; 1 == true;",
+            &[
+                (
+                    "constant-comparison",
+                    "Comparison of constants is always true",
+                    2,
+                ),
+                (
+                    "constant-comparison",
+                    "Comparison of constants is always false",
+                    11,
+                ),
+                (
+                    "constant-comparison",
+                    "Comparison of constants is always true",
+                    25,
+                ),
+                (
+                    "constant-comparison",
+                    "Comparison of constants is always false",
+                    46,
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_chained_comparison() {
+        assert_lints(
+            "a < b > c; a == (b == c);",
+            &[
+                (
+                    "chained-comparison",
+                    "Comparisons do not chain; '>' compares the result of another comparison",
+                    6,
+                ),
+                (
+                    "chained-comparison",
+                    "Comparisons do not chain; '==' compares the result of another comparison",
+                    13,
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        assert_lints(
+            "let f = fn() { return 1; f(); let x = 2; }; return f(); f;",
+            &[
+                ("unreachable-code", "Unreachable code after 'return'", 25),
+                ("unreachable-code", "Unreachable code after 'return'", 56),
+            ],
+        );
+        assert_lints(
+            "try { throw 1; 2 } catch (e) { e }",
+            &[("unreachable-code", "Unreachable code after 'throw'", 15)],
+        );
+        assert_lints(
+            "while (x) { if (x) { continue; x } break; while (y) {} }",
+            &[
+                ("unreachable-code", "Unreachable code after 'continue'", 31),
+                ("unreachable-code", "Unreachable code after 'break'", 42),
+                ("empty-block", "Empty block", 52),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_identical_branches() {
+        assert_lints(
+            "if (a) { b; c } else { b; c }; if (a) { b } else { c }; if (a) { b }",
+            &[(
+                "identical-branches",
+                "Both branches of this 'if' are identical",
+                0,
+            )],
+        );
+    }
+
+    #[test]
+    fn test_self_assignment() {
+        assert_lints(
+            "let x = x; let y = x;",
+            &[("self-assignment", "'x' is assigned to itself", 0)],
+        );
+    }
+
+    #[test]
+    fn test_empty_block() {
+        assert_lints(
+            "let f = fn() {}; if (f) { 1 } else {}",
+            &[
+                ("empty-block", "Empty block", 13),
+                ("empty-block", "Empty block", 35),
+            ],
+        );
+    }
+}
@@ -0,0 +1,341 @@
+//! What the server knows about one document: its syntax tree and where
+//! every name is bound and used.
+
+use std::ops::Range;
+
+use lexer::{
+    symbol::Symbol,
+    tokens::{Token, TokenKind},
+};
+use parser::cst::{self, NodeKind, SyntaxNode, SyntaxTree, TriviaKind};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BindingKind {
+    Let,
+    /// A `let` whose value is a function literal.
+    Function,
+    Parameter,
+}
+
+pub struct Binding {
+    pub name: Symbol,
+    pub kind: BindingKind,
+    /// The identifier that introduces the binding.
+    pub span: Range<usize>,
+    /// The whole `let` statement, or the parameter itself.
+    pub definition: Range<usize>,
+    pub top_level: bool,
+}
+
+pub struct Reference {
+    pub span: Range<usize>,
+    /// Index into `Analysis::bindings`, or `None` for an undefined name.
+    pub binding: Option<usize>,
+}
+
+pub struct Analysis<'a> {
+    pub tree: SyntaxTree<'a>,
+    pub bindings: Vec<Binding>,
+    pub references: Vec<Reference>,
+}
+
+impl<'a> Analysis<'a> {
+    pub fn new(text: &'a str) -> Self {
+        let mut analysis = Analysis {
+            tree: cst::parse(text),
+            bindings: Vec::new(),
+            references: Vec::new(),
+        };
+        let root = analysis.tree.root.clone();
+        let mut scopes = vec![Vec::new()];
+        analysis.walk(&root, &mut scopes);
+        analysis
+    }
+
+    /// Functions open a scope holding their parameters and the `let`s in
+    /// their body, as they do at runtime; blocks do not, so a `catch`
+    /// parameter and the variables of a `for` are defined in the enclosing
+    /// scope.
+    fn walk(&mut self, node: &SyntaxNode, scopes: &mut Vec<Vec<usize>>) {
+        match node.kind {
+            NodeKind::Let => {
+                let mut children = node.nodes().filter(|n| n.kind != NodeKind::Annotation);
+                let (Some(identifier), Some(value)) = (children.next(), children.next()) else {
+                    return;
+                };
+                let kind = if value.kind == NodeKind::Function {
+                    BindingKind::Function
+                } else {
+                    BindingKind::Let
+                };
+                // defined before its value so that functions can recurse
+                self.define(identifier, kind, node.span(), scopes);
+                self.walk(value, scopes);
+            }
+            NodeKind::Function => {
+                scopes.push(Vec::new());
+                for child in node.nodes() {
+                    if child.kind == NodeKind::Parameters {
+                        let names = child.nodes().filter(|n| n.kind == NodeKind::Identifier);
+                        for parameter in names {
+                            let span = parameter.span();
+                            self.define(parameter, BindingKind::Parameter, span, scopes);
+                        }
+                    } else {
+                        self.walk(child, scopes);
+                    }
+                }
+                scopes.pop();
+            }
+            NodeKind::Try => {
+                for child in node.nodes() {
+                    if child.kind == NodeKind::Identifier {
+                        let span = child.span();
+                        self.define(child, BindingKind::Parameter, span, scopes);
+                    } else {
+                        self.walk(child, scopes);
+                    }
+                }
+            }
+            NodeKind::For => {
+                let mut children = node.nodes();
+                let variable = children.next();
+                // the iterable is evaluated before the variable is bound
+                if let Some(iterable) = children.next() {
+                    self.walk(iterable, scopes);
+                }
+                let names: Vec<&SyntaxNode> = match variable {
+                    Some(v) if v.kind == NodeKind::Identifier => vec![v],
+                    Some(v) if v.kind == NodeKind::Array => v
+                        .nodes()
+                        .filter(|n| n.kind == NodeKind::Identifier)
+                        .collect(),
+                    _ => Vec::new(),
+                };
+                for name in names {
+                    self.define(name, BindingKind::Let, name.span(), scopes);
+                }
+                for child in children {
+                    self.walk(child, scopes);
+                }
+            }
+            NodeKind::Identifier => {
+                let Some(token) = node.first_token() else {
+                    return;
+                };
+                let binding = match token.token {
+                    Token::Ident { raw, .. } => scopes
+                        .iter()
+                        .rev()
+                        .flat_map(|scope| scope.iter().rev())
+                        .copied()
+                        .find(|b| self.bindings[*b].name == raw),
+                    _ => None,
+                };
+                self.references.push(Reference {
+                    span: token.token.span().unwrap_or_default(),
+                    binding,
+                });
+            }
+            NodeKind::Error => {}
+            _ => {
+                for child in node.nodes() {
+                    self.walk(child, scopes);
+                }
+            }
+        }
+    }
+
+    fn define(
+        &mut self,
+        identifier: &SyntaxNode,
+        kind: BindingKind,
+        definition: Range<usize>,
+        scopes: &mut [Vec<usize>],
+    ) {
+        let Some(token) = identifier.first_token() else {
+            return;
+        };
+        let Token::Ident { raw, .. } = token.token else {
+            return;
+        };
+        self.bindings.push(Binding {
+            name: raw,
+            kind,
+            span: token.token.span().unwrap_or_default(),
+            definition,
+            top_level: scopes.len() == 1,
+        });
+        if let Some(scope) = scopes.last_mut() {
+            scope.push(self.bindings.len() - 1);
+        }
+    }
+
+    /// The binding defined or used at `offset`, counting the position just
+    /// after an identifier as on it.
+    pub fn binding_at(&self, offset: usize) -> Option<usize> {
+        let on = |span: &Range<usize>| span.start <= offset && offset <= span.end;
+        self.references
+            .iter()
+            .find(|r| on(&r.span))
+            .and_then(|r| r.binding)
+            .or_else(|| self.bindings.iter().position(|b| on(&b.span)))
+    }
+
+    /// The kind of binding an identifier starting at `offset` refers to.
+    fn identifier_kind(&self, offset: usize) -> Option<BindingKind> {
+        self.bindings
+            .iter()
+            .find(|b| b.span.start == offset)
+            .map(|b| b.kind)
+            .or_else(|| {
+                self.references
+                    .iter()
+                    .find(|r| r.span.start == offset)
+                    .and_then(|r| r.binding)
+                    .map(|b| self.bindings[b].kind)
+            })
+    }
+
+    /// Every highlighted token as `(span, index into SEMANTIC_TOKEN_TYPES)`,
+    /// in source order.
+    pub fn semantic_tokens(&self) -> Vec<(Range<usize>, u32)> {
+        let mut tokens = Vec::new();
+        let mut offset = 0;
+        for token in &self.tree.tokens {
+            for trivia in &token.leading {
+                if trivia.kind == TriviaKind::Comment {
+                    tokens.push((offset..offset + trivia.text.len(), COMMENT));
+                }
+                offset += trivia.text.len();
+            }
+            let span = offset..offset + token.text.len();
+            offset = span.end;
+            let kind = match token.token.kind() {
+                TokenKind::Function
+                | TokenKind::Let
+                | TokenKind::True
+                | TokenKind::False
+                | TokenKind::If
+                | TokenKind::Else
+                | TokenKind::Return
+                | TokenKind::Throw
+                | TokenKind::Try
+                | TokenKind::Catch
+                | TokenKind::While
+                | TokenKind::Break
+                | TokenKind::Continue
+                | TokenKind::For
+                | TokenKind::In => Some(KEYWORD),
+                TokenKind::Int => Some(NUMBER),
+                TokenKind::String => Some(STRING),
+                TokenKind::Ident => Some(match self.identifier_kind(span.start) {
+                    Some(BindingKind::Parameter) => PARAMETER,
+                    Some(BindingKind::Function) => FUNCTION,
+                    _ => VARIABLE,
+                }),
+                TokenKind::Assign
+                | TokenKind::Plus
+                | TokenKind::Minus
+                | TokenKind::Multiply
+                | TokenKind::Divide
+                | TokenKind::Negation
+                | TokenKind::Equals
+                | TokenKind::NotEquals
+                | TokenKind::LessThan
+                | TokenKind::GreaterThan
+                | TokenKind::DotDot
+                | TokenKind::DotDotEquals => Some(OPERATOR),
+                _ => None,
+            };
+            if let Some(kind) = kind {
+                tokens.push((span, kind));
+            }
+            for trivia in &token.trailing {
+                if trivia.kind == TriviaKind::Comment {
+                    tokens.push((offset..offset + trivia.text.len(), COMMENT));
+                }
+                offset += trivia.text.len();
+            }
+        }
+        tokens
+    }
+
+    /// The span to underline for a parse error at `location`.
+    pub fn error_span(&self, location: Option<usize>, text: &str) -> Range<usize> {
+        let Some(location) = location else {
+            return text.len()..text.len();
+        };
+        self.tree
+            .tokens
+            .iter()
+            .find(|t| t.token.location() == Some(location))
+            .and_then(|t| t.token.span())
+            .unwrap_or(location..location)
+    }
+}
+
+pub const SEMANTIC_TOKEN_TYPES: &[&str] = &[
+    "keyword",
+    "variable",
+    "parameter",
+    "function",
+    "number",
+    "operator",
+    "comment",
+    "string",
+];
+
+const KEYWORD: u32 = 0;
+const VARIABLE: u32 = 1;
+const PARAMETER: u32 = 2;
+const FUNCTION: u32 = 3;
+const NUMBER: u32 = 4;
+const OPERATOR: u32 = 5;
+const COMMENT: u32 = 6;
+const STRING: u32 = 7;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolution() {
+        let text = "let x = 1;\nlet f = fn(x) { x + y };\nf(x);\ntry { e } catch (e) { e };\nfor ([k, x] in x) { k }";
+        let analysis = Analysis::new(text);
+        let names: Vec<(&str, BindingKind, bool)> = analysis
+            .bindings
+            .iter()
+            .map(|b| (b.name.as_str(), b.kind, b.top_level))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("x", BindingKind::Let, true),
+                ("f", BindingKind::Function, true),
+                ("x", BindingKind::Parameter, false),
+                ("e", BindingKind::Parameter, true),
+                ("k", BindingKind::Let, true),
+                ("x", BindingKind::Let, true),
+            ]
+        );
+        let resolved: Vec<(&str, Option<usize>)> = analysis
+            .references
+            .iter()
+            .map(|r| (&text[r.span.clone()], r.binding))
+            .collect();
+        assert_eq!(
+            resolved,
+            vec![
+                ("x", Some(2)),
+                ("y", None),
+                ("f", Some(1)),
+                ("x", Some(0)),
+                ("e", None),
+                ("e", Some(3)),
+                ("x", Some(0)),
+                ("k", Some(4)),
+            ]
+        );
+    }
+}
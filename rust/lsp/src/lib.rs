@@ -0,0 +1,530 @@
+//! A Language Server Protocol server for Monkey.
+//!
+//! Documents are synchronized in full on every change and analysed again
+//! from scratch for each request. Supported: diagnostics from parse errors,
+//! hover and go-to-definition for `let` bindings and parameters, document
+//! symbols for top-level `let`s and semantic tokens. Hover also shows the
+//! inferred type of a name when the document parses without errors.
+//!
+//! Identifiers are interned in the lexer's global interner, which never
+//! frees a name. A session therefore keeps every distinct identifier it has
+//! ever seen, including those typed and then deleted: memory grows with the
+//! number of different names edited, not with the size of the open
+//! documents. Restart the server if a very long session grows too large.
+
+pub mod analysis;
+pub mod transport;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+
+use lexer::lines::LineIndex;
+use serde_json::{json, Value};
+
+use analysis::{Analysis, BindingKind, SEMANTIC_TOKEN_TYPES};
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INVALID_REQUEST: i64 = -32600;
+
+/// LSP `SymbolKind` values.
+const SYMBOL_FUNCTION: u32 = 12;
+const SYMBOL_VARIABLE: u32 = 13;
+
+/// Serves LSP requests read from `input` until the client sends `exit` or
+/// closes the stream. Returns whether the session ended cleanly, that is
+/// with `exit` after `shutdown`.
+pub fn run(mut input: impl BufRead, output: impl Write) -> io::Result<bool> {
+    let mut server = Server {
+        output,
+        documents: HashMap::new(),
+        shut_down: false,
+    };
+    while let Some(body) = transport::read_body(&mut input)? {
+        let message: Value = match serde_json::from_slice(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                // the framing was fine, so the next message can still be read
+                server.reply(&Value::Null, Err((PARSE_ERROR, e.to_string())))?;
+                continue;
+            }
+        };
+        if message["method"] == "exit" {
+            return Ok(server.shut_down);
+        }
+        server.handle(&message)?;
+    }
+    Ok(false)
+}
+
+struct Server<W> {
+    output: W,
+    documents: HashMap<String, String>,
+    shut_down: bool,
+}
+
+type Response = Result<Value, (i64, String)>;
+
+fn invalid_params() -> (i64, String) {
+    (INVALID_PARAMS, String::from("invalid params"))
+}
+
+impl<W: Write> Server<W> {
+    fn handle(&mut self, message: &Value) -> io::Result<()> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let Some(id) = message.get("id") else {
+            return self.notification(method, params);
+        };
+
+        let response = if self.shut_down {
+            Err((INVALID_REQUEST, String::from("server is shut down")))
+        } else {
+            self.request(method, params)
+        };
+        self.reply(id, response)
+    }
+
+    fn reply(&mut self, id: &Value, response: Response) -> io::Result<()> {
+        let reply = match response {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": code, "message": message},
+            }),
+        };
+        transport::write_message(&mut self.output, &reply)
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> io::Result<()> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.to_string(), text.to_string());
+                self.publish_diagnostics(uri)
+            }
+            "textDocument/didChange" => {
+                // full sync: the last change holds the whole document
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes
+                    .and_then(|c| c.last())
+                    .and_then(|c| c["text"].as_str())
+                {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                }
+                self.publish_diagnostics(uri)
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                self.notify(
+                    "textDocument/publishDiagnostics",
+                    json!({"uri": uri, "diagnostics": []}),
+                )
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        let message = json!({"jsonrpc": "2.0", "method": method, "params": params});
+        transport::write_message(&mut self.output, &message)
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) -> io::Result<()> {
+        let Some(text) = self.documents.get(uri) else {
+            return Ok(());
+        };
+        let analysis = Analysis::new(text);
+        let index = LineIndex::new(text);
+        let diagnostics: Vec<Value> = analysis
+            .tree
+            .errors
+            .iter()
+            .map(|error| {
+                let span = analysis.error_span(error.location(), text);
+                json!({
+                    "range": range(&index, span),
+                    "severity": 1,
+                    "source": "monkey",
+                    "message": error.message(),
+                })
+            })
+            .collect();
+        self.notify(
+            "textDocument/publishDiagnostics",
+            json!({"uri": uri, "diagnostics": diagnostics}),
+        )
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Response {
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "documentSymbolProvider": true,
+                    "semanticTokensProvider": {
+                        "legend": {"tokenTypes": SEMANTIC_TOKEN_TYPES, "tokenModifiers": []},
+                        "full": true,
+                    },
+                },
+                "serverInfo": {"name": "monkey-lsp", "version": env!("CARGO_PKG_VERSION")},
+            })),
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Value::Null)
+            }
+            "textDocument/hover" => self.with_position(params, hover),
+            "textDocument/definition" => self.with_position(params, definition),
+            "textDocument/documentSymbol" => {
+                let (_, text) = self.document(params)?;
+                Ok(document_symbols(text))
+            }
+            "textDocument/semanticTokens/full" => {
+                let (_, text) = self.document(params)?;
+                Ok(semantic_tokens(text))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unsupported method '{}'", method))),
+        }
+    }
+
+    fn document<'s>(&'s self, params: &'s Value) -> Result<(&'s str, &'s str), (i64, String)> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .ok_or_else(invalid_params)?;
+        let text = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| (INVALID_PARAMS, format!("unknown document '{}'", uri)))?;
+        Ok((uri, text))
+    }
+
+    /// Runs `handler` with the document and the byte offset of the request's
+    /// position in it.
+    fn with_position(&self, params: &Value, handler: fn(&str, &str, usize) -> Value) -> Response {
+        let (uri, text) = self.document(params)?;
+        let position = &params["position"];
+        let (Some(line), Some(character)) =
+            (position["line"].as_u64(), position["character"].as_u64())
+        else {
+            return Err(invalid_params());
+        };
+        let offset = LineIndex::new(text).offset(line as u32, character as u32);
+        Ok(handler(uri, text, offset))
+    }
+}
+
+fn position(index: &LineIndex, offset: usize) -> Value {
+    let (line, character) = index.position(offset);
+    json!({"line": line, "character": character})
+}
+
+fn range(index: &LineIndex, span: Range<usize>) -> Value {
+    json!({"start": position(index, span.start), "end": position(index, span.end)})
+}
+
+fn hover(_uri: &str, text: &str, offset: usize) -> Value {
+    let analysis = Analysis::new(text);
+    let Some(binding) = analysis.binding_at(offset) else {
+        return Value::Null;
+    };
+    let binding = &analysis.bindings[binding];
+    let code = match binding.kind {
+        BindingKind::Parameter => format!("(parameter) {}", binding.name),
+        BindingKind::Let | BindingKind::Function => text[binding.definition.clone()].to_string(),
+    };
+    let mut value = format!("```monkey\n{}\n```", code);
+    if let Ok(program) = analysis.tree.to_ast() {
+        if let Some(ty) = typecheck::check(text, &program).type_at(offset) {
+            value.push_str(&format!("\n\n`{}`", ty));
+        }
+    }
+    json!({
+        "contents": {"kind": "markdown", "value": value},
+        "range": range(&LineIndex::new(text), binding.span.clone()),
+    })
+}
+
+fn definition(uri: &str, text: &str, offset: usize) -> Value {
+    let analysis = Analysis::new(text);
+    match analysis.binding_at(offset) {
+        Some(binding) => json!({
+            "uri": uri,
+            "range": range(&LineIndex::new(text), analysis.bindings[binding].span.clone()),
+        }),
+        None => Value::Null,
+    }
+}
+
+fn document_symbols(text: &str) -> Value {
+    let analysis = Analysis::new(text);
+    let index = LineIndex::new(text);
+    let symbols: Vec<Value> = analysis
+        .bindings
+        .iter()
+        .filter(|b| b.top_level)
+        .map(|b| {
+            let kind = match b.kind {
+                BindingKind::Function => SYMBOL_FUNCTION,
+                _ => SYMBOL_VARIABLE,
+            };
+            json!({
+                "name": b.name.as_str(),
+                "kind": kind,
+                "range": range(&index, b.definition.clone()),
+                "selectionRange": range(&index, b.span.clone()),
+            })
+        })
+        .collect();
+    Value::from(symbols)
+}
+
+/// Encodes tokens as the LSP's relative `[line, start, length, type,
+/// modifiers]` quintuples. A token spanning lines, like a string with a
+/// newline in it, is split into one entry per line, since we do not claim
+/// the client's `multilineTokenSupport`.
+fn semantic_tokens(text: &str) -> Value {
+    let analysis = Analysis::new(text);
+    let index = LineIndex::new(text);
+    let mut data = Vec::new();
+    let (mut last_line, mut last_start) = (0, 0);
+    for (span, kind) in analysis.semantic_tokens() {
+        let mut offset = span.start;
+        while offset < span.end {
+            let (line, start) = index.position(offset);
+            let (_, end) = index.position(index.offset(line, u32::MAX).min(span.end));
+            offset = index.offset(line + 1, 0);
+            if end == start {
+                continue;
+            }
+            let delta_start = if line == last_line {
+                start - last_start
+            } else {
+                start
+            };
+            data.extend([line - last_line, delta_start, end - start, kind, 0]);
+            (last_line, last_start) = (line, start);
+        }
+    }
+    json!({ "data": data })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///test.monke";
+
+    fn frame(messages: &[Value]) -> Vec<u8> {
+        let mut input = Vec::new();
+        for message in messages {
+            transport::write_message(&mut input, message).unwrap();
+        }
+        input
+    }
+
+    /// Runs a whole session and returns whether it ended cleanly and every
+    /// message the server sent.
+    fn session(messages: &[Value]) -> (bool, Vec<Value>) {
+        let input = frame(messages);
+        let mut output = Vec::new();
+        let clean = run(&input[..], &mut output).expect("session failed");
+        let mut replies = Vec::new();
+        let mut reader = &output[..];
+        while let Some(reply) = transport::read_message(&mut reader).unwrap() {
+            replies.push(reply);
+        }
+        (clean, replies)
+    }
+
+    fn open(text: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {"textDocument": {"uri": URI, "languageId": "monkey", "version": 1, "text": text}},
+        })
+    }
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params})
+    }
+
+    fn at(line: u32, character: u32) -> Value {
+        json!({"textDocument": {"uri": URI}, "position": {"line": line, "character": character}})
+    }
+
+    fn document() -> Value {
+        json!({"textDocument": {"uri": URI}})
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let (clean, replies) = session(&[
+            request(1, "initialize", json!({"capabilities": {}})),
+            json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+            request(2, "workspace/symbol", json!({"query": ""})),
+            request(3, "shutdown", Value::Null),
+            json!({"jsonrpc": "2.0", "method": "exit"}),
+        ]);
+        assert!(clean);
+        assert_eq!(replies.len(), 3);
+        let capabilities = &replies[0]["result"]["capabilities"];
+        assert_eq!(capabilities["definitionProvider"], true);
+        assert_eq!(
+            capabilities["semanticTokensProvider"]["legend"]["tokenTypes"][0],
+            "keyword"
+        );
+        assert_eq!(replies[1]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(
+            replies[2],
+            json!({"jsonrpc": "2.0", "id": 3, "result": null})
+        );
+
+        let (clean, _) = session(&[json!({"jsonrpc": "2.0", "method": "exit"})]);
+        assert!(!clean);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let (_, replies) = session(&[
+            open("let x = 5;\nlet = 10;"),
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didChange",
+                "params": {
+                    "textDocument": {"uri": URI, "version": 2},
+                    "contentChanges": [{"text": "let x = 5;"}],
+                },
+            }),
+        ]);
+        assert_eq!(
+            replies[0],
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": {
+                    "uri": URI,
+                    "diagnostics": [{
+                        "range": {
+                            "start": {"line": 1, "character": 4},
+                            "end": {"line": 1, "character": 5},
+                        },
+                        "severity": 1,
+                        "source": "monkey",
+                        "message": "Unexpected '='. Expected identifier",
+                    }],
+                },
+            })
+        );
+        assert_eq!(replies[1]["params"]["diagnostics"], json!([]));
+    }
+
+    #[test]
+    fn test_hover_and_definition() {
+        let text = "let add = fn(a, b) { a + b };\nadd(1, 2);";
+        let (_, replies) = session(&[
+            open(text),
+            request(1, "textDocument/hover", at(1, 1)),
+            request(2, "textDocument/hover", at(0, 21)),
+            request(3, "textDocument/definition", at(0, 25)),
+            request(4, "textDocument/definition", at(1, 5)),
+        ]);
+        assert_eq!(
+            replies[1]["result"]["contents"]["value"],
+            "```monkey\nlet add = fn(a, b) { a + b };\n```\n\n`fn(int, int) -> int`"
+        );
+        assert_eq!(
+            replies[2]["result"]["contents"]["value"],
+            "```monkey\n(parameter) a\n```\n\n`int`"
+        );
+        assert_eq!(
+            replies[3]["result"],
+            json!({
+                "uri": URI,
+                "range": {
+                    "start": {"line": 0, "character": 16},
+                    "end": {"line": 0, "character": 17},
+                },
+            })
+        );
+        assert_eq!(replies[4]["result"], Value::Null);
+    }
+
+    #[test]
+    fn test_malformed_messages() {
+        let mut input = b"Content-Length: 8\r\n\r\n{\"id\": 1".to_vec();
+        input.extend(frame(&[request(2, "shutdown", Value::Null)]));
+        let mut output = Vec::new();
+        run(&input[..], &mut output).unwrap();
+        let mut reader = &output[..];
+        let reply = transport::read_message(&mut reader).unwrap().unwrap();
+        assert_eq!(reply["id"], Value::Null);
+        assert_eq!(reply["error"]["code"], PARSE_ERROR);
+        let reply = transport::read_message(&mut reader).unwrap().unwrap();
+        assert_eq!(reply["id"], 2);
+
+        let input = format!(
+            "Content-Length: {}\r\n\r\n",
+            transport::MAX_CONTENT_LENGTH + 1
+        );
+        assert!(run(input.as_bytes(), Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_document_symbols() {
+        let (_, replies) = session(&[
+            open("let x = 1;\nlet f = fn(y) { let z = y; z };"),
+            request(1, "textDocument/documentSymbol", document()),
+        ]);
+        let symbols = replies[1]["result"].as_array().unwrap();
+        let summary: Vec<(&str, u64)> = symbols
+            .iter()
+            .map(|s| (s["name"].as_str().unwrap(), s["kind"].as_u64().unwrap()))
+            .collect();
+        assert_eq!(summary, vec![("x", 13), ("f", 12)]);
+        assert_eq!(
+            symbols[1]["range"]["end"],
+            json!({"line": 1, "character": 31})
+        );
+    }
+
+    #[test]
+    fn test_semantic_tokens() {
+        let (_, replies) = session(&[
+            open("// f\nlet f = fn(x) { x };\nf(2)"),
+            request(1, "textDocument/semanticTokens/full", document()),
+        ]);
+        #[rustfmt::skip]
+        let expected = json!([
+            0, 0, 4, 6, 0, // comment
+            1, 0, 3, 0, 0, // let
+            0, 4, 1, 3, 0, // f
+            0, 2, 1, 5, 0, // =
+            0, 2, 2, 0, 0, // fn
+            0, 3, 1, 2, 0, // x
+            0, 5, 1, 2, 0, // x
+            1, 0, 1, 3, 0, // f
+            0, 2, 1, 4, 0, // 2
+        ]);
+        assert_eq!(replies[1]["result"]["data"], expected);
+
+        let (_, replies) = session(&[
+            open("1 + \"a\n\né\" 2"),
+            request(1, "textDocument/semanticTokens/full", document()),
+        ]);
+        #[rustfmt::skip]
+        let expected = json!([
+            0, 0, 1, 4, 0, // 1
+            0, 2, 1, 5, 0, // +
+            0, 2, 2, 7, 0, // "a
+            2, 0, 2, 7, 0, // é"
+            0, 3, 1, 4, 0, // 2
+        ]);
+        assert_eq!(replies[1]["result"]["data"], expected);
+    }
+}
@@ -0,0 +1,14 @@
+use std::{io, process};
+
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    match lsp::run(stdin.lock(), stdout.lock()) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("lsp: {}", e);
+            process::exit(1);
+        }
+    }
+}
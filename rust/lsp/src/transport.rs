@@ -0,0 +1,69 @@
+//! LSP base protocol: JSON-RPC messages framed by a `Content-Length` header.
+
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+/// The largest body accepted, so a bad `Content-Length` cannot make the
+/// server allocate without bound.
+pub const MAX_CONTENT_LENGTH: usize = 64 << 20;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the next message, or `None` once the input is closed.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let Some(body) = read_body(input)? else {
+        return Ok(None);
+    };
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| invalid(e.to_string()))
+}
+
+/// Reads the body of the next message without parsing it, or `None` once
+/// the input is closed. Errors here lose track of where messages start.
+pub fn read_body(input: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return match length {
+                None => Ok(None),
+                Some(_) => Err(invalid(String::from("input ended inside a header"))),
+            };
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                let value = value.trim();
+                length = Some(
+                    value
+                        .parse::<usize>()
+                        .map_err(|_| invalid(format!("bad Content-Length '{}'", value)))?,
+                );
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| invalid(String::from("missing Content-Length header")))?;
+    if length > MAX_CONTENT_LENGTH {
+        return Err(invalid(format!(
+            "Content-Length {} exceeds the limit of {} bytes",
+            length, MAX_CONTENT_LENGTH
+        )));
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+pub fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
@@ -0,0 +1,20 @@
+//! Parsing throughput and allocations per token.
+//!
+//! Run with `cargo bench -p parser`. Tokens borrow their text, so the
+//! allocations left are the tree's own boxes and vectors.
+
+use std::hint::black_box;
+
+use lexer::Lexer;
+use parser::Parser;
+
+
+fn main() {
+    let source = benchmark::source();
+    let tokens = Lexer::new(&source).count();
+    benchmark::measure("parse", || {
+        let mut parser = Parser::new(Lexer::new(&source));
+        black_box(parser.parse_program());
+        tokens
+    });
+}
@@ -1,84 +1,700 @@
+//! Syntax tree produced by the parser.
+//!
+//! With the `serde` feature every node can be written to and read back from
+//! JSON. The schema is:
+//!
+//! - tokens are objects tagged by `"kind"` (see `lexer::tokens::Token`); the
+//!   `location` they carry is the span start of the node they belong to
+//! - `Program` is `{"statements": [Statement, ...]}`
+//! - `Statement` and `Expression` are objects tagged by `"type"` with the
+//!   variant name; struct variants hold their fields by name, e.g.
+//!   `{"type":"Let","token":{..},"identifier":Expression,"value":Expression}`
+//! - token-only expressions (`Identifier`, `Integer`, `Boolean`, `String`) merge the
+//!   token into the node: `{"type":"Identifier","kind":"Ident","location":4,"raw":"x"}`,
+//!   and so do the token-only statements `Break` and `Continue`
+//! - nodes closed by a delimiter (`Block`, `Call`, `Array`, `Hash`,
+//!   `Index` and array types) have an `"end"`, the byte offset just past
+//!   it. A node's span runs from the `location` of its first token to its
+//!   `end`, or to the end of its last child if it has none, where a token
+//!   ends after its source text; `Expression::span` and the like work
+//!   this out
+//! - optional children (`If::alternative`) are `null` when absent
+//! - hash pairs are two element arrays `[key, value]`
+//! - type annotations are left out when a node has none, so
+//!   unannotated programs read and write the same JSON as before they
+//!   existed
+//!
+//! Field names follow the Rust structs below and are part of the stable format.
+
+use std::{fmt, ops::Range};
+
 use lexer::tokens::Token;
 
 #[derive(Debug)]
-pub struct Let {
-    pub token: Token,
-    pub identifier: Expression,
-    pub value: Expression,
-}
-
-#[derive(Debug)]
-pub struct Return {
-    pub token: Token,
-    pub return_value: Expression,
-}
-
-#[derive(Debug)]
-pub enum Statement {
-    Let(Let),
-    Return(Return),
-}
-
-#[derive(Debug)]
-pub struct Program {
-    pub statements: Vec<Statement>,
-}
-
-#[derive(Debug)]
-pub enum Expression {
-    Identifier(Token),
-}
-
-#[derive(Debug)]
-pub enum Node {
-    Program(Program),
-    Statement(Statement),
-    Expression(Expression),
-}
-
-// implementations
-// pub trait Literable {
-//     fn literal(&self) -> String;
-// }
-// impl Literable for Program {
-//     fn literal(&self) -> String {
-//         if self.statements.len() > 0 {
-//             format!("{:?}", self.statements[0])
-//         } else {
-//             String::from("")
-//         }
-//     }
-// }
-// impl Literable for Token {
-//     fn literal(&self) -> String {
-//         match self {
-//             Self::Illegal { raw, .. } => raw.clone(),
-//             Self::EOF => String::from("END"),
-//             Self::Ident { raw, .. } => raw.clone(),
-//             Self::Int { value, .. } => format!("{}", value),
-//             Self::Assign { .. } => String::from("="),
-//             Self::Plus { .. } => String::from("+"),
-//             Self::Minus { .. } => String::from("-"),
-//             Self::Multiply { .. } => String::from("*"),
-//             Self::Divide { .. } => String::from("/"),
-//             Self::Negation { .. } => String::from("!"),
-//             Self::Equals { .. } => String::from("=="),
-//             Self::NotEquals { .. } => String::from("!="),
-//             Self::LessThan { .. } => String::from("<"),
-//             Self::GreaterThan { .. } => String::from(">"),
-//             Self::Comma { .. } => String::from(","),
-//             Self::Semicolon { .. } => String::from(";"),
-//             Self::LeftParen { .. } => String::from("("),
-//             Self::RightParen { .. } => String::from(")"),
-//             Self::LeftBrace { .. } => String::from("{"),
-//             Self::RightBrace { .. } => String::from("}"),
-//             Self::Function { .. } => String::from("fn"),
-//             Self::Let { .. } => String::from("let"),
-//             Self::True { .. } => String::from("true"),
-//             Self::False { .. } => String::from("false"),
-//             Self::If { .. } => String::from("if"),
-//             Self::Else { .. } => String::from("else"),
-//             Self::Return { .. } => String::from("return"),
-//         }
-//     }
-// }
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "'de: 'a"))
+)]
+pub struct Let<'a> {
+    pub token: Token<'a>,
+    pub identifier: Expression<'a>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub annotation: Option<TypeAnnotation<'a>>,
+    pub value: Expression<'a>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "'de: 'a"))
+)]
+pub struct Return<'a> {
+    pub token: Token<'a>,
+    pub return_value: Expression<'a>,
+}
+
+/// `throw value;`
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "'de: 'a"))
+)]
+pub struct Throw<'a> {
+    pub token: Token<'a>,
+    pub value: Expression<'a>,
+}
+
+/// `while (condition) { body }`
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "'de: 'a"))
+)]
+pub struct While<'a> {
+    pub token: Token<'a>,
+    pub condition: Expression<'a>,
+    pub body: Block<'a>,
+}
+
+/// `for (variable in iterable) { body }`. The variable is an `Identifier`,
+/// or an `Array` of them that each item is destructured into.
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "'de: 'a"))
+)]
+pub struct For<'a> {
+    pub token: Token<'a>,
+    pub variable: Expression<'a>,
+    pub iterable: Expression<'a>,
+    pub body: Block<'a>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "'de: 'a"))
+)]
+pub struct ExpressionStatement<'a> {
+    pub token: Token<'a>,
+    pub expression: Expression<'a>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "'de: 'a"))
+)]
+pub struct Block<'a> {
+    pub token: Token<'a>,
+    pub statements: Vec<Statement<'a>>,
+    /// Byte offset just past the closing `}`.
+    pub end: usize,
+}
+
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", bound(deserialize = "'de: 'a"))
+)]
+pub enum Statement<'a> {
+    Let(Let<'a>),
+    Return(Return<'a>),
+    Throw(Throw<'a>),
+    While(While<'a>),
+    For(For<'a>),
+    /// `break;`, only inside a loop.
+    Break(Token<'a>),
+    /// `continue;`, only inside a loop.
+    Continue(Token<'a>),
+    Expression(ExpressionStatement<'a>),
+}
+
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "'de: 'a"))
+)]
+pub struct Program<'a> {
+    pub statements: Vec<Statement<'a>>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "'de: 'a"))
+)]
+pub struct Prefix<'a> {
+    pub token: Token<'a>,
+    pub right: Box<Expression<'a>>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "'de: 'a"))
+)]
+pub struct Infix<'a> {
+    pub token: Token<'a>,
+    pub left: Box<Expression<'a>>,
+    pub right: Box<Expression<'a>>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "'de: 'a"))
+)]
+pub struct If<'a> {
+    pub token: Token<'a>,
+    pub condition: Box<Expression<'a>>,
+    pub consequence: Block<'a>,
+    pub alternative: Option<Block<'a>>,
+}
+
+/// `try { body } catch (parameter) { handler }`. The parameter is an
+/// `Identifier`.
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "'de: 'a"))
+)]
+pub struct Try<'a> {
+    pub token: Token<'a>,
+    pub body: Block<'a>,
+    pub parameter: Box<Expression<'a>>,
+    pub handler: Block<'a>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "'de: 'a"))
+)]
+pub struct FunctionLiteral<'a> {
+    pub token: Token<'a>,
+    pub parameters: Vec<Expression<'a>>,
+    /// The annotation of each parameter, in the same order, or empty when
+    /// no parameter has one. See `parameter_type`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub parameter_types: Vec<Option<TypeAnnotation<'a>>>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub return_type: Option<TypeAnnotation<'a>>,
+    pub body: Block<'a>,
+}
+
+impl<'a> FunctionLiteral<'a> {
+    /// The annotation of the parameter at `index`, if it has one.
+    pub fn parameter_type(&self, index: usize) -> Option<&TypeAnnotation<'a>> {
+        self.parameter_types.get(index).and_then(Option::as_ref)
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "'de: 'a"))
+)]
+pub struct Call<'a> {
+    pub token: Token<'a>,
+    pub function: Box<Expression<'a>>,
+    pub arguments: Vec<Expression<'a>>,
+    /// Byte offset just past the closing `)`.
+    pub end: usize,
+}
+
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "'de: 'a"))
+)]
+pub struct ArrayLiteral<'a> {
+    pub token: Token<'a>,
+    pub elements: Vec<Expression<'a>>,
+    /// Byte offset just past the closing `]`.
+    pub end: usize,
+}
+
+/// `{key: value, ...}`. Pairs keep their source order.
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "'de: 'a"))
+)]
+pub struct HashLiteral<'a> {
+    pub token: Token<'a>,
+    pub pairs: Vec<(Expression<'a>, Expression<'a>)>,
+    /// Byte offset just past the closing `}`.
+    pub end: usize,
+}
+
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "'de: 'a"))
+)]
+pub struct Index<'a> {
+    pub token: Token<'a>,
+    pub left: Box<Expression<'a>>,
+    pub index: Box<Expression<'a>>,
+    /// Byte offset just past the closing `]`.
+    pub end: usize,
+}
+
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", bound(deserialize = "'de: 'a"))
+)]
+pub enum Expression<'a> {
+    Identifier(Token<'a>),
+    Integer(Token<'a>),
+    Boolean(Token<'a>),
+    String(Token<'a>),
+    Prefix(Prefix<'a>),
+    Infix(Infix<'a>),
+    If(If<'a>),
+    Try(Try<'a>),
+    Function(FunctionLiteral<'a>),
+    Call(Call<'a>),
+    Array(ArrayLiteral<'a>),
+    Hash(HashLiteral<'a>),
+    Index(Index<'a>),
+}
+
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "'de: 'a"))
+)]
+pub struct ArrayType<'a> {
+    pub token: Token<'a>,
+    pub element: Box<TypeAnnotation<'a>>,
+    /// Byte offset just past the closing `]`.
+    pub end: usize,
+}
+
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound(deserialize = "'de: 'a"))
+)]
+pub struct FunctionType<'a> {
+    pub token: Token<'a>,
+    pub parameters: Vec<TypeAnnotation<'a>>,
+    pub result: Box<TypeAnnotation<'a>>,
+}
+
+/// A type written after `:` or `->`: `int`, `[string]`,
+/// `fn(int, bool) -> int`.
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", bound(deserialize = "'de: 'a"))
+)]
+pub enum TypeAnnotation<'a> {
+    /// One of `TYPE_NAMES`.
+    Named(Token<'a>),
+    Array(ArrayType<'a>),
+    Function(FunctionType<'a>),
+}
+
+/// The names a `TypeAnnotation::Named` can have.
+pub const TYPE_NAMES: &[&str] = &["int", "bool", "string", "null"];
+
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", bound(deserialize = "'de: 'a"))
+)]
+pub enum Node<'a> {
+    Program(Program<'a>),
+    Statement(Statement<'a>),
+    Expression(Expression<'a>),
+}
+
+// Spans are worked out from the first token of a node and the `end` of the
+// node or of its last child, so are not stored twice.
+
+/// The span of a token in the tree, which is never `EOF`.
+fn token_span(token: &Token) -> Range<usize> {
+    token.span().unwrap_or_default()
+}
+
+impl Statement<'_> {
+    /// The bytes of source the statement was parsed from, up to but not
+    /// including a trailing `;`.
+    pub fn span(&self) -> Range<usize> {
+        let (token, end) = match self {
+            Statement::Let(l) => (&l.token, l.value.span().end),
+            Statement::Return(r) => (&r.token, r.return_value.span().end),
+            Statement::Throw(t) => (&t.token, t.value.span().end),
+            Statement::While(w) => (&w.token, w.body.end),
+            Statement::For(f) => (&f.token, f.body.end),
+            Statement::Break(token) | Statement::Continue(token) => (token, token_span(token).end),
+            Statement::Expression(e) => return e.expression.span(),
+        };
+        token_span(token).start..end
+    }
+}
+
+impl Block<'_> {
+    /// From the `{` to just past the `}`.
+    pub fn span(&self) -> Range<usize> {
+        token_span(&self.token).start..self.end
+    }
+}
+
+impl<'a> Expression<'a> {
+    /// The bytes of source the expression was parsed from, without any
+    /// parentheses around it. Walks down the tree without recursing, so is
+    /// safe on trees of any depth.
+    pub fn span(&self) -> Range<usize> {
+        let mut first = self;
+        let start = loop {
+            first = match first {
+                Expression::Infix(i) => &i.left,
+                Expression::Call(c) => &c.function,
+                Expression::Index(i) => &i.left,
+                other => break token_span(other.token()).start,
+            };
+        };
+        let mut last = self;
+        let end = loop {
+            last = match last {
+                Expression::Prefix(p) => &p.right,
+                Expression::Infix(i) => &i.right,
+                Expression::If(i) => break i.alternative.as_ref().unwrap_or(&i.consequence).end,
+                Expression::Try(t) => break t.handler.end,
+                Expression::Function(f) => break f.body.end,
+                Expression::Call(c) => break c.end,
+                Expression::Array(a) => break a.end,
+                Expression::Hash(h) => break h.end,
+                Expression::Index(i) => break i.end,
+                other => break token_span(other.token()).end,
+            };
+        };
+        start..end
+    }
+
+    /// The token the node was built from: the operator of an infix
+    /// expression, the `(` of a call and the `[` of an index, the first
+    /// token of the others.
+    pub fn token(&self) -> &Token<'a> {
+        match self {
+            Expression::Identifier(token)
+            | Expression::Integer(token)
+            | Expression::Boolean(token)
+            | Expression::String(token) => token,
+            Expression::Prefix(p) => &p.token,
+            Expression::Infix(i) => &i.token,
+            Expression::If(i) => &i.token,
+            Expression::Try(t) => &t.token,
+            Expression::Function(f) => &f.token,
+            Expression::Call(c) => &c.token,
+            Expression::Array(a) => &a.token,
+            Expression::Hash(h) => &h.token,
+            Expression::Index(i) => &i.token,
+        }
+    }
+}
+
+impl TypeAnnotation<'_> {
+    /// The bytes of source the type was parsed from.
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            TypeAnnotation::Named(token) => token_span(token),
+            TypeAnnotation::Array(a) => token_span(&a.token).start..a.end,
+            TypeAnnotation::Function(f) => token_span(&f.token).start..f.result.span().end,
+        }
+    }
+}
+
+// Display renders canonical Monkey source: every prefix and infix expression
+// is wrapped in parentheses and every statement is terminated, so the output
+// parses back into the same tree.
+
+fn write_separated<T: fmt::Display>(f: &mut fmt::Formatter<'_>, items: &[T]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Let<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "let {}", self.identifier)?;
+        if let Some(annotation) = &self.annotation {
+            write!(f, ": {}", annotation)?;
+        }
+        write!(f, " = {};", self.value)
+    }
+}
+
+impl fmt::Display for Return<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "return {};", self.return_value)
+    }
+}
+
+impl fmt::Display for Throw<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "throw {};", self.value)
+    }
+}
+
+impl fmt::Display for While<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // prefix and infix conditions already carry their own parentheses
+        match &self.condition {
+            c @ (Expression::Prefix(_) | Expression::Infix(_)) => write!(f, "while {} ", c)?,
+            c => write!(f, "while ({}) ", c)?,
+        }
+        write!(f, "{}", self.body)
+    }
+}
+
+impl fmt::Display for For<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "for ({} in {}) {}",
+            self.variable, self.iterable, self.body
+        )
+    }
+}
+
+impl fmt::Display for ExpressionStatement<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{};", self.expression)
+    }
+}
+
+impl fmt::Display for Block<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        for stmt in &self.statements {
+            write!(f, " {}", stmt)?;
+        }
+        write!(f, " }}")
+    }
+}
+
+impl fmt::Display for Statement<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Statement::Let(l) => l.fmt(f),
+            Statement::Return(r) => r.fmt(f),
+            Statement::Throw(t) => t.fmt(f),
+            Statement::While(w) => w.fmt(f),
+            Statement::For(l) => l.fmt(f),
+            Statement::Break(t) | Statement::Continue(t) => write!(f, "{};", t),
+            Statement::Expression(e) => e.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for Program<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, stmt) in self.statements.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", stmt)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Prefix<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}{})", self.token, self.right)
+    }
+}
+
+impl fmt::Display for Infix<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({} {} {})", self.left, self.token, self.right)
+    }
+}
+
+impl fmt::Display for If<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // prefix and infix conditions already carry their own parentheses
+        match self.condition.as_ref() {
+            c @ (Expression::Prefix(_) | Expression::Infix(_)) => write!(f, "if {} ", c)?,
+            c => write!(f, "if ({}) ", c)?,
+        }
+        write!(f, "{}", self.consequence)?;
+        if let Some(alternative) = &self.alternative {
+            write!(f, " else {}", alternative)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Try<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "try {} catch ({}) {}",
+            self.body, self.parameter, self.handler
+        )
+    }
+}
+
+impl fmt::Display for FunctionLiteral<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fn(")?;
+        for (i, parameter) in self.parameters.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", parameter)?;
+            if let Some(annotation) = self.parameter_type(i) {
+                write!(f, ": {}", annotation)?;
+            }
+        }
+        write!(f, ")")?;
+        if let Some(annotation) = &self.return_type {
+            write!(f, " -> {}", annotation)?;
+        }
+        write!(f, " {}", self.body)
+    }
+}
+
+impl fmt::Display for Call<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", self.function)?;
+        write_separated(f, &self.arguments)?;
+        write!(f, ")")
+    }
+}
+
+impl fmt::Display for ArrayLiteral<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        write_separated(f, &self.elements)?;
+        write!(f, "]")
+    }
+}
+
+impl fmt::Display for HashLiteral<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        for (i, (key, value)) in self.pairs.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {}", key, value)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl fmt::Display for Index<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}[{}])", self.left, self.index)
+    }
+}
+
+impl fmt::Display for Expression<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Identifier(t)
+            | Expression::Integer(t)
+            | Expression::Boolean(t)
+            | Expression::String(t) => t.fmt(f),
+            Expression::Prefix(p) => p.fmt(f),
+            Expression::Infix(i) => i.fmt(f),
+            Expression::If(i) => i.fmt(f),
+            Expression::Try(t) => t.fmt(f),
+            Expression::Function(func) => func.fmt(f),
+            Expression::Call(c) => c.fmt(f),
+            Expression::Array(a) => a.fmt(f),
+            Expression::Hash(h) => h.fmt(f),
+            Expression::Index(i) => i.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for TypeAnnotation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeAnnotation::Named(t) => t.fmt(f),
+            TypeAnnotation::Array(a) => write!(f, "[{}]", a.element),
+            TypeAnnotation::Function(func) => {
+                write!(f, "fn(")?;
+                write_separated(f, &func.parameters)?;
+                write!(f, ") -> {}", func.result)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Node<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Program(p) => p.fmt(f),
+            Node::Statement(s) => s.fmt(f),
+            Node::Expression(e) => e.fmt(f),
+        }
+    }
+}
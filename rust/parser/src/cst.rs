@@ -0,0 +1,1224 @@
+//! Lossless concrete syntax tree.
+//!
+//! Every byte of the input is kept: whitespace and comments are trivia
+//! attached to the neighbouring token, so printing a tree gives back the exact
+//! source it was parsed from. A token owns the trivia after it up to and
+//! including the end of its line; everything else is leading trivia of the
+//! next token. Trivia after the last token belongs to `Token::EOF`, which is
+//! always the last child of the `Program` node.
+//!
+//! Grouping parentheses and separators have nodes and tokens of their own
+//! here; `SyntaxTree::to_ast` drops them to produce the same `ast::Program`
+//! as `Parser::parse_program`.
+
+use std::{fmt, mem, ops::Range};
+
+use lexer::{
+    tokens::{Token, TokenKind},
+    Lexer,
+};
+
+use crate::{
+    ast::{
+        ArrayLiteral, ArrayType, Block, Call, Expression, ExpressionStatement, For,
+        FunctionLiteral, FunctionType, HashLiteral, If, Index, Infix, Let, Prefix, Program, Return,
+        Statement, Throw, Try, TypeAnnotation, While, TYPE_NAMES,
+    },
+    describe, outside_loop, token_precedence, too_deep, unexpected, unknown_type, ParserError,
+    Precedence, MAX_NESTING,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    Comment,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Trivia<'a> {
+    pub kind: TriviaKind,
+    pub text: &'a str,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxToken<'a> {
+    pub token: Token<'a>,
+    /// The token's own source text; empty for `Token::EOF`.
+    pub text: &'a str,
+    pub leading: Vec<Trivia<'a>>,
+    pub trailing: Vec<Trivia<'a>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    Program,
+    Let,
+    Return,
+    Throw,
+    /// `while`, the condition in parentheses, the body and an optional `;`.
+    While,
+    /// `for`, `(`, the variable, `in`, the iterable, `)`, the body and an
+    /// optional `;`. The variable is an `Identifier` or an `Array` of them.
+    For,
+    Break,
+    Continue,
+    ExpressionStatement,
+    Block,
+    Identifier,
+    Integer,
+    Boolean,
+    String,
+    Prefix,
+    Infix,
+    /// A parenthesized expression: `(`, the expression, `)`.
+    Paren,
+    If,
+    /// `try`, the body, `catch`, the parameter in parentheses, the handler.
+    Try,
+    Function,
+    /// A function's parameter list, parentheses and commas included.
+    Parameters,
+    Call,
+    /// A call's argument list, parentheses and commas included.
+    Arguments,
+    /// An array literal, brackets and commas included.
+    Array,
+    /// A hash literal: `{`, each key and value node with the `:` between
+    /// them and the `,` after, `}`.
+    Hash,
+    /// The indexed expression, `[`, the index, `]`.
+    Index,
+    /// `:` or `->` and the type after it.
+    Annotation,
+    /// `int`, `bool`, `string` or `null`.
+    NamedType,
+    /// `[`, the element type, `]`.
+    ArrayType,
+    /// `fn`, the parameter types in parentheses, `->`, the result type.
+    FunctionType,
+    /// Tokens skipped while recovering from a parse error.
+    Error,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SyntaxElement<'a> {
+    Node(SyntaxNode<'a>),
+    Token(SyntaxToken<'a>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxNode<'a> {
+    pub kind: NodeKind,
+    pub children: Vec<SyntaxElement<'a>>,
+}
+
+pub struct SyntaxTree<'a> {
+    pub root: SyntaxNode<'a>,
+    /// Every token of the tree in source order, ending with `Token::EOF`.
+    pub tokens: Vec<SyntaxToken<'a>>,
+    pub errors: Vec<ParserError>,
+}
+
+/// Lexes `source` into tokens carrying their trivia, ending with `Token::EOF`.
+pub fn tokenize(source: &str) -> Vec<SyntaxToken<'_>> {
+    tokenize_from(source, 0, |_| false)
+}
+
+/// Lexes from `offset`, which must be where some token's leading trivia
+/// starts. Before keeping each token after the first, `resume` is asked
+/// whether to stop at the offset where that token's leading trivia starts;
+/// the tokens before it are complete at that point.
+pub(crate) fn tokenize_from(
+    source: &str,
+    offset: usize,
+    mut resume: impl FnMut(usize) -> bool,
+) -> Vec<SyntaxToken<'_>> {
+    let mut tokens: Vec<SyntaxToken> = Vec::new();
+    let mut pending = Vec::new();
+    let mut end = offset;
+
+    let mut lex = Lexer::with_comments(source);
+    lex.seek(offset);
+    loop {
+        let token = lex.next_token();
+        let start = token.location().unwrap_or(source.len());
+        if start > end {
+            pending.push(Trivia {
+                kind: TriviaKind::Whitespace,
+                text: &source[end..start],
+            });
+        }
+        end = if token == Token::EOF {
+            start
+        } else {
+            lex.position()
+        };
+
+        if let Token::Comment { raw, .. } = token {
+            pending.push(Trivia {
+                kind: TriviaKind::Comment,
+                text: &source[start..start + raw.len()],
+            });
+            end = start + raw.len();
+            continue;
+        }
+
+        let mut leading = Vec::new();
+        let mut trivia = pending.drain(..);
+        if let Some(previous) = tokens.last_mut() {
+            for piece in trivia.by_ref() {
+                match piece.text.find('\n') {
+                    Some(newline) if piece.kind == TriviaKind::Whitespace => {
+                        let (line_end, rest) = piece.text.split_at(newline + 1);
+                        previous.trailing.push(Trivia {
+                            kind: TriviaKind::Whitespace,
+                            text: line_end,
+                        });
+                        if !rest.is_empty() {
+                            leading.push(Trivia {
+                                kind: TriviaKind::Whitespace,
+                                text: rest,
+                            });
+                        }
+                        break;
+                    }
+                    _ => previous.trailing.push(piece),
+                }
+            }
+        }
+        leading.extend(trivia);
+
+        if !tokens.is_empty() {
+            let leading_len: usize = leading.iter().map(|t| t.text.len()).sum();
+            if resume(start - leading_len) {
+                return tokens;
+            }
+        }
+
+        let at_end = token == Token::EOF;
+        tokens.push(SyntaxToken {
+            token,
+            text: &source[start..end],
+            leading,
+            trailing: Vec::new(),
+        });
+        if at_end {
+            return tokens;
+        }
+    }
+}
+
+/// Parses `source` into a lossless tree. Malformed statements become `Error`
+/// nodes, so the tree always covers the whole input.
+pub fn parse(source: &str) -> SyntaxTree<'_> {
+    let mut parser = CstParser::new(tokenize(source), 0);
+    let root = parser.program();
+    SyntaxTree {
+        root,
+        tokens: parser.tokens,
+        errors: parser.errors,
+    }
+}
+
+impl fmt::Display for Trivia<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.text)
+    }
+}
+
+impl fmt::Display for SyntaxToken<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for trivia in &self.leading {
+            trivia.fmt(f)?;
+        }
+        f.write_str(self.text)?;
+        for trivia in &self.trailing {
+            trivia.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SyntaxElement<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyntaxElement::Node(node) => node.fmt(f),
+            SyntaxElement::Token(token) => token.fmt(f),
+        }
+    }
+}
+
+/// Prints the node's exact source text, trivia included.
+impl fmt::Display for SyntaxNode<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for child in &self.children {
+            child.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> SyntaxNode<'a> {
+    pub(crate) fn new(kind: NodeKind) -> Self {
+        SyntaxNode {
+            kind,
+            children: Vec::new(),
+        }
+    }
+
+    /// Child nodes, skipping tokens.
+    pub fn nodes(&self) -> impl Iterator<Item = &SyntaxNode<'a>> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// Child tokens, skipping nodes.
+    pub fn tokens(&self) -> impl Iterator<Item = &SyntaxToken<'a>> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Token(token) => Some(token),
+            SyntaxElement::Node(_) => None,
+        })
+    }
+
+    /// The last token anywhere below this node.
+    pub fn last_token(&self) -> Option<&SyntaxToken<'a>> {
+        self.children.iter().rev().find_map(|child| match child {
+            SyntaxElement::Token(token) => Some(token),
+            SyntaxElement::Node(node) => node.last_token(),
+        })
+    }
+
+    /// The first token anywhere below this node.
+    pub fn first_token(&self) -> Option<&SyntaxToken<'a>> {
+        self.children.iter().find_map(|child| match child {
+            SyntaxElement::Token(token) => Some(token),
+            SyntaxElement::Node(node) => node.first_token(),
+        })
+    }
+
+    /// From the start of the node's first token to the end of its last,
+    /// trivia excluded; empty for a node without tokens.
+    pub fn span(&self) -> Range<usize> {
+        match (self.first_token(), self.last_token()) {
+            (Some(first), Some(last)) => {
+                let start = first.token.span().unwrap_or_default().start;
+                start..last.token.span().map_or(start, |span| span.end)
+            }
+            _ => 0..0,
+        }
+    }
+}
+
+pub(crate) struct CstParser<'a> {
+    pub(crate) tokens: Vec<SyntaxToken<'a>>,
+    pub(crate) position: usize,
+    pub(crate) errors: Vec<ParserError>,
+    /// How many loops of the current function the parser is inside.
+    loops: usize,
+    /// How many expressions, blocks and types the parser is inside.
+    depth: usize,
+    /// The deepest level the innermost expression being parsed reaches so
+    /// far, as in `Parser`.
+    reach: usize,
+}
+
+impl<'a> CstParser<'a> {
+    pub(crate) fn new(tokens: Vec<SyntaxToken<'a>>, position: usize) -> Self {
+        CstParser {
+            tokens,
+            position,
+            errors: Vec::new(),
+            loops: 0,
+            depth: 0,
+            reach: 0,
+        }
+    }
+
+    fn current(&self) -> &Token<'a> {
+        &self.tokens[self.position].token
+    }
+
+    pub(crate) fn at(&self, kind: TokenKind) -> bool {
+        self.current().kind() == kind
+    }
+
+    /// Moves the current token into `node`. `Token::EOF` is never consumed.
+    pub(crate) fn bump(&mut self, node: &mut SyntaxNode<'a>) {
+        node.children
+            .push(SyntaxElement::Token(self.tokens[self.position].clone()));
+        if !self.at(TokenKind::EOF) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind, node: &mut SyntaxNode<'a>) -> Result<(), ParserError> {
+        if self.at(kind) {
+            self.bump(node);
+            Ok(())
+        } else {
+            Err(unexpected(self.current(), &describe(kind)))
+        }
+    }
+
+    /// Runs `parse` one level deeper, failing at the current token if that
+    /// is past `MAX_NESTING`.
+    fn nested(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<SyntaxNode<'a>, ParserError>,
+    ) -> Result<SyntaxNode<'a>, ParserError> {
+        if self.depth == MAX_NESTING {
+            return Err(too_deep(self.current()));
+        }
+        self.depth += 1;
+        self.reach = self.reach.max(self.depth);
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn eat_semicolon(&mut self, node: &mut SyntaxNode<'a>) {
+        if self.at(TokenKind::Semicolon) {
+            self.bump(node);
+        }
+    }
+
+    fn program(&mut self) -> SyntaxNode<'a> {
+        let mut program = SyntaxNode::new(NodeKind::Program);
+        while !self.at(TokenKind::EOF) {
+            let stmt = self.top_level_statement();
+            program.children.push(SyntaxElement::Node(stmt));
+        }
+        self.bump(&mut program);
+        program
+    }
+
+    /// A statement of the program, or an `Error` node if it is malformed.
+    pub(crate) fn top_level_statement(&mut self) -> SyntaxNode<'a> {
+        let start = self.position;
+        match self.statement() {
+            Ok(stmt) => stmt,
+            Err(e) => {
+                self.errors.push(e);
+                let failed = self.position;
+                self.recover(start, failed, false)
+            }
+        }
+    }
+
+    /// Wraps everything from `start` in an `Error` node, through the first
+    /// `;` from `failed`, where the statement went wrong, on, skipping any
+    /// inside a `{` the statement opened. A `;` before `failed` belongs to
+    /// something nested in the statement. In a block, the node stops before
+    /// the `}` that closes it, as `Parser::synchronize` does.
+    fn recover(&mut self, start: usize, failed: usize, in_block: bool) -> SyntaxNode<'a> {
+        let mut error = SyntaxNode::new(NodeKind::Error);
+        let mut braces = 0usize;
+        self.position = start;
+        loop {
+            match self.current().kind() {
+                TokenKind::EOF => break,
+                _ if self.position < failed => {}
+                TokenKind::Semicolon if braces == 0 => {
+                    self.bump(&mut error);
+                    break;
+                }
+                TokenKind::RightBrace if braces == 0 && in_block => break,
+                _ => {}
+            }
+            match self.current().kind() {
+                TokenKind::LeftBrace => braces += 1,
+                TokenKind::RightBrace => braces = braces.saturating_sub(1),
+                _ => {}
+            }
+            self.bump(&mut error);
+        }
+        error
+    }
+
+    fn statement(&mut self) -> Result<SyntaxNode<'a>, ParserError> {
+        match self.current().kind() {
+            TokenKind::Let => {
+                let mut node = SyntaxNode::new(NodeKind::Let);
+                self.bump(&mut node);
+                if !self.at(TokenKind::Ident) {
+                    return Err(unexpected(self.current(), &describe(TokenKind::Ident)));
+                }
+                let identifier = self.leaf(NodeKind::Identifier);
+                node.children.push(SyntaxElement::Node(identifier));
+                if self.at(TokenKind::Colon) {
+                    let annotation = self.annotation()?;
+                    node.children.push(SyntaxElement::Node(annotation));
+                    self.expect(TokenKind::Assign, &mut node)?;
+                } else if self.at(TokenKind::Assign) {
+                    self.bump(&mut node);
+                } else {
+                    return Err(unexpected(self.current(), "':' or '='"));
+                }
+                let value = self.expression(Precedence::Lowest)?;
+                node.children.push(SyntaxElement::Node(value));
+                self.eat_semicolon(&mut node);
+                Ok(node)
+            }
+            TokenKind::Return | TokenKind::Throw => {
+                let kind = if self.at(TokenKind::Return) {
+                    NodeKind::Return
+                } else {
+                    NodeKind::Throw
+                };
+                let mut node = SyntaxNode::new(kind);
+                self.bump(&mut node);
+                let value = self.expression(Precedence::Lowest)?;
+                node.children.push(SyntaxElement::Node(value));
+                self.eat_semicolon(&mut node);
+                Ok(node)
+            }
+            TokenKind::While => {
+                let mut node = self.leaf(NodeKind::While);
+                self.expect(TokenKind::LeftParen, &mut node)?;
+                let condition = self.expression(Precedence::Lowest)?;
+                node.children.push(SyntaxElement::Node(condition));
+                self.expect(TokenKind::RightParen, &mut node)?;
+                self.loops += 1;
+                let body = self.block();
+                self.loops -= 1;
+                node.children.push(SyntaxElement::Node(body?));
+                self.eat_semicolon(&mut node);
+                Ok(node)
+            }
+            TokenKind::For => {
+                let mut node = self.leaf(NodeKind::For);
+                self.expect(TokenKind::LeftParen, &mut node)?;
+                let variable = match self.current().kind() {
+                    TokenKind::Ident => self.leaf(NodeKind::Identifier),
+                    TokenKind::LeftBracket => {
+                        let mut pattern = self.leaf(NodeKind::Array);
+                        loop {
+                            if !self.at(TokenKind::Ident) {
+                                return Err(unexpected(
+                                    self.current(),
+                                    &describe(TokenKind::Ident),
+                                ));
+                            }
+                            let name = self.leaf(NodeKind::Identifier);
+                            pattern.children.push(SyntaxElement::Node(name));
+                            if !self.at(TokenKind::Comma) {
+                                break;
+                            }
+                            self.bump(&mut pattern);
+                        }
+                        self.expect(TokenKind::RightBracket, &mut pattern)?;
+                        pattern
+                    }
+                    _ => return Err(unexpected(self.current(), "identifier or '['")),
+                };
+                node.children.push(SyntaxElement::Node(variable));
+                self.expect(TokenKind::In, &mut node)?;
+                let iterable = self.expression(Precedence::Lowest)?;
+                node.children.push(SyntaxElement::Node(iterable));
+                self.expect(TokenKind::RightParen, &mut node)?;
+                self.loops += 1;
+                let body = self.block();
+                self.loops -= 1;
+                node.children.push(SyntaxElement::Node(body?));
+                self.eat_semicolon(&mut node);
+                Ok(node)
+            }
+            TokenKind::Break | TokenKind::Continue => {
+                if self.loops == 0 {
+                    return Err(outside_loop(self.current()));
+                }
+                let kind = if self.at(TokenKind::Break) {
+                    NodeKind::Break
+                } else {
+                    NodeKind::Continue
+                };
+                let mut node = self.leaf(kind);
+                self.eat_semicolon(&mut node);
+                Ok(node)
+            }
+            _ => {
+                let mut node = SyntaxNode::new(NodeKind::ExpressionStatement);
+                let expression = self.expression(Precedence::Lowest)?;
+                node.children.push(SyntaxElement::Node(expression));
+                self.eat_semicolon(&mut node);
+                Ok(node)
+            }
+        }
+    }
+
+    fn leaf(&mut self, kind: NodeKind) -> SyntaxNode<'a> {
+        let mut node = SyntaxNode::new(kind);
+        self.bump(&mut node);
+        node
+    }
+
+    fn expression(&mut self, precedence: Precedence) -> Result<SyntaxNode<'a>, ParserError> {
+        self.nested(|parser| {
+            let depth = parser.depth;
+            let outer = mem::replace(&mut parser.reach, depth);
+            let expression = parser.operators(precedence, depth);
+            parser.reach = parser.reach.max(outer);
+            expression
+        })
+    }
+
+    /// An operand and the operators after it that bind tighter than
+    /// `precedence`, as the expression at nesting level `depth`.
+    fn operators(
+        &mut self,
+        precedence: Precedence,
+        depth: usize,
+    ) -> Result<SyntaxNode<'a>, ParserError> {
+        let mut left = match self.current().kind() {
+            TokenKind::Ident => self.leaf(NodeKind::Identifier),
+            TokenKind::Int => self.leaf(NodeKind::Integer),
+            TokenKind::True | TokenKind::False => self.leaf(NodeKind::Boolean),
+            TokenKind::String => self.leaf(NodeKind::String),
+            TokenKind::LeftBracket => {
+                let mut node = self.leaf(NodeKind::Array);
+                self.list(TokenKind::RightBracket, &mut node)?;
+                node
+            }
+            TokenKind::LeftBrace => self.hash_literal()?,
+            TokenKind::Negation | TokenKind::Minus => {
+                let mut node = self.leaf(NodeKind::Prefix);
+                let right = self.expression(Precedence::Prefix)?;
+                node.children.push(SyntaxElement::Node(right));
+                node
+            }
+            TokenKind::LeftParen => {
+                let mut node = self.leaf(NodeKind::Paren);
+                let inner = self.expression(Precedence::Lowest)?;
+                node.children.push(SyntaxElement::Node(inner));
+                self.expect(TokenKind::RightParen, &mut node)?;
+                node
+            }
+            TokenKind::If => self.if_expression()?,
+            TokenKind::Try => self.try_expression()?,
+            TokenKind::Function => self.function_literal()?,
+            _ => return Err(unexpected(self.current(), "expression")),
+        };
+
+        // levels below `depth` that `left` takes up
+        let mut height = self.reach - depth;
+        while !self.at(TokenKind::Semicolon) && precedence < token_precedence(self.current()) {
+            if depth + height >= MAX_NESTING {
+                return Err(too_deep(self.current()));
+            }
+            self.reach = depth;
+            let kind = match self.current().kind() {
+                TokenKind::LeftParen => NodeKind::Call,
+                TokenKind::LeftBracket => NodeKind::Index,
+                _ => NodeKind::Infix,
+            };
+            let mut node = SyntaxNode::new(kind);
+            let operator_precedence = token_precedence(self.current());
+            node.children.push(SyntaxElement::Node(left));
+            match kind {
+                NodeKind::Call => {
+                    let mut arguments = self.leaf(NodeKind::Arguments);
+                    self.list(TokenKind::RightParen, &mut arguments)?;
+                    node.children.push(SyntaxElement::Node(arguments));
+                }
+                NodeKind::Index => {
+                    self.bump(&mut node);
+                    let index = self.expression(Precedence::Lowest)?;
+                    node.children.push(SyntaxElement::Node(index));
+                    self.expect(TokenKind::RightBracket, &mut node)?;
+                }
+                _ => {
+                    self.bump(&mut node);
+                    let right = self.expression(operator_precedence)?;
+                    node.children.push(SyntaxElement::Node(right));
+                }
+            }
+            left = node;
+            height = (height + 1).max(self.reach - depth);
+            self.reach = depth + height;
+        }
+
+        Ok(left)
+    }
+
+    fn hash_literal(&mut self) -> Result<SyntaxNode<'a>, ParserError> {
+        let mut node = self.leaf(NodeKind::Hash);
+        while !self.at(TokenKind::RightBrace) {
+            let key = self.expression(Precedence::Lowest)?;
+            node.children.push(SyntaxElement::Node(key));
+            self.expect(TokenKind::Colon, &mut node)?;
+            let value = self.expression(Precedence::Lowest)?;
+            node.children.push(SyntaxElement::Node(value));
+            if !self.at(TokenKind::RightBrace) {
+                self.expect(TokenKind::Comma, &mut node)?;
+            }
+        }
+        self.bump(&mut node);
+        Ok(node)
+    }
+
+    fn if_expression(&mut self) -> Result<SyntaxNode<'a>, ParserError> {
+        let mut node = self.leaf(NodeKind::If);
+        self.expect(TokenKind::LeftParen, &mut node)?;
+        let condition = self.expression(Precedence::Lowest)?;
+        node.children.push(SyntaxElement::Node(condition));
+        self.expect(TokenKind::RightParen, &mut node)?;
+        let consequence = self.block()?;
+        node.children.push(SyntaxElement::Node(consequence));
+        if self.at(TokenKind::Else) {
+            self.bump(&mut node);
+            let alternative = self.block()?;
+            node.children.push(SyntaxElement::Node(alternative));
+        }
+        Ok(node)
+    }
+
+    fn try_expression(&mut self) -> Result<SyntaxNode<'a>, ParserError> {
+        let mut node = self.leaf(NodeKind::Try);
+        let body = self.block()?;
+        node.children.push(SyntaxElement::Node(body));
+        self.expect(TokenKind::Catch, &mut node)?;
+        self.expect(TokenKind::LeftParen, &mut node)?;
+        if !self.at(TokenKind::Ident) {
+            return Err(unexpected(self.current(), &describe(TokenKind::Ident)));
+        }
+        let parameter = self.leaf(NodeKind::Identifier);
+        node.children.push(SyntaxElement::Node(parameter));
+        self.expect(TokenKind::RightParen, &mut node)?;
+        let handler = self.block()?;
+        node.children.push(SyntaxElement::Node(handler));
+        Ok(node)
+    }
+
+    fn block(&mut self) -> Result<SyntaxNode<'a>, ParserError> {
+        self.nested(Self::block_inner)
+    }
+
+    fn block_inner(&mut self) -> Result<SyntaxNode<'a>, ParserError> {
+        let mut node = SyntaxNode::new(NodeKind::Block);
+        self.expect(TokenKind::LeftBrace, &mut node)?;
+        loop {
+            match self.current().kind() {
+                TokenKind::RightBrace => break,
+                TokenKind::EOF => return Err(unexpected(&Token::EOF, "'}'")),
+                _ => {}
+            }
+            // a malformed statement becomes an `Error` node here, so the
+            // rest of the block still parses
+            let start = self.position;
+            let stmt = match self.statement() {
+                Ok(stmt) => stmt,
+                Err(e) => {
+                    self.errors.push(e);
+                    let failed = self.position;
+                    self.recover(start, failed, true)
+                }
+            };
+            node.children.push(SyntaxElement::Node(stmt));
+        }
+        self.bump(&mut node);
+        Ok(node)
+    }
+
+    fn function_literal(&mut self) -> Result<SyntaxNode<'a>, ParserError> {
+        let mut node = self.leaf(NodeKind::Function);
+
+        let mut parameters = SyntaxNode::new(NodeKind::Parameters);
+        self.expect(TokenKind::LeftParen, &mut parameters)?;
+        if !self.at(TokenKind::RightParen) {
+            loop {
+                if !self.at(TokenKind::Ident) {
+                    return Err(unexpected(self.current(), &describe(TokenKind::Ident)));
+                }
+                let parameter = self.leaf(NodeKind::Identifier);
+                parameters.children.push(SyntaxElement::Node(parameter));
+                if self.at(TokenKind::Colon) {
+                    let annotation = self.annotation()?;
+                    parameters.children.push(SyntaxElement::Node(annotation));
+                }
+                if self.at(TokenKind::Comma) {
+                    self.bump(&mut parameters);
+                } else {
+                    break;
+                }
+            }
+        }
+        self.expect(TokenKind::RightParen, &mut parameters)?;
+        node.children.push(SyntaxElement::Node(parameters));
+        if self.at(TokenKind::Arrow) {
+            let annotation = self.annotation()?;
+            node.children.push(SyntaxElement::Node(annotation));
+        }
+
+        let loops = mem::take(&mut self.loops);
+        let body = self.block();
+        self.loops = loops;
+        node.children.push(SyntaxElement::Node(body?));
+        Ok(node)
+    }
+
+    /// The current `:` or `->` and the type after it.
+    fn annotation(&mut self) -> Result<SyntaxNode<'a>, ParserError> {
+        let mut node = self.leaf(NodeKind::Annotation);
+        let ty = self.type_node()?;
+        node.children.push(SyntaxElement::Node(ty));
+        Ok(node)
+    }
+
+    fn type_node(&mut self) -> Result<SyntaxNode<'a>, ParserError> {
+        self.nested(Self::type_node_inner)
+    }
+
+    fn type_node_inner(&mut self) -> Result<SyntaxNode<'a>, ParserError> {
+        match *self.current() {
+            Token::Ident { raw, .. } if TYPE_NAMES.contains(&raw.as_str()) => {
+                Ok(self.leaf(NodeKind::NamedType))
+            }
+            ref token @ Token::Ident { .. } => Err(unknown_type(token)),
+            Token::LeftBracket { .. } => {
+                let mut node = self.leaf(NodeKind::ArrayType);
+                let element = self.type_node()?;
+                node.children.push(SyntaxElement::Node(element));
+                self.expect(TokenKind::RightBracket, &mut node)?;
+                Ok(node)
+            }
+            Token::Function { .. } => {
+                let mut node = self.leaf(NodeKind::FunctionType);
+                self.expect(TokenKind::LeftParen, &mut node)?;
+                if !self.at(TokenKind::RightParen) {
+                    loop {
+                        let parameter = self.type_node()?;
+                        node.children.push(SyntaxElement::Node(parameter));
+                        if !self.at(TokenKind::Comma) {
+                            break;
+                        }
+                        self.bump(&mut node);
+                    }
+                }
+                self.expect(TokenKind::RightParen, &mut node)?;
+                self.expect(TokenKind::Arrow, &mut node)?;
+                let result = self.type_node()?;
+                node.children.push(SyntaxElement::Node(result));
+                Ok(node)
+            }
+            ref token => Err(unexpected(token, "type")),
+        }
+    }
+
+    /// Comma separated expressions up to and including `end`, after the
+    /// opening token already in `node`.
+    fn list(&mut self, end: TokenKind, node: &mut SyntaxNode<'a>) -> Result<(), ParserError> {
+        if !self.at(end) {
+            let item = self.expression(Precedence::Lowest)?;
+            node.children.push(SyntaxElement::Node(item));
+            while self.at(TokenKind::Comma) {
+                self.bump(node);
+                let item = self.expression(Precedence::Lowest)?;
+                node.children.push(SyntaxElement::Node(item));
+            }
+        }
+        self.expect(end, node)
+    }
+}
+
+fn malformed(node: &SyntaxNode) -> ParserError {
+    ParserError::new(
+        format!("Malformed {:?} node in syntax tree", node.kind),
+        node.first_token().and_then(|t| t.token.location()),
+    )
+}
+
+/// The first token of `node`, which the AST keeps as the node's own token.
+fn first_token<'a>(node: &SyntaxNode<'a>) -> Result<Token<'a>, ParserError> {
+    node.first_token()
+        .map(|t| t.token.clone())
+        .ok_or_else(|| malformed(node))
+}
+
+/// Where the last token of `node`, a closing `)`, `]` or `}`, ends.
+fn end(node: &SyntaxNode) -> Result<usize, ParserError> {
+    node.last_token()
+        .and_then(|t| t.token.span())
+        .map(|span| span.end)
+        .ok_or_else(|| malformed(node))
+}
+
+fn nth_node<'n, 'a>(node: &'n SyntaxNode<'a>, n: usize) -> Result<&'n SyntaxNode<'a>, ParserError> {
+    node.nodes().nth(n).ok_or_else(|| malformed(node))
+}
+
+fn lower_statement<'a>(node: &SyntaxNode<'a>) -> Result<Statement<'a>, ParserError> {
+    match node.kind {
+        NodeKind::Let => {
+            let annotation = annotation(node)?;
+            let value = nth_node(node, if annotation.is_some() { 2 } else { 1 })?;
+            Ok(Statement::Let(Let {
+                token: first_token(node)?,
+                identifier: lower_expression(nth_node(node, 0)?)?,
+                annotation,
+                value: lower_expression(value)?,
+            }))
+        }
+        NodeKind::Return => Ok(Statement::Return(Return {
+            token: first_token(node)?,
+            return_value: lower_expression(nth_node(node, 0)?)?,
+        })),
+        NodeKind::Throw => Ok(Statement::Throw(Throw {
+            token: first_token(node)?,
+            value: lower_expression(nth_node(node, 0)?)?,
+        })),
+        NodeKind::While => Ok(Statement::While(While {
+            token: first_token(node)?,
+            condition: lower_expression(nth_node(node, 0)?)?,
+            body: lower_block(nth_node(node, 1)?)?,
+        })),
+        NodeKind::For => Ok(Statement::For(For {
+            token: first_token(node)?,
+            variable: lower_expression(nth_node(node, 0)?)?,
+            iterable: lower_expression(nth_node(node, 1)?)?,
+            body: lower_block(nth_node(node, 2)?)?,
+        })),
+        NodeKind::Break => Ok(Statement::Break(first_token(node)?)),
+        NodeKind::Continue => Ok(Statement::Continue(first_token(node)?)),
+        NodeKind::ExpressionStatement => Ok(Statement::Expression(ExpressionStatement {
+            token: first_token(node)?,
+            expression: lower_expression(nth_node(node, 0)?)?,
+        })),
+        _ => Err(malformed(node)),
+    }
+}
+
+fn lower_block<'a>(node: &SyntaxNode<'a>) -> Result<Block<'a>, ParserError> {
+    if node.kind != NodeKind::Block {
+        return Err(malformed(node));
+    }
+    Ok(Block {
+        token: first_token(node)?,
+        statements: node
+            .nodes()
+            .map(lower_statement)
+            .collect::<Result<_, _>>()?,
+        end: end(node)?,
+    })
+}
+
+fn lower_expression<'a>(node: &SyntaxNode<'a>) -> Result<Expression<'a>, ParserError> {
+    let expression = match node.kind {
+        NodeKind::Identifier => Expression::Identifier(first_token(node)?),
+        NodeKind::Integer => Expression::Integer(first_token(node)?),
+        NodeKind::Boolean => Expression::Boolean(first_token(node)?),
+        NodeKind::String => Expression::String(first_token(node)?),
+        NodeKind::Paren => lower_expression(nth_node(node, 0)?)?,
+        NodeKind::Prefix => Expression::Prefix(Prefix {
+            token: first_token(node)?,
+            right: Box::new(lower_expression(nth_node(node, 0)?)?),
+        }),
+        NodeKind::Infix => Expression::Infix(Infix {
+            token: node
+                .tokens()
+                .next()
+                .ok_or_else(|| malformed(node))?
+                .token
+                .clone(),
+            left: Box::new(lower_expression(nth_node(node, 0)?)?),
+            right: Box::new(lower_expression(nth_node(node, 1)?)?),
+        }),
+        NodeKind::If => Expression::If(If {
+            token: first_token(node)?,
+            condition: Box::new(lower_expression(nth_node(node, 0)?)?),
+            consequence: lower_block(nth_node(node, 1)?)?,
+            alternative: node.nodes().nth(2).map(lower_block).transpose()?,
+        }),
+        NodeKind::Try => Expression::Try(Try {
+            token: first_token(node)?,
+            body: lower_block(nth_node(node, 0)?)?,
+            parameter: Box::new(lower_expression(nth_node(node, 1)?)?),
+            handler: lower_block(nth_node(node, 2)?)?,
+        }),
+        NodeKind::Function => {
+            let mut parameters = Vec::new();
+            let mut parameter_types = Vec::new();
+            for child in nth_node(node, 0)?.nodes() {
+                if child.kind == NodeKind::Annotation {
+                    let ty = parameter_types.last_mut().ok_or_else(|| malformed(node))?;
+                    *ty = Some(lower_type(nth_node(child, 0)?)?);
+                } else {
+                    parameters.push(lower_expression(child)?);
+                    parameter_types.push(None);
+                }
+            }
+            if parameter_types.iter().all(Option::is_none) {
+                parameter_types.clear();
+            }
+            let return_type = annotation(node)?;
+            let body = nth_node(node, if return_type.is_some() { 2 } else { 1 })?;
+            Expression::Function(FunctionLiteral {
+                token: first_token(node)?,
+                parameters,
+                parameter_types,
+                return_type,
+                body: lower_block(body)?,
+            })
+        }
+        NodeKind::Call => {
+            let arguments = nth_node(node, 1)?;
+            Expression::Call(Call {
+                token: first_token(arguments)?,
+                function: Box::new(lower_expression(nth_node(node, 0)?)?),
+                arguments: arguments
+                    .nodes()
+                    .map(lower_expression)
+                    .collect::<Result<_, _>>()?,
+                end: end(arguments)?,
+            })
+        }
+        NodeKind::Array => Expression::Array(ArrayLiteral {
+            token: first_token(node)?,
+            elements: node
+                .nodes()
+                .map(lower_expression)
+                .collect::<Result<_, _>>()?,
+            end: end(node)?,
+        }),
+        NodeKind::Hash => {
+            let children = node
+                .nodes()
+                .map(lower_expression)
+                .collect::<Result<Vec<_>, _>>()?;
+            if children.len() % 2 != 0 {
+                return Err(malformed(node));
+            }
+            let mut children = children.into_iter();
+            let mut pairs = Vec::new();
+            while let (Some(key), Some(value)) = (children.next(), children.next()) {
+                pairs.push((key, value));
+            }
+            Expression::Hash(HashLiteral {
+                token: first_token(node)?,
+                pairs,
+                end: end(node)?,
+            })
+        }
+        NodeKind::Index => Expression::Index(Index {
+            token: node
+                .tokens()
+                .next()
+                .ok_or_else(|| malformed(node))?
+                .token
+                .clone(),
+            left: Box::new(lower_expression(nth_node(node, 0)?)?),
+            index: Box::new(lower_expression(nth_node(node, 1)?)?),
+            end: end(node)?,
+        }),
+        _ => return Err(malformed(node)),
+    };
+    Ok(expression)
+}
+
+/// The type in the `Annotation` child of `node`, if it has one.
+fn annotation<'a>(node: &SyntaxNode<'a>) -> Result<Option<TypeAnnotation<'a>>, ParserError> {
+    node.nodes()
+        .find(|child| child.kind == NodeKind::Annotation)
+        .map(|child| lower_type(nth_node(child, 0)?))
+        .transpose()
+}
+
+fn lower_type<'a>(node: &SyntaxNode<'a>) -> Result<TypeAnnotation<'a>, ParserError> {
+    let ty = match node.kind {
+        NodeKind::NamedType => TypeAnnotation::Named(first_token(node)?),
+        NodeKind::ArrayType => TypeAnnotation::Array(ArrayType {
+            token: first_token(node)?,
+            element: Box::new(lower_type(nth_node(node, 0)?)?),
+            end: end(node)?,
+        }),
+        NodeKind::FunctionType => {
+            let mut types = node
+                .nodes()
+                .map(lower_type)
+                .collect::<Result<Vec<_>, _>>()?;
+            let result = types.pop().ok_or_else(|| malformed(node))?;
+            TypeAnnotation::Function(FunctionType {
+                token: first_token(node)?,
+                parameters: types,
+                result: Box::new(result),
+            })
+        }
+        _ => return Err(malformed(node)),
+    };
+    Ok(ty)
+}
+
+impl<'a> SyntaxTree<'a> {
+    /// The exact text the tree was parsed from.
+    pub fn text(&self) -> String {
+        self.root.to_string()
+    }
+
+    /// Derives the abstract syntax tree. Fails with the parse errors if the
+    /// tree contains any.
+    pub fn to_ast(&self) -> Result<Program<'a>, Vec<ParserError>> {
+        if !self.errors.is_empty() {
+            return Err(self.errors.clone());
+        }
+        let statements = self
+            .root
+            .nodes()
+            .map(lower_statement)
+            .collect::<Result<_, _>>()
+            .map_err(|e| vec![e])?;
+        Ok(Program { statements })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::Rng, Parser};
+
+    const SOURCES: &[&str] = &[
+        "",
+        "   \n\n",
+        "let five = 5;\nlet add = fn(x, y) {\n  x + y; // sum\n};\n\n// call it\nadd(five, (10 * 2));\n",
+        "if ((1 < 2)) { true } else { false }\r\n-5;\t!x == y",
+        "let a = [\"one\", [2]] ;\na[0] [1][ 0 ];\n[];\n{ \"k\": a , 1:{} }[ \"k\" ];",
+        "let n: int = 1;\nlet f = fn(a: [string], g : fn(int) -> bool) -> fn() -> null { g(a) };\nlet r = try {throw  \"x\" ;} catch ( e ) { e };\nwhile (i < 3) { if (i) {break ;} continue }\nfor ( [k,v] in h ) { k } ;for (c in 0 ..= n-1) {}",
+        "let = 5; é + 1 ;  let y = (2;\n// trailing",
+        "fn() {",
+    ];
+
+    #[test]
+    fn test_lossless() {
+        for source in SOURCES {
+            assert_eq!(parse(source).text(), *source);
+            let tokens: String = tokenize(source).iter().map(|t| t.to_string()).collect();
+            assert_eq!(tokens, *source);
+        }
+    }
+
+    #[test]
+    fn test_trivia_attachment() {
+        let tokens = tokenize("x; // one\n  // two\ny");
+        let semicolon = &tokens[1];
+        assert_eq!(
+            semicolon.trailing,
+            vec![
+                Trivia {
+                    kind: TriviaKind::Whitespace,
+                    text: " ",
+                },
+                Trivia {
+                    kind: TriviaKind::Comment,
+                    text: "// one",
+                },
+                Trivia {
+                    kind: TriviaKind::Whitespace,
+                    text: "\n",
+                },
+            ]
+        );
+        let y = &tokens[2];
+        assert_eq!(y.text, "y");
+        assert_eq!(
+            y.leading,
+            vec![
+                Trivia {
+                    kind: TriviaKind::Whitespace,
+                    text: "  ",
+                },
+                Trivia {
+                    kind: TriviaKind::Comment,
+                    text: "// two",
+                },
+                Trivia {
+                    kind: TriviaKind::Whitespace,
+                    text: "\n",
+                },
+            ]
+        );
+        assert_eq!(tokens[3].token, Token::EOF);
+    }
+
+    #[test]
+    fn test_to_ast_matches_parser() {
+        for source in &SOURCES[..6] {
+            let mut parser = Parser::new(Lexer::new(source));
+            let expected = parser.parse_program().expect("Failed to parse program");
+            let program = parse(source).to_ast().expect("Failed to lower tree");
+            assert_eq!(program.to_string(), expected.to_string());
+            assert_eq!(format!("{:?}", program), format!("{:?}", expected));
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let tree = parse(SOURCES[6]);
+        assert_eq!(tree.errors.len(), 3);
+        assert!(tree.to_ast().is_err());
+        let kinds: Vec<NodeKind> = tree.root.nodes().map(|n| n.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![NodeKind::Error, NodeKind::Error, NodeKind::Error]
+        );
+
+        let source = "continue;\nwhile (x) { fn() { break; } }\nfor ([k, 1] in x) {}";
+        let mut parser = Parser::new(Lexer::new(source));
+        let _ = parser.parse_program();
+        assert_eq!(parse(source).errors, parser.errors());
+
+        let n = MAX_NESTING;
+        let deep = [
+            format!("{}1{}", "(".repeat(n), ")".repeat(n)),
+            format!("1{}", "*1".repeat(n)),
+            format!("{}1", "!".repeat(n)),
+            format!("{}1{}", "(".repeat(n / 2), ")+1".repeat(n / 2)),
+            format!("{}1{}", "while (x) { ".repeat(n), " }".repeat(n)),
+            format!("let x: {}int{} = 1;", "[".repeat(n), "]".repeat(n)),
+        ];
+        for source in deep {
+            let mut parser = Parser::new(Lexer::new(&source));
+            let _ = parser.parse_program();
+            assert_eq!(parser.errors().len(), 1, "source: {}", source);
+            assert_eq!(parse(&source).errors, parser.errors(), "source: {}", source);
+        }
+    }
+
+    /// Parses the sources above with random fragments spliced in, and
+    /// random strings of fragments alone, with both parsers.
+    #[test]
+    fn test_random_inputs_match_parser() {
+        let fragments = [
+            "let ", "x", "y", " = ", "1", "\"s\"", "true", "fn", "(", ")", "{", "}", "[", "]", ",",
+            ";", ":", "->", "int", "if", "else", "return ", "throw ", "try", "catch", "while",
+            "for", " in ", "break", "continue", "+", "-", "!", "*", "<", "==", "..", "..=", " ",
+            "\n", "// c\n", "é",
+        ];
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for i in 0..20_000 {
+            let mut source = match i % 2 {
+                0 => SOURCES[rng.below(SOURCES.len())].to_string(),
+                _ => String::new(),
+            };
+            for _ in 0..=rng.below(12) {
+                let mut at = rng.below(source.len() + 1);
+                while !source.is_char_boundary(at) {
+                    at -= 1;
+                }
+                source.insert_str(at, fragments[rng.below(fragments.len())]);
+            }
+
+            let mut parser = Parser::new(Lexer::new(&source));
+            let program = parser.parse_program().expect("Failed to parse program");
+            let tree = parse(&source);
+            assert_eq!(tree.errors, parser.errors(), "source: {:?}", source);
+            if let Ok(ast) = tree.to_ast() {
+                assert_eq!(
+                    format!("{:?}", ast),
+                    format!("{:?}", program),
+                    "source: {:?}",
+                    source
+                );
+            }
+        }
+    }
+}
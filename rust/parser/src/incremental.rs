@@ -0,0 +1,389 @@
+//! Updating a `cst::SyntaxTree` after an edit without lexing and parsing the
+//! whole buffer again.
+//!
+//! Relexing starts two tokens before the edit and stops at the first token
+//! boundary past it that lines up with an old one; from there the old tokens
+//! are moved over unchanged. Reparsing works on top-level statements in the
+//! same way: statements that end before the relexed tokens, and statements
+//! that start after them once the parser lines up with an old statement
+//! start again, are reused. Reused tokens and nodes are rebound to the new
+//! text, so the result is exactly what `cst::parse` would produce for it.
+
+use std::ops::Range;
+
+use lexer::tokens::TokenKind;
+
+use crate::cst::{
+    tokenize_from, CstParser, NodeKind, SyntaxElement, SyntaxNode, SyntaxToken, SyntaxTree, Trivia,
+};
+
+/// Replaces the bytes in `range` with `replacement`. Both ends of the range
+/// must be on character boundaries.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TextEdit<'e> {
+    pub range: Range<usize>,
+    pub replacement: &'e str,
+}
+
+impl TextEdit<'_> {
+    /// The text after the edit.
+    pub fn apply(&self, text: &str) -> String {
+        let mut edited = String::with_capacity(text.len() + self.replacement.len());
+        edited.push_str(&text[..self.range.start]);
+        edited.push_str(self.replacement);
+        edited.push_str(&text[self.range.end..]);
+        edited
+    }
+
+    /// Moves an offset past the edit into the edited text.
+    fn shift(&self, offset: usize) -> usize {
+        offset + self.replacement.len() - self.range.len()
+    }
+}
+
+/// The result of `relex`.
+pub struct Relexed<'n> {
+    pub tokens: Vec<SyntaxToken<'n>>,
+    /// Indices of the tokens that were lexed again; all others were reused.
+    pub relexed: Range<usize>,
+    /// Index in the old token list of the first token reused after the edit.
+    old_resume: usize,
+}
+
+/// Offset where each token's leading trivia starts.
+fn full_starts(tokens: &[SyntaxToken]) -> Vec<usize> {
+    let mut offset = 0;
+    tokens
+        .iter()
+        .map(|token| {
+            let start = offset;
+            offset += token_len(token);
+            start
+        })
+        .collect()
+}
+
+fn trivia_len(trivia: &[Trivia]) -> usize {
+    trivia.iter().map(|t| t.text.len()).sum()
+}
+
+fn token_len(token: &SyntaxToken) -> usize {
+    trivia_len(&token.leading) + token.text.len() + trivia_len(&token.trailing)
+}
+
+/// Takes the next `len` bytes of `text` at `*offset`.
+fn take<'n>(text: &'n str, offset: &mut usize, len: usize) -> &'n str {
+    let slice = &text[*offset..*offset + len];
+    *offset += len;
+    slice
+}
+
+fn rebind_trivia<'n>(trivia: &[Trivia], text: &'n str, offset: &mut usize) -> Vec<Trivia<'n>> {
+    trivia
+        .iter()
+        .map(|t| Trivia {
+            kind: t.kind,
+            text: take(text, offset, t.text.len()),
+        })
+        .collect()
+}
+
+/// Copies `token`, whose leading trivia now starts at `offset` in `text`,
+/// borrowing from `text` instead.
+fn rebind<'n>(token: &SyntaxToken, offset: usize, text: &'n str) -> SyntaxToken<'n> {
+    let mut offset = offset;
+    let leading = rebind_trivia(&token.leading, text, &mut offset);
+    let start = offset;
+    let own = take(text, &mut offset, token.text.len());
+    let trailing = rebind_trivia(&token.trailing, text, &mut offset);
+    SyntaxToken {
+        token: token.token.relocate(start, text),
+        text: own,
+        leading,
+        trailing,
+    }
+}
+
+/// Updates `tokens`, the tokens of the text before `edit`, to those of
+/// `text`, the text after it.
+pub fn relex<'n>(tokens: &[SyntaxToken], edit: &TextEdit, text: &'n str) -> Relexed<'n> {
+    let starts = full_starts(tokens);
+    // the token the edit starts in, one more before it because the edit may
+    // join the two, and another because if that one is a `/` the edit may
+    // make it the start of a comment, which belongs to the token before it
+    let containing = starts.partition_point(|start| *start <= edit.range.start) - 1;
+    let first = containing.saturating_sub(2);
+
+    let mut old_resume = tokens.len();
+    let edit_end = edit.range.start + edit.replacement.len();
+    let fresh = tokenize_from(text, starts[first], |offset| {
+        if offset < edit_end {
+            return false;
+        }
+        let old = offset + edit.range.len() - edit.replacement.len();
+        match starts.binary_search(&old) {
+            Ok(index) => {
+                old_resume = index;
+                true
+            }
+            Err(_) => false,
+        }
+    });
+
+    let mut result: Vec<SyntaxToken> = tokens[..first]
+        .iter()
+        .zip(&starts)
+        .map(|(token, start)| rebind(token, *start, text))
+        .collect();
+    let relexed = first..first + fresh.len();
+    result.extend(fresh);
+    result.extend(
+        tokens[old_resume..]
+            .iter()
+            .zip(&starts[old_resume..])
+            .map(|(token, start)| rebind(token, edit.shift(*start), text)),
+    );
+
+    Relexed {
+        tokens: result,
+        relexed,
+        old_resume,
+    }
+}
+
+fn count_tokens(node: &SyntaxNode) -> usize {
+    node.children
+        .iter()
+        .map(|child| match child {
+            SyntaxElement::Node(node) => count_tokens(node),
+            SyntaxElement::Token(_) => 1,
+        })
+        .sum()
+}
+
+/// Whether `node` is an `Error` node or has one inside, such as for a
+/// malformed statement in a block.
+fn has_error(node: &SyntaxNode) -> bool {
+    node.kind == NodeKind::Error || node.nodes().any(has_error)
+}
+
+/// Copies `node`, replacing its tokens in order with `tokens[*next..]`.
+fn rebind_node<'n>(
+    node: &SyntaxNode,
+    tokens: &[SyntaxToken<'n>],
+    next: &mut usize,
+) -> SyntaxNode<'n> {
+    let children = node
+        .children
+        .iter()
+        .map(|child| match child {
+            SyntaxElement::Node(node) => SyntaxElement::Node(rebind_node(node, tokens, next)),
+            SyntaxElement::Token(_) => {
+                *next += 1;
+                SyntaxElement::Token(tokens[*next - 1].clone())
+            }
+        })
+        .collect();
+    SyntaxNode {
+        kind: node.kind,
+        children,
+    }
+}
+
+impl SyntaxTree<'_> {
+    /// The tree for `text`, which must be this tree's text with `edit`
+    /// applied.
+    pub fn reparse<'n>(&self, edit: &TextEdit, text: &'n str) -> SyntaxTree<'n> {
+        let Relexed {
+            tokens,
+            relexed,
+            old_resume,
+        } = relex(&self.tokens, edit, text);
+
+        // token range of every old top-level statement
+        let statements: Vec<&SyntaxNode> = self.root.nodes().collect();
+        let mut ranges = Vec::with_capacity(statements.len());
+        let mut next = 0;
+        for stmt in &statements {
+            let len = count_tokens(stmt);
+            ranges.push(next..next + len);
+            next += len;
+        }
+
+        // A statement without errors looks at most one token past its end,
+        // so it is unaffected if that token comes before the relexed ones.
+        // Error nodes may have looked arbitrarily far ahead.
+        let prefix = statements
+            .iter()
+            .zip(&ranges)
+            .take_while(|(stmt, range)| !has_error(stmt) && range.end < relexed.start)
+            .count();
+
+        let mut root = SyntaxNode::new(NodeKind::Program);
+        let mut next = 0;
+        for stmt in &statements[..prefix] {
+            let node = rebind_node(stmt, &tokens, &mut next);
+            root.children.push(SyntaxElement::Node(node));
+        }
+
+        // statements are parsed until one would start where an old statement
+        // after the edit started
+        let moved = |index: usize| index + relexed.end - old_resume;
+        let mut suffix = statements.len();
+        let mut parser = CstParser::new(tokens, next);
+        while !parser.at(TokenKind::EOF) {
+            if parser.position >= relexed.end {
+                if let Some(index) = ranges.iter().position(|range| {
+                    range.start >= old_resume && moved(range.start) == parser.position
+                }) {
+                    suffix = index;
+                    break;
+                }
+            }
+            let stmt = parser.top_level_statement();
+            root.children.push(SyntaxElement::Node(stmt));
+        }
+
+        let mut next = parser.position;
+        for stmt in &statements[suffix..] {
+            let node = rebind_node(stmt, &parser.tokens, &mut next);
+            root.children.push(SyntaxElement::Node(node));
+        }
+        parser.position = parser.tokens.len() - 1;
+        parser.bump(&mut root);
+
+        // an error is at a token of the statement it was found in, or at the
+        // end of the text
+        let errors_before = match statements.get(suffix) {
+            Some(stmt) => {
+                let boundary = stmt.span().start;
+                self.errors
+                    .iter()
+                    .filter(|e| e.location().is_some_and(|location| location < boundary))
+                    .count()
+            }
+            None => self.errors.len(),
+        };
+        let mut errors = parser.errors;
+        let delta = edit.replacement.len() as isize - edit.range.len() as isize;
+        errors.extend(
+            self.errors[errors_before..]
+                .iter()
+                .map(|e| e.shifted(delta)),
+        );
+
+        SyntaxTree {
+            root,
+            tokens: parser.tokens,
+            errors,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cst::parse, testing::Rng};
+
+    const SOURCE: &str = "let five = 5; // five
+let add = fn(x, y) {
+  x + y;
+};
+
+// call it
+let result = add(five, 10)
+if (result == 15) { result } else { -1 }
+let broken = ;
+(result)
+let names = [\"a b\", \"c\"][0];
+";
+
+    fn assert_matches_full_parse(source: &str, edit: TextEdit) -> Range<usize> {
+        let tree = parse(source);
+        let text = edit.apply(source);
+        let updated = tree.reparse(&edit, &text);
+        let expected = parse(&text);
+
+        let context = format!("{:?} applied to {:?}", edit, source);
+        assert_eq!(updated.text(), text, "{}", context);
+        assert_eq!(updated.tokens, expected.tokens, "{}", context);
+        assert_eq!(updated.root, expected.root, "{}", context);
+        assert_eq!(updated.errors, expected.errors, "{}", context);
+
+        relex(&tree.tokens, &edit, &text).relexed
+    }
+
+    #[test]
+    fn test_edits_match_full_parse() {
+        let tests = [
+            ("x/y", 2..2, "/"),
+            ("a/!b", 2..3, "//"),
+            ("a /!b", 3..4, "/ c\n"),
+        ];
+        for (source, range, replacement) in tests {
+            assert_matches_full_parse(source, TextEdit { range, replacement });
+        }
+    }
+
+    #[test]
+    fn test_random_edits_match_full_parse() {
+        let fragments = [
+            "", "x", "=", "/", "//", "/ c\n", "\n", " ", "{", "}", ";", "(", ")", "!", "1", "é",
+            "\"", "[", "let ", "fn", ".", "..", "=",
+        ];
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let mut text = SOURCE.to_string();
+        for _ in 0..20_000 {
+            if text.len() > 2 * SOURCE.len() {
+                text = SOURCE.to_string();
+            }
+            let mut start = rng.below(text.len() + 1);
+            while !text.is_char_boundary(start) {
+                start -= 1;
+            }
+            let mut end = (start + rng.below(8)).min(text.len());
+            while !text.is_char_boundary(end) {
+                end += 1;
+            }
+            let replacement = [
+                fragments[rng.below(fragments.len())],
+                fragments[rng.below(fragments.len())],
+            ]
+            .concat();
+            let edit = TextEdit {
+                range: start..end,
+                replacement: &replacement,
+            };
+            assert_matches_full_parse(&text, edit.clone());
+            text = edit.apply(&text);
+        }
+    }
+
+    #[test]
+    fn test_edit_reuses_tokens() {
+        let start = SOURCE.find("10").unwrap();
+        let relexed = assert_matches_full_parse(
+            SOURCE,
+            TextEdit {
+                range: start..start + 2,
+                replacement: "100",
+            },
+        );
+        assert!(relexed.len() <= 3, "relexed {:?}", relexed);
+
+        assert_matches_full_parse(
+            "",
+            TextEdit {
+                range: 0..0,
+                replacement: "let x = 1;",
+            },
+        );
+        assert_matches_full_parse(
+            "x",
+            TextEdit {
+                range: 0..1,
+                replacement: "",
+            },
+        );
+    }
+}
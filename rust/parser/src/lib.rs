@@ -1,127 +1,790 @@
-use lexer::{tokens::Token, Lexer};
+use std::{fmt, mem};
+
+use lexer::{
+    stream::TokenStream,
+    tokens::{Token, TokenKind},
+    Lexer,
+};
 
 pub mod ast;
-use ast::{Let, Program, Return, Statement};
+pub mod cst;
+pub mod incremental;
+pub mod visit;
+use ast::{
+    ArrayLiteral, ArrayType, Block, Call, Expression, ExpressionStatement, For, FunctionLiteral,
+    FunctionType, HashLiteral, If, Index, Infix, Let, Prefix, Program, Return, Statement, Throw,
+    Try, TypeAnnotation, While, TYPE_NAMES,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParserError {
+    message: String,
+    location: Option<usize>,
+}
+
+impl ParserError {
+    fn new(message: String, location: Option<usize>) -> Self {
+        ParserError { message, location }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Byte offset of the token the error is about, or `None` when it is
+    /// about the end of input.
+    pub fn location(&self) -> Option<usize> {
+        self.location
+    }
 
-#[derive(Clone, Debug)]
-pub struct ParserError(String);
+    /// The same error in a copy of the source where everything from the
+    /// error on moved by `delta` bytes.
+    pub(crate) fn shifted(&self, delta: isize) -> Self {
+        ParserError {
+            message: self.message.clone(),
+            location: self.location.map(|l| l.wrapping_add_signed(delta)),
+        }
+    }
+}
+
+impl fmt::Display for ParserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Precedence {
+    Lowest,
+    Range,
+    Equals,
+    LessGreater,
+    Sum,
+    Product,
+    Prefix,
+    Call,
+    Index,
+}
+
+pub fn precedence(kind: TokenKind) -> Precedence {
+    match kind {
+        TokenKind::DotDot | TokenKind::DotDotEquals => Precedence::Range,
+        TokenKind::Equals | TokenKind::NotEquals => Precedence::Equals,
+        TokenKind::LessThan | TokenKind::GreaterThan => Precedence::LessGreater,
+        TokenKind::Plus | TokenKind::Minus => Precedence::Sum,
+        TokenKind::Multiply | TokenKind::Divide => Precedence::Product,
+        TokenKind::LeftParen => Precedence::Call,
+        TokenKind::LeftBracket => Precedence::Index,
+        _ => Precedence::Lowest,
+    }
+}
+
+pub fn token_precedence(token: &Token) -> Precedence {
+    precedence(token.kind())
+}
+
+/// How many levels expressions, blocks and types may nest. Parsing, and
+/// everything that walks the tree afterwards, recurses once or more per
+/// level, so without a bound deeply nested input would overflow the stack.
+/// This keeps the deepest input inside the 2 MiB a spawned thread gets,
+/// even in a debug build.
+/// An operator counts as a level for its left operand as well as its
+/// right, so `1 + 1 + 1` is three levels deep.
+pub const MAX_NESTING: usize = 128;
+
+/// `'=='` for kinds with fixed text, `identifier` for the others.
+fn describe(kind: TokenKind) -> String {
+    match kind.literal() {
+        Some(text) => format!("'{}'", text),
+        None => kind.to_string(),
+    }
+}
+
+/// The error for an identifier used as a type that is not one of
+/// `TYPE_NAMES`.
+fn unknown_type(token: &Token) -> ParserError {
+    ParserError::new(
+        format!(
+            "Unknown type '{}'. Expected one of {}",
+            token,
+            TYPE_NAMES.join(", ")
+        ),
+        token.location(),
+    )
+}
+
+fn unexpected(token: &Token, expected: &str) -> ParserError {
+    if let Token::Illegal { location, raw } = token {
+        // the lexer leaves digits illegal only when they overflow an int
+        if raw.starts_with(|c: char| c.is_ascii_digit()) {
+            return ParserError::new(
+                format!("Integer literal {} is too large", raw),
+                Some(*location),
+            );
+        }
+    }
+    let found = match token.kind() {
+        kind @ (TokenKind::Ident | TokenKind::Int | TokenKind::String | TokenKind::Illegal) => {
+            format!("{} '{}'", kind, token)
+        }
+        kind => describe(kind),
+    };
+    ParserError::new(
+        format!("Unexpected {}. Expected {}", found, expected),
+        token.location(),
+    )
+}
+
+/// The error for nesting past `MAX_NESTING` at `token`.
+fn too_deep(token: &Token) -> ParserError {
+    ParserError::new(
+        format!("Nesting limit of {} exceeded", MAX_NESTING),
+        token.location(),
+    )
+}
+
+/// The error for a `break` or `continue` that is not inside a loop of the
+/// function it is in.
+fn outside_loop(token: &Token) -> ParserError {
+    ParserError::new(
+        format!("Unexpected '{}' outside of a loop", token),
+        token.location(),
+    )
+}
 
 pub struct Parser<'a> {
-    lex: Lexer<'a>,
-    current_token: Token,
-    peek_token: Token,
+    tokens: TokenStream<'a>,
+    current_token: Token<'a>,
     errors: Vec<ParserError>,
+    /// How many loops of the current function the parser is inside.
+    loops: usize,
+    /// How many expressions, blocks and types the parser is inside.
+    depth: usize,
+    /// The deepest level the innermost expression being parsed reaches so
+    /// far, counting how far operators push their left operands down.
+    reach: usize,
+    /// How many `{` before `current_token` are still open.
+    braces: usize,
 }
 
 impl<'a> Parser<'a> {
-    pub fn new(mut lex: Lexer<'a>) -> Self {
-        let current_token = lex.next_token();
-        let peek_token = lex.next_token();
+    pub fn new(lex: Lexer<'a>) -> Self {
+        let mut tokens = TokenStream::new(lex);
+        let current_token = tokens.next_token();
         Parser {
-            lex,
+            tokens,
             current_token,
-            peek_token,
             errors: Vec::new(),
+            loops: 0,
+            depth: 0,
+            reach: 0,
+            braces: 0,
         }
     }
 
     pub fn next_token(&mut self) {
-        self.current_token = self.peek_token.clone();
-        self.peek_token = self.lex.next_token();
+        match self.current_token.kind() {
+            TokenKind::LeftBrace => self.braces += 1,
+            TokenKind::RightBrace => self.braces = self.braces.saturating_sub(1),
+            _ => {}
+        }
+        self.current_token = self.tokens.next_token();
+    }
+
+    /// The token after `current_token`.
+    pub fn peek_token(&mut self) -> &Token<'a> {
+        self.tokens.peek()
+    }
+
+    /// The token `k + 1` positions after `current_token`, so `peek_nth(0)`
+    /// is the same as `peek_token`.
+    pub fn peek_nth(&mut self, k: usize) -> &Token<'a> {
+        self.tokens.peek_nth(k)
+    }
+
+    fn peek_is(&mut self, kind: TokenKind) -> bool {
+        self.peek_token().kind() == kind
+    }
+
+    fn expect_peek(&mut self, kind: TokenKind) -> Result<(), ParserError> {
+        if self.peek_is(kind) {
+            self.next_token();
+            Ok(())
+        } else {
+            Err(unexpected(self.peek_token(), &describe(kind)))
+        }
     }
 
-    pub fn parse_program(&mut self) -> Option<Program> {
+    /// Where the current token, a closing `)`, `]` or `}`, ends.
+    fn end(&self) -> usize {
+        self.current_token.span().map_or(0, |span| span.end)
+    }
+
+    /// Runs `parse` one level deeper, failing at the current token if that
+    /// is past `MAX_NESTING`.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParserError>,
+    ) -> Result<T, ParserError> {
+        if self.depth == MAX_NESTING {
+            return Err(too_deep(&self.current_token));
+        }
+        self.depth += 1;
+        self.reach = self.reach.max(self.depth);
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Skips the remainder of a malformed statement so a single mistake
+    /// is reported once rather than once per leftover token. `braces` is
+    /// how many were open where the statement started: it ends at the next
+    /// `;` outside any it opened, or before the `}` closing the block it is
+    /// in.
+    fn synchronize(&mut self, braces: usize) {
+        loop {
+            match self.current_token.kind() {
+                TokenKind::EOF => break,
+                TokenKind::Semicolon if self.braces <= braces => break,
+                TokenKind::RightBrace if self.braces == braces && braces > 0 => break,
+                _ => self.next_token(),
+            }
+        }
+    }
+
+    pub fn parse_program(&mut self) -> Option<Program<'a>> {
         let mut program = Program {
             statements: Vec::new(),
         };
 
-        loop {
+        while self.current_token != Token::EOF {
             let stmt = self.parse_statement();
             if let Some(stmt) = stmt {
                 program.statements.push(stmt);
             }
             self.next_token();
-
-            if self.peek_token == Token::EOF {
-                break;
-            }
         }
 
         Some(program)
     }
 
-    pub fn parse_statement(&mut self) -> Option<Statement> {
-        match &self.current_token {
-            tok @ Token::Let { .. } => match self.parse_let_statement(tok.clone()) {
-                Ok(l) => Some(Statement::Let(l)),
-                Err(msg) => {
-                    self.errors.push(msg.to_owned());
-                    None
+    pub fn parse_statement(&mut self) -> Option<Statement<'a>> {
+        let braces = self.braces;
+        match self.try_parse_statement() {
+            Ok(stmt) => Some(stmt),
+            Err(msg) => {
+                self.errors.push(msg);
+                self.synchronize(braces);
+                None
+            }
+        }
+    }
+
+    fn try_parse_statement(&mut self) -> Result<Statement<'a>, ParserError> {
+        let token = self.current_token.clone();
+        match token.kind() {
+            TokenKind::Let => self.parse_let_statement(token).map(Statement::Let),
+            TokenKind::Return => self.parse_return_statement(token).map(Statement::Return),
+            TokenKind::Throw => self.parse_throw_statement(token).map(Statement::Throw),
+            TokenKind::While => self.parse_while_statement(token).map(Statement::While),
+            TokenKind::For => self.parse_for_statement(token).map(Statement::For),
+            TokenKind::Break | TokenKind::Continue => {
+                if self.loops == 0 {
+                    return Err(outside_loop(&token));
                 }
-            },
-            tok @ Token::Return { .. } => match self.parse_return_statement(tok.clone()) {
-                Ok(r) => Some(Statement::Return(r)),
-                Err(msg) => {
-                    self.errors.push(msg.to_owned());
-                    None
+                if self.peek_is(TokenKind::Semicolon) {
+                    self.next_token();
                 }
-            },
-            _ => None,
+                Ok(if token.kind() == TokenKind::Break {
+                    Statement::Break(token)
+                } else {
+                    Statement::Continue(token)
+                })
+            }
+            _ => self.parse_expression_statement().map(Statement::Expression),
         }
     }
 
-    pub fn parse_return_statement(&mut self, ret_tok: Token) -> Result<Return, ParserError> {
+    pub fn parse_return_statement(
+        &mut self,
+        ret_tok: Token<'a>,
+    ) -> Result<Return<'a>, ParserError> {
         self.next_token();
-        let temp_current = self.current_token.clone();
-        loop {
-            match &self.current_token {
-                Token::Semicolon { .. } => {
-                    break Ok(Return {
-                        token: ret_tok,
-                        return_value: ast::Expression::Identifier(temp_current),
-                    })
+        let return_value = self.parse_expression(Precedence::Lowest)?;
+        if self.peek_is(TokenKind::Semicolon) {
+            self.next_token();
+        }
+        Ok(Return {
+            token: ret_tok,
+            return_value,
+        })
+    }
+
+    pub fn parse_throw_statement(&mut self, token: Token<'a>) -> Result<Throw<'a>, ParserError> {
+        self.next_token();
+        let value = self.parse_expression(Precedence::Lowest)?;
+        if self.peek_is(TokenKind::Semicolon) {
+            self.next_token();
+        }
+        Ok(Throw { token, value })
+    }
+
+    pub fn parse_while_statement(&mut self, token: Token<'a>) -> Result<While<'a>, ParserError> {
+        self.expect_peek(TokenKind::LeftParen)?;
+        self.next_token();
+        let condition = self.parse_expression(Precedence::Lowest)?;
+        self.expect_peek(TokenKind::RightParen)?;
+        self.expect_peek(TokenKind::LeftBrace)?;
+        self.loops += 1;
+        let body = self.parse_block();
+        self.loops -= 1;
+        let body = body?;
+        if self.peek_is(TokenKind::Semicolon) {
+            self.next_token();
+        }
+        Ok(While {
+            token,
+            condition,
+            body,
+        })
+    }
+
+    pub fn parse_for_statement(&mut self, token: Token<'a>) -> Result<For<'a>, ParserError> {
+        self.expect_peek(TokenKind::LeftParen)?;
+        self.next_token();
+        let variable = match self.current_token.kind() {
+            TokenKind::Ident => Expression::Identifier(self.current_token.clone()),
+            TokenKind::LeftBracket => {
+                let token = self.current_token.clone();
+                let mut elements = Vec::new();
+                loop {
+                    self.expect_peek(TokenKind::Ident)?;
+                    elements.push(Expression::Identifier(self.current_token.clone()));
+                    if !self.peek_is(TokenKind::Comma) {
+                        break;
+                    }
+                    self.next_token();
+                }
+                self.expect_peek(TokenKind::RightBracket)?;
+                Expression::Array(ArrayLiteral {
+                    token,
+                    elements,
+                    end: self.end(),
+                })
+            }
+            _ => return Err(unexpected(&self.current_token, "identifier or '['")),
+        };
+        self.expect_peek(TokenKind::In)?;
+        self.next_token();
+        let iterable = self.parse_expression(Precedence::Lowest)?;
+        self.expect_peek(TokenKind::RightParen)?;
+        self.expect_peek(TokenKind::LeftBrace)?;
+        self.loops += 1;
+        let body = self.parse_block();
+        self.loops -= 1;
+        let body = body?;
+        if self.peek_is(TokenKind::Semicolon) {
+            self.next_token();
+        }
+        Ok(For {
+            token,
+            variable,
+            iterable,
+            body,
+        })
+    }
+
+    pub fn parse_let_statement(&mut self, let_tok: Token<'a>) -> Result<Let<'a>, ParserError> {
+        self.expect_peek(TokenKind::Ident)?;
+        let ident_tok = self.current_token.clone();
+        let annotation = self.parse_annotation(TokenKind::Colon)?;
+        if annotation.is_some() {
+            self.expect_peek(TokenKind::Assign)?;
+        } else if !self.peek_is(TokenKind::Assign) {
+            return Err(unexpected(self.peek_token(), "':' or '='"));
+        } else {
+            self.next_token();
+        }
+        self.next_token();
+        let value = self.parse_expression(Precedence::Lowest)?;
+        if self.peek_is(TokenKind::Semicolon) {
+            self.next_token();
+        }
+        Ok(Let {
+            token: let_tok,
+            identifier: Expression::Identifier(ident_tok),
+            annotation,
+            value,
+        })
+    }
+
+    pub fn parse_expression_statement(&mut self) -> Result<ExpressionStatement<'a>, ParserError> {
+        let token = self.current_token.clone();
+        let expression = self.parse_expression(Precedence::Lowest)?;
+        if self.peek_is(TokenKind::Semicolon) {
+            self.next_token();
+        }
+        Ok(ExpressionStatement { token, expression })
+    }
+
+    pub fn parse_expression(
+        &mut self,
+        precedence: Precedence,
+    ) -> Result<Expression<'a>, ParserError> {
+        self.nested(|parser| {
+            let depth = parser.depth;
+            let outer = mem::replace(&mut parser.reach, depth);
+            let expression = parser.parse_operators(precedence, depth);
+            parser.reach = parser.reach.max(outer);
+            expression
+        })
+    }
+
+    /// An operand and the operators after it that bind tighter than
+    /// `precedence`, as the expression at nesting level `depth`.
+    fn parse_operators(
+        &mut self,
+        precedence: Precedence,
+        depth: usize,
+    ) -> Result<Expression<'a>, ParserError> {
+        let token = self.current_token.clone();
+        let mut left = match token.kind() {
+            TokenKind::Ident => Expression::Identifier(token),
+            TokenKind::Int => Expression::Integer(token),
+            TokenKind::True | TokenKind::False => Expression::Boolean(token),
+            TokenKind::String => Expression::String(token),
+            TokenKind::LeftBracket => Expression::Array(ArrayLiteral {
+                token,
+                elements: self.parse_expression_list(TokenKind::RightBracket)?,
+                end: self.end(),
+            }),
+            TokenKind::LeftBrace => self.parse_hash_literal()?,
+            TokenKind::Negation | TokenKind::Minus => self.parse_prefix_expression()?,
+            TokenKind::LeftParen => self.parse_grouped_expression()?,
+            TokenKind::If => self.parse_if_expression()?,
+            TokenKind::Try => self.parse_try_expression()?,
+            TokenKind::Function => self.parse_function_literal()?,
+            _ => return Err(unexpected(&token, "expression")),
+        };
+
+        // levels below `depth` that `left` takes up
+        let mut height = self.reach - depth;
+        while !self.peek_is(TokenKind::Semicolon)
+            && precedence < token_precedence(self.peek_token())
+        {
+            if depth + height >= MAX_NESTING {
+                return Err(too_deep(self.peek_token()));
+            }
+            self.reach = depth;
+            left = match self.peek_token().kind() {
+                TokenKind::LeftParen => {
+                    self.next_token();
+                    self.parse_call_expression(left)?
+                }
+                TokenKind::LeftBracket => {
+                    self.next_token();
+                    self.parse_index_expression(left)?
                 }
                 _ => {
                     self.next_token();
+                    self.parse_infix_expression(left)?
+                }
+            };
+            height = (height + 1).max(self.reach - depth);
+            self.reach = depth + height;
+        }
+
+        Ok(left)
+    }
+
+    fn parse_prefix_expression(&mut self) -> Result<Expression<'a>, ParserError> {
+        let token = self.current_token.clone();
+        self.next_token();
+        let right = self.parse_expression(Precedence::Prefix)?;
+        Ok(Expression::Prefix(Prefix {
+            token,
+            right: Box::new(right),
+        }))
+    }
+
+    fn parse_infix_expression(
+        &mut self,
+        left: Expression<'a>,
+    ) -> Result<Expression<'a>, ParserError> {
+        let token = self.current_token.clone();
+        let precedence = token_precedence(&token);
+        self.next_token();
+        let right = self.parse_expression(precedence)?;
+        Ok(Expression::Infix(Infix {
+            token,
+            left: Box::new(left),
+            right: Box::new(right),
+        }))
+    }
+
+    fn parse_grouped_expression(&mut self) -> Result<Expression<'a>, ParserError> {
+        self.next_token();
+        let expression = self.parse_expression(Precedence::Lowest)?;
+        self.expect_peek(TokenKind::RightParen)?;
+        Ok(expression)
+    }
+
+    fn parse_if_expression(&mut self) -> Result<Expression<'a>, ParserError> {
+        let token = self.current_token.clone();
+        self.expect_peek(TokenKind::LeftParen)?;
+        self.next_token();
+        let condition = self.parse_expression(Precedence::Lowest)?;
+        self.expect_peek(TokenKind::RightParen)?;
+        self.expect_peek(TokenKind::LeftBrace)?;
+        let consequence = self.parse_block()?;
+
+        let alternative = if self.peek_is(TokenKind::Else) {
+            self.next_token();
+            self.expect_peek(TokenKind::LeftBrace)?;
+            Some(self.parse_block()?)
+        } else {
+            None
+        };
+
+        Ok(Expression::If(If {
+            token,
+            condition: Box::new(condition),
+            consequence,
+            alternative,
+        }))
+    }
+
+    fn parse_try_expression(&mut self) -> Result<Expression<'a>, ParserError> {
+        let token = self.current_token.clone();
+        self.expect_peek(TokenKind::LeftBrace)?;
+        let body = self.parse_block()?;
+        self.expect_peek(TokenKind::Catch)?;
+        self.expect_peek(TokenKind::LeftParen)?;
+        self.expect_peek(TokenKind::Ident)?;
+        let parameter = Expression::Identifier(self.current_token.clone());
+        self.expect_peek(TokenKind::RightParen)?;
+        self.expect_peek(TokenKind::LeftBrace)?;
+        let handler = self.parse_block()?;
+
+        Ok(Expression::Try(Try {
+            token,
+            body,
+            parameter: Box::new(parameter),
+            handler,
+        }))
+    }
+
+    fn parse_block(&mut self) -> Result<Block<'a>, ParserError> {
+        self.nested(Self::parse_block_inner)
+    }
+
+    fn parse_block_inner(&mut self) -> Result<Block<'a>, ParserError> {
+        let token = self.current_token.clone();
+        let mut statements = Vec::new();
+        self.next_token();
+
+        loop {
+            match self.current_token.kind() {
+                TokenKind::RightBrace => break,
+                TokenKind::EOF => return Err(unexpected(&Token::EOF, "'}'")),
+                _ => {}
+            }
+            // a malformed statement is reported and skipped here, so the
+            // rest of the block still parses
+            if let Some(stmt) = self.parse_statement() {
+                statements.push(stmt);
+            } else if self.current_token.kind() != TokenKind::Semicolon {
+                continue;
+            }
+            self.next_token();
+        }
+
+        Ok(Block {
+            token,
+            statements,
+            end: self.end(),
+        })
+    }
+
+    fn parse_function_literal(&mut self) -> Result<Expression<'a>, ParserError> {
+        let token = self.current_token.clone();
+        self.expect_peek(TokenKind::LeftParen)?;
+
+        let mut parameters = Vec::new();
+        let mut parameter_types = Vec::new();
+        if self.peek_is(TokenKind::RightParen) {
+            self.next_token();
+        } else {
+            loop {
+                self.expect_peek(TokenKind::Ident)?;
+                parameters.push(Expression::Identifier(self.current_token.clone()));
+                parameter_types.push(self.parse_annotation(TokenKind::Colon)?);
+                if self.peek_is(TokenKind::Comma) {
+                    self.next_token();
+                } else {
+                    break;
                 }
             }
+            self.expect_peek(TokenKind::RightParen)?;
+        }
+        if parameter_types.iter().all(Option::is_none) {
+            parameter_types.clear();
+        }
+        let return_type = self.parse_annotation(TokenKind::Arrow)?;
+
+        self.expect_peek(TokenKind::LeftBrace)?;
+        // a function body is outside any loop around the literal
+        let loops = mem::take(&mut self.loops);
+        let body = self.parse_block();
+        self.loops = loops;
+        let body = body?;
+
+        Ok(Expression::Function(FunctionLiteral {
+            token,
+            parameters,
+            parameter_types,
+            return_type,
+            body,
+        }))
+    }
+
+    /// The type after the next token if that is `before`, which is `:`
+    /// or `->`.
+    fn parse_annotation(
+        &mut self,
+        before: TokenKind,
+    ) -> Result<Option<TypeAnnotation<'a>>, ParserError> {
+        if !self.peek_is(before) {
+            return Ok(None);
         }
+        self.next_token();
+        self.next_token();
+        self.parse_type().map(Some)
+    }
+
+    /// A type starting at the current token, which is left on its last token.
+    fn parse_type(&mut self) -> Result<TypeAnnotation<'a>, ParserError> {
+        self.nested(Self::parse_type_inner)
     }
 
-    pub fn parse_let_statement(&mut self, let_tok: Token) -> Result<Let, ParserError> {
-        match &self.peek_token {
-            Token::Ident { .. } => {
+    fn parse_type_inner(&mut self) -> Result<TypeAnnotation<'a>, ParserError> {
+        let token = self.current_token.clone();
+        match token {
+            Token::Ident { raw, .. } if TYPE_NAMES.contains(&raw.as_str()) => {
+                Ok(TypeAnnotation::Named(token))
+            }
+            Token::Ident { .. } => Err(unknown_type(&token)),
+            Token::LeftBracket { .. } => {
                 self.next_token();
-                let ident_tok = self.current_token.clone();
-                match &self.peek_token {
-                    Token::Assign { .. } => {
+                let element = self.parse_type()?;
+                self.expect_peek(TokenKind::RightBracket)?;
+                Ok(TypeAnnotation::Array(ArrayType {
+                    token,
+                    element: Box::new(element),
+                    end: self.end(),
+                }))
+            }
+            Token::Function { .. } => {
+                self.expect_peek(TokenKind::LeftParen)?;
+                let mut parameters = Vec::new();
+                if self.peek_is(TokenKind::RightParen) {
+                    self.next_token();
+                } else {
+                    loop {
                         self.next_token();
-                        let result = Ok(Let {
-                            token: let_tok,
-                            identifier: ast::Expression::Identifier(ident_tok.clone()),
-                            value: ast::Expression::Identifier(self.peek_token.clone()), // TEMPORARY
-                        });
-
-                        loop {
-                            match &self.current_token {
-                                Token::Semicolon { .. } => break result,
-                                _ => {
-                                    self.next_token();
-                                }
-                            }
+                        parameters.push(self.parse_type()?);
+                        if !self.peek_is(TokenKind::Comma) {
+                            break;
                         }
+                        self.next_token();
                     }
-                    a => Err(ParserError(format!(
-                        "Unexpected Token '{:?}'. Expected assignment operator",
-                        a
-                    ))),
+                    self.expect_peek(TokenKind::RightParen)?;
                 }
+                self.expect_peek(TokenKind::Arrow)?;
+                self.next_token();
+                let result = self.parse_type()?;
+                Ok(TypeAnnotation::Function(FunctionType {
+                    token,
+                    parameters,
+                    result: Box::new(result),
+                }))
+            }
+            _ => Err(unexpected(&token, "type")),
+        }
+    }
+
+    fn parse_call_expression(
+        &mut self,
+        function: Expression<'a>,
+    ) -> Result<Expression<'a>, ParserError> {
+        let token = self.current_token.clone();
+        let arguments = self.parse_expression_list(TokenKind::RightParen)?;
+        Ok(Expression::Call(Call {
+            token,
+            function: Box::new(function),
+            arguments,
+            end: self.end(),
+        }))
+    }
+
+    /// Comma separated expressions after the current token, up to and
+    /// including `end`.
+    fn parse_expression_list(
+        &mut self,
+        end: TokenKind,
+    ) -> Result<Vec<Expression<'a>>, ParserError> {
+        let mut list = Vec::new();
+        if self.peek_is(end) {
+            self.next_token();
+            return Ok(list);
+        }
+        self.next_token();
+        list.push(self.parse_expression(Precedence::Lowest)?);
+        while self.peek_is(TokenKind::Comma) {
+            self.next_token();
+            self.next_token();
+            list.push(self.parse_expression(Precedence::Lowest)?);
+        }
+        self.expect_peek(end)?;
+        Ok(list)
+    }
+
+    fn parse_hash_literal(&mut self) -> Result<Expression<'a>, ParserError> {
+        let token = self.current_token.clone();
+        let mut pairs = Vec::new();
+        while !self.peek_is(TokenKind::RightBrace) {
+            self.next_token();
+            let key = self.parse_expression(Precedence::Lowest)?;
+            self.expect_peek(TokenKind::Colon)?;
+            self.next_token();
+            let value = self.parse_expression(Precedence::Lowest)?;
+            pairs.push((key, value));
+            if !self.peek_is(TokenKind::RightBrace) {
+                self.expect_peek(TokenKind::Comma)?;
             }
-            a => Err(ParserError(format!(
-                "Unexpected Token '{:?}'. Expected identifier",
-                a
-            ))),
         }
+        self.next_token();
+        Ok(Expression::Hash(HashLiteral {
+            token,
+            pairs,
+            end: self.end(),
+        }))
+    }
+
+    fn parse_index_expression(
+        &mut self,
+        left: Expression<'a>,
+    ) -> Result<Expression<'a>, ParserError> {
+        let token = self.current_token.clone();
+        self.next_token();
+        let index = self.parse_expression(Precedence::Lowest)?;
+        self.expect_peek(TokenKind::RightBracket)?;
+        Ok(Expression::Index(Index {
+            token,
+            left: Box::new(left),
+            index: Box::new(index),
+            end: self.end(),
+        }))
     }
 
     pub fn errors(&self) -> Vec<ParserError> {
@@ -129,13 +792,29 @@ impl<'a> Parser<'a> {
     }
 }
 
+/// Helpers shared by the tests of several modules.
+#[cfg(test)]
+pub(crate) mod testing {
+    /// A xorshift generator, so that failures can be replayed.
+    pub(crate) struct Rng(pub(crate) u64);
+
+    impl Rng {
+        pub(crate) fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use lexer::{tokens::Token, Lexer};
 
     use crate::{
-        ast::{Expression, Return, Statement},
-        Parser,
+        ast::{Expression, Statement, TypeAnnotation},
+        Parser, MAX_NESTING,
     };
 
     #[test]
@@ -151,15 +830,16 @@ let foobar = 838383;";
 
         assert_eq!(program.statements.len(), 3);
 
-        let ident_names = vec!["x", "y", "foobar"];
+        let ident_names = ["x", "y", "foobar"];
 
         for (i, t) in ident_names.iter().enumerate() {
             let stmt = &program.statements[i];
 
             if let Statement::Let(let_stmt) = stmt {
-                let Expression::Identifier(ident) = &let_stmt.identifier;
-                if let Token::Ident { raw, .. } = ident {
-                    assert_eq!(raw, t.to_owned());
+                if let Expression::Identifier(Token::Ident { raw, .. }) = &let_stmt.identifier {
+                    assert_eq!(raw, t);
+                } else {
+                    panic!("Let statement does not bind an identifier");
                 }
             } else {
                 // temporary
@@ -196,11 +876,452 @@ return 993322;";
 
         assert_eq!(parser.errors().len(), 0);
 
-        for (i, stmt) in program.statements.iter().enumerate() {
-            if let Statement::Return(ret) = stmt {
-            } else {
+        for stmt in program.statements.iter() {
+            if !matches!(stmt, Statement::Return(_)) {
                 panic!("Statement should be a return statement");
             }
         }
     }
+
+    fn parse(input: &str) -> crate::ast::Program<'_> {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program().expect("Failed to parse program");
+        let errors = parser.errors();
+        assert!(errors.is_empty(), "parser errors: {:?}", errors);
+        program
+    }
+
+    #[test]
+    fn test_lookahead() {
+        let mut parser = Parser::new(Lexer::new("let x = 5;"));
+
+        assert_eq!(parser.current_token, Token::Let { location: 0 });
+        assert_eq!(
+            parser.peek_nth(2),
+            &Token::Int {
+                location: 8,
+                raw: "5".into(),
+                value: 5
+            }
+        );
+        assert_eq!(
+            parser.peek_token(),
+            &Token::Ident {
+                location: 4,
+                raw: "x".into(),
+            }
+        );
+        parser.next_token();
+        assert_eq!(parser.peek_nth(2), &Token::Semicolon { location: 9 });
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        let tests = [
+            ("-a * b", "((-a) * b);"),
+            ("!-a", "(!(-a));"),
+            ("a + b + c", "((a + b) + c);"),
+            ("a + b - c", "((a + b) - c);"),
+            ("a * b * c", "((a * b) * c);"),
+            ("a * b / c", "((a * b) / c);"),
+            ("a + b / c", "(a + (b / c));"),
+            ("a + b * c + d / e - f", "(((a + (b * c)) + (d / e)) - f);"),
+            ("3 + 4; -5 * 5", "(3 + 4);\n((-5) * 5);"),
+            ("5 > 4 == 3 < 4", "((5 > 4) == (3 < 4));"),
+            ("5 < 4 != 3 > 4", "((5 < 4) != (3 > 4));"),
+            ("a..b + 1", "(a .. (b + 1));"),
+            ("0..=n == m", "(0 ..= (n == m));"),
+            ("5 < 10 > 5", "((5 < 10) > 5);"),
+            (
+                "3 + 4 * 5 == 3 * 1 + 4 * 5",
+                "((3 + (4 * 5)) == ((3 * 1) + (4 * 5)));",
+            ),
+            ("true", "true;"),
+            ("3 > 5 == false", "((3 > 5) == false);"),
+            ("1 + (2 + 3) + 4", "((1 + (2 + 3)) + 4);"),
+            ("(5 + 5) * 2", "((5 + 5) * 2);"),
+            ("-(5 + 5)", "(-(5 + 5));"),
+            ("!(true == true)", "(!(true == true));"),
+            ("a + add(b * c) + d", "((a + add((b * c))) + d);"),
+            (
+                "add(a, b, 1, 2 * 3, 4 + 5, add(6, 7 * 8))",
+                "add(a, b, 1, (2 * 3), (4 + 5), add(6, (7 * 8)));",
+            ),
+            (
+                "add(a + b + c * d / f + g)",
+                "add((((a + b) + ((c * d) / f)) + g));",
+            ),
+            (
+                "a * [1, 2, 3, 4][b * c] * d",
+                "((a * ([1, 2, 3, 4][(b * c)])) * d);",
+            ),
+            (
+                "add(a * b[2], b[1], 2 * [1, 2][1])",
+                "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])));",
+            ),
+            ("f(x)[0][1]", "((f(x)[0])[1]);"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse(input).to_string(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_compound_expressions() {
+        let tests = [
+            ("if (x < y) { x }", "if (x < y) { x; };"),
+            (
+                "if (x) { x } else { let y = 2; return y; }",
+                "if (x) { x; } else { let y = 2; return y; };",
+            ),
+            ("fn() {}", "fn() { };"),
+            (
+                "let add = fn(x, y) { x + y; };",
+                "let add = fn(x, y) { (x + y); };",
+            ),
+            ("fn(x) { x }(5)", "fn(x) { x; }(5);"),
+            ("return add(1, -2);", "return add(1, (-2));"),
+            (r#"let s = "hello world";"#, r#"let s = "hello world";"#),
+            (r#"["a\"b", 1 + 2, []]"#, r#"["a\"b", (1 + 2), []];"#),
+            ("let x: int = 5", "let x: int = 5;"),
+            (
+                r#"{"a": 1 + 2, true: {}}["a"]"#,
+                r#"({"a": (1 + 2), true: {}}["a"]);"#,
+            ),
+            (
+                "fn(a: int, b) -> fn([bool]) -> null { a }",
+                "fn(a: int, b) -> fn([bool]) -> null { a; };",
+            ),
+            ("throw \"oops\"", "throw \"oops\";"),
+            (
+                "let r = try { f() } catch (e) { throw e; }",
+                "let r = try { f(); } catch (e) { throw e; };",
+            ),
+            (
+                "while (i < 3) { if (i == 1) { break; } continue }",
+                "while (i < 3) { if (i == 1) { break; }; continue; }",
+            ),
+            (
+                "while (true) { let f = fn() { while (x) { break } }; }",
+                "while (true) { let f = fn() { while (x) { break; } }; }",
+            ),
+            (
+                "for (x in [1, 2]) { puts(x) }",
+                "for (x in [1, 2]) { puts(x); }",
+            ),
+            (
+                "for ([k, v] in h) { if (v) { break } };",
+                "for ([k, v] in h) { if (v) { break; }; }",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse(input).to_string(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        let input = "let five = 5;
+let add = fn(x, y) { x + y; };
+let result = add(five, -add(1, 2 * 3));
+if (5 < 10) { return !true; } else { return false; }
+fn(f) { f(f) }(fn(g) { g });
+10 != 9;
+[\"a\", [1, 2]][0][-1];";
+        let printed = parse(input).to_string();
+        assert_eq!(parse(&printed).to_string(), printed);
+    }
+
+    #[test]
+    fn test_spans() {
+        let input = "let x: [int] = add(1, [2, 3])[0] + -f({\"a\": 1});
+while (x) { x }
+if (a) { 1 } else { (2 + 3) };
+fn(n) { n }(007);
+return x;";
+        let program = parse(input);
+        let text = |span: std::ops::Range<usize>| &input[span];
+        let statements: Vec<&str> = program.statements.iter().map(|s| text(s.span())).collect();
+        assert_eq!(
+            statements,
+            vec![
+                "let x: [int] = add(1, [2, 3])[0] + -f({\"a\": 1})",
+                "while (x) { x }",
+                "if (a) { 1 } else { (2 + 3) }",
+                "fn(n) { n }(007)",
+                "return x",
+            ]
+        );
+
+        let Statement::Let(stmt) = &program.statements[0] else {
+            panic!("expected a let statement");
+        };
+        assert_eq!(text(stmt.annotation.as_ref().unwrap().span()), "[int]");
+        let Expression::Infix(sum) = &stmt.value else {
+            panic!("expected an infix expression");
+        };
+        assert_eq!(text(sum.left.span()), "add(1, [2, 3])[0]");
+        assert_eq!(text(sum.right.span()), "-f({\"a\": 1})");
+        let Statement::Expression(stmt) = &program.statements[2] else {
+            panic!("expected an expression statement");
+        };
+        let Expression::If(i) = &stmt.expression else {
+            panic!("expected an if expression");
+        };
+        assert_eq!(text(i.consequence.span()), "{ 1 }");
+        // parentheses are not part of the tree
+        assert_eq!(
+            text(i.alternative.as_ref().unwrap().statements[0].span()),
+            "2 + 3"
+        );
+    }
+
+    #[test]
+    fn test_expression_errors() {
+        let input = "let x = ;
+if (x { 1 };
+fn(1) { x };
+add(1, 2;
+[1, 2;
+a[1;
+{1 2};
+try { 1 } (e) { 2 };
+try { 1 } catch (2) {};";
+        let mut parser = Parser::new(Lexer::new(input));
+        let _ = parser.parse_program().expect("Failed to parse program");
+
+        let messages: Vec<String> = parser.errors().iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Unexpected ';'. Expected expression",
+                "Unexpected '{'. Expected ')'",
+                "Unexpected integer '1'. Expected identifier",
+                "Unexpected ';'. Expected ')'",
+                "Unexpected ';'. Expected ']'",
+                "Unexpected ';'. Expected ']'",
+                "Unexpected integer '2'. Expected ':'",
+                "Unexpected '('. Expected 'catch'",
+                "Unexpected integer '2'. Expected identifier",
+            ]
+        );
+        let locations: Vec<Option<usize>> = parser.errors().iter().map(|e| e.location()).collect();
+        assert_eq!(
+            locations,
+            vec![
+                Some(8),
+                Some(16),
+                Some(26),
+                Some(44),
+                Some(51),
+                Some(56),
+                Some(61),
+                Some(75),
+                Some(103),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_block_errors() {
+        let tests = [
+            ("let f = fn() { return; };", 1, 1),
+            ("let f = fn() { return; }; let g = 2;", 1, 2),
+            ("if (x) { let = 1; let y = 2; } else { y(; }; z", 2, 2),
+            ("while (x) { let a = {1: ; }; a }", 1, 1),
+            ("fn() { fn() { return; }; let = 2; 3 }", 2, 1),
+            ("fn() { let = 1;", 2, 0),
+        ];
+        for (input, errors, statements) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let program = parser.parse_program().expect("Failed to parse program");
+            assert_eq!(parser.errors().len(), errors, "input: {}", input);
+            assert_eq!(program.statements.len(), statements, "input: {}", input);
+            assert_eq!(
+                crate::cst::parse(input).errors,
+                parser.errors(),
+                "input: {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_loop_errors() {
+        let tests = [
+            ("break;", vec![0]),
+            ("if (x) { continue; }", vec![9]),
+            ("while (x) { fn() { break; } }", vec![19]),
+            ("while (true) { let f = fn() { break; }; }", vec![30]),
+            ("let h = fn() { break; }; h()", vec![15]),
+            ("while (x) { let f = fn() { 1 }; continue; }", vec![]),
+            ("for (x in xs) { break; }", vec![]),
+            ("for (1 in xs) {}", vec![5]),
+            ("for ([] in xs) {}", vec![6]),
+            ("for (x of xs) {}", vec![7]),
+        ];
+
+        // each mistake is reported once, without errors from recovering
+        for (input, locations) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            let _ = parser.parse_program().expect("Failed to parse program");
+            let errors: Vec<Option<usize>> = parser.errors().iter().map(|e| e.location()).collect();
+            assert_eq!(
+                errors,
+                locations.into_iter().map(Some).collect::<Vec<_>>(),
+                "input: {}",
+                input
+            );
+        }
+
+        let mut parser = Parser::new(Lexer::new("continue;"));
+        let _ = parser.parse_program();
+        assert_eq!(
+            parser.errors()[0].message(),
+            "Unexpected 'continue' outside of a loop"
+        );
+        let mut parser = Parser::new(Lexer::new("for (1 in xs) {}"));
+        let _ = parser.parse_program();
+        assert_eq!(
+            parser.errors()[0].message(),
+            "Unexpected integer '1'. Expected identifier or '['"
+        );
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let n = MAX_NESTING;
+        let parens = |k: usize| format!("{}1{}", "(".repeat(k), ")".repeat(k));
+        let blocks = |k: usize| format!("{}1{}", "if (x) { ".repeat(k), " }".repeat(k));
+        let types = |k: usize| format!("let x: {}int{} = 1;", "[".repeat(k), "]".repeat(k));
+        let tests = [
+            (parens(n - 1), None),
+            (parens(n), Some(n)),
+            (format!("1{}", "+1".repeat(n - 1)), None),
+            (format!("1{}", "+1".repeat(n)), Some(2 * n - 1)),
+            (format!("{}1", "-".repeat(n - 1)), None),
+            (format!("{}1", "-".repeat(n)), Some(n)),
+            (blocks(n / 2 - 1), None),
+            (blocks(n / 2), Some(9 * (n / 2))),
+            (types(n - 1), None),
+            (types(n), Some(7 + n)),
+            (
+                format!("{}1{}", "(".repeat(n / 2), ")+1".repeat(n / 2)),
+                Some(2 * n - 1),
+            ),
+        ];
+
+        for (input, location) in tests {
+            let mut parser = Parser::new(Lexer::new(&input));
+            let _ = parser.parse_program();
+            let errors = parser.errors();
+            assert_eq!(
+                errors.first().and_then(|e| e.location()),
+                location,
+                "input: {}",
+                input
+            );
+            if location.is_some() {
+                assert_eq!(errors[0].message(), "Nesting limit of 128 exceeded");
+            }
+        }
+    }
+
+    #[test]
+    fn test_annotations() {
+        let program = parse("let f: fn(int) -> int = fn(n: int) -> int { n };");
+        let Statement::Let(stmt) = &program.statements[0] else {
+            panic!("expected a let statement");
+        };
+        assert_eq!(
+            stmt.annotation.as_ref().map(|a| a.to_string()).as_deref(),
+            Some("fn(int) -> int")
+        );
+        let Expression::Function(function) = &stmt.value else {
+            panic!("expected a function");
+        };
+        assert_eq!(
+            function.parameter_type(0).map(|a| a.to_string()).as_deref(),
+            Some("int")
+        );
+        assert!(function.parameter_type(1).is_none());
+        assert!(matches!(
+            function.return_type,
+            Some(TypeAnnotation::Named(Token::Ident { location: 38, .. }))
+        ));
+
+        let input = "let x: = 1;
+let y: integer = 2;
+let z int = 3;
+fn(a: [int) { a };
+fn() -> { 1 };
+let g: fn(int) = 1;";
+        let mut parser = Parser::new(Lexer::new(input));
+        let _ = parser.parse_program().expect("Failed to parse program");
+        let errors: Vec<(String, Option<usize>)> = parser
+            .errors()
+            .iter()
+            .map(|e| (e.to_string(), e.location()))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                ("Unexpected '='. Expected type".to_string(), Some(7)),
+                (
+                    "Unknown type 'integer'. Expected one of int, bool, string, null".to_string(),
+                    Some(19)
+                ),
+                (
+                    "Unexpected identifier 'int'. Expected ':' or '='".to_string(),
+                    Some(38)
+                ),
+                ("Unexpected ')'. Expected ']'".to_string(), Some(57)),
+                ("Unexpected '{'. Expected type".to_string(), Some(74)),
+                ("Unexpected '='. Expected '->'".to_string(), Some(96)),
+            ]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json_schema() {
+        let program = parse("let x = -5;");
+        let json = serde_json::to_string(&program).expect("Failed to serialize");
+        assert_eq!(
+            json,
+            r#"{"statements":[{"type":"Let","token":{"kind":"Let","location":0},"identifier":{"type":"Identifier","kind":"Ident","location":4,"raw":"x"},"value":{"type":"Prefix","token":{"kind":"Minus","location":8},"right":{"type":"Integer","kind":"Int","location":9,"raw":"5","value":5}}}]}"#
+        );
+
+        let program = parse("let x: [int] = 1;");
+        let json = serde_json::to_string(&program).expect("Failed to serialize");
+        assert!(
+            json.contains(r#""annotation":{"type":"Array","token":{"kind":"LeftBracket","location":7},"element":{"type":"Named","kind":"Ident","location":8,"raw":"int"},"end":12}"#),
+            "{}",
+            json
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_json_round_trip() {
+        let program = parse(
+            "let add = fn(x: int, y) -> int { x + y; };
+if (add(1, 2) > 2) { return true; } else { !false }
+fn() {}();
+[\"a\", 1][0];
+{\"k\": [1], 2: {}};
+puts(\"say \\\"hi\\\"\\n\");
+try { throw 1; } catch (e) { e };
+while (!done) { if (x) { break; } continue; }
+for ([k, v] in 0..=3) { continue; }",
+        );
+        let json = serde_json::to_string_pretty(&program).expect("Failed to serialize");
+        let decoded: crate::ast::Program =
+            serde_json::from_str(&json).expect("Failed to deserialize");
+
+        assert_eq!(decoded.to_string(), program.to_string());
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", program));
+    }
 }
@@ -0,0 +1,726 @@
+//! Traversal of the syntax tree.
+//!
+//! `Visitor` walks a tree by shared reference and `VisitorMut` by mutable
+//! reference, so a pass can rewrite nodes in place (`*expr = ...`). Every
+//! method defaults to the matching `walk_*` function, which visits the
+//! node's children; override only the nodes a pass cares about and call the
+//! `walk_*` function from the override to keep descending.
+//!
+//! Every token stored in the tree is also reported to `visit_token`.
+
+use lexer::tokens::Token;
+
+use crate::ast::{
+    ArrayLiteral, Block, Call, Expression, ExpressionStatement, For, FunctionLiteral, HashLiteral,
+    If, Index, Infix, Let, Prefix, Program, Return, Statement, Throw, Try, TypeAnnotation, While,
+};
+
+pub trait Visitor {
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program)
+    }
+
+    fn visit_statement(&mut self, stmt: &Statement) {
+        walk_statement(self, stmt)
+    }
+
+    fn visit_let(&mut self, stmt: &Let) {
+        walk_let(self, stmt)
+    }
+
+    fn visit_return(&mut self, stmt: &Return) {
+        walk_return(self, stmt)
+    }
+
+    fn visit_throw(&mut self, stmt: &Throw) {
+        walk_throw(self, stmt)
+    }
+
+    fn visit_while(&mut self, stmt: &While) {
+        walk_while(self, stmt)
+    }
+
+    fn visit_for(&mut self, stmt: &For) {
+        walk_for(self, stmt)
+    }
+
+    fn visit_break(&mut self, token: &Token) {
+        self.visit_token(token)
+    }
+
+    fn visit_continue(&mut self, token: &Token) {
+        self.visit_token(token)
+    }
+
+    fn visit_expression_statement(&mut self, stmt: &ExpressionStatement) {
+        walk_expression_statement(self, stmt)
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        walk_block(self, block)
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        walk_expression(self, expr)
+    }
+
+    fn visit_identifier(&mut self, token: &Token) {
+        self.visit_token(token)
+    }
+
+    fn visit_integer(&mut self, token: &Token) {
+        self.visit_token(token)
+    }
+
+    fn visit_boolean(&mut self, token: &Token) {
+        self.visit_token(token)
+    }
+
+    fn visit_string(&mut self, token: &Token) {
+        self.visit_token(token)
+    }
+
+    fn visit_prefix(&mut self, expr: &Prefix) {
+        walk_prefix(self, expr)
+    }
+
+    fn visit_infix(&mut self, expr: &Infix) {
+        walk_infix(self, expr)
+    }
+
+    fn visit_if(&mut self, expr: &If) {
+        walk_if(self, expr)
+    }
+
+    fn visit_try(&mut self, expr: &Try) {
+        walk_try(self, expr)
+    }
+
+    fn visit_function(&mut self, expr: &FunctionLiteral) {
+        walk_function(self, expr)
+    }
+
+    fn visit_call(&mut self, expr: &Call) {
+        walk_call(self, expr)
+    }
+
+    fn visit_array(&mut self, expr: &ArrayLiteral) {
+        walk_array(self, expr)
+    }
+
+    fn visit_hash(&mut self, expr: &HashLiteral) {
+        walk_hash(self, expr)
+    }
+
+    fn visit_index(&mut self, expr: &Index) {
+        walk_index(self, expr)
+    }
+
+    fn visit_type_annotation(&mut self, annotation: &TypeAnnotation) {
+        walk_type_annotation(self, annotation)
+    }
+
+    fn visit_token(&mut self, _token: &Token) {}
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    for stmt in &program.statements {
+        visitor.visit_statement(stmt);
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Statement) {
+    match stmt {
+        Statement::Let(l) => visitor.visit_let(l),
+        Statement::Return(r) => visitor.visit_return(r),
+        Statement::Throw(t) => visitor.visit_throw(t),
+        Statement::While(w) => visitor.visit_while(w),
+        Statement::For(f) => visitor.visit_for(f),
+        Statement::Break(t) => visitor.visit_break(t),
+        Statement::Continue(t) => visitor.visit_continue(t),
+        Statement::Expression(e) => visitor.visit_expression_statement(e),
+    }
+}
+
+pub fn walk_let<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Let) {
+    visitor.visit_token(&stmt.token);
+    visitor.visit_expression(&stmt.identifier);
+    if let Some(annotation) = &stmt.annotation {
+        visitor.visit_type_annotation(annotation);
+    }
+    visitor.visit_expression(&stmt.value);
+}
+
+pub fn walk_return<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Return) {
+    visitor.visit_token(&stmt.token);
+    visitor.visit_expression(&stmt.return_value);
+}
+
+pub fn walk_throw<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Throw) {
+    visitor.visit_token(&stmt.token);
+    visitor.visit_expression(&stmt.value);
+}
+
+pub fn walk_while<V: Visitor + ?Sized>(visitor: &mut V, stmt: &While) {
+    visitor.visit_token(&stmt.token);
+    visitor.visit_expression(&stmt.condition);
+    visitor.visit_block(&stmt.body);
+}
+
+pub fn walk_for<V: Visitor + ?Sized>(visitor: &mut V, stmt: &For) {
+    visitor.visit_token(&stmt.token);
+    visitor.visit_expression(&stmt.variable);
+    visitor.visit_expression(&stmt.iterable);
+    visitor.visit_block(&stmt.body);
+}
+
+pub fn walk_expression_statement<V: Visitor + ?Sized>(visitor: &mut V, stmt: &ExpressionStatement) {
+    visitor.visit_token(&stmt.token);
+    visitor.visit_expression(&stmt.expression);
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &Block) {
+    visitor.visit_token(&block.token);
+    for stmt in &block.statements {
+        visitor.visit_statement(stmt);
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expression) {
+    match expr {
+        Expression::Identifier(t) => visitor.visit_identifier(t),
+        Expression::Integer(t) => visitor.visit_integer(t),
+        Expression::Boolean(t) => visitor.visit_boolean(t),
+        Expression::String(t) => visitor.visit_string(t),
+        Expression::Prefix(p) => visitor.visit_prefix(p),
+        Expression::Infix(i) => visitor.visit_infix(i),
+        Expression::If(i) => visitor.visit_if(i),
+        Expression::Try(t) => visitor.visit_try(t),
+        Expression::Function(f) => visitor.visit_function(f),
+        Expression::Call(c) => visitor.visit_call(c),
+        Expression::Array(a) => visitor.visit_array(a),
+        Expression::Hash(h) => visitor.visit_hash(h),
+        Expression::Index(i) => visitor.visit_index(i),
+    }
+}
+
+pub fn walk_prefix<V: Visitor + ?Sized>(visitor: &mut V, expr: &Prefix) {
+    visitor.visit_token(&expr.token);
+    visitor.visit_expression(&expr.right);
+}
+
+pub fn walk_infix<V: Visitor + ?Sized>(visitor: &mut V, expr: &Infix) {
+    visitor.visit_expression(&expr.left);
+    visitor.visit_token(&expr.token);
+    visitor.visit_expression(&expr.right);
+}
+
+pub fn walk_if<V: Visitor + ?Sized>(visitor: &mut V, expr: &If) {
+    visitor.visit_token(&expr.token);
+    visitor.visit_expression(&expr.condition);
+    visitor.visit_block(&expr.consequence);
+    if let Some(alternative) = &expr.alternative {
+        visitor.visit_block(alternative);
+    }
+}
+
+pub fn walk_try<V: Visitor + ?Sized>(visitor: &mut V, expr: &Try) {
+    visitor.visit_token(&expr.token);
+    visitor.visit_block(&expr.body);
+    visitor.visit_expression(&expr.parameter);
+    visitor.visit_block(&expr.handler);
+}
+
+pub fn walk_function<V: Visitor + ?Sized>(visitor: &mut V, expr: &FunctionLiteral) {
+    visitor.visit_token(&expr.token);
+    for (i, parameter) in expr.parameters.iter().enumerate() {
+        visitor.visit_expression(parameter);
+        if let Some(annotation) = expr.parameter_type(i) {
+            visitor.visit_type_annotation(annotation);
+        }
+    }
+    if let Some(annotation) = &expr.return_type {
+        visitor.visit_type_annotation(annotation);
+    }
+    visitor.visit_block(&expr.body);
+}
+
+pub fn walk_call<V: Visitor + ?Sized>(visitor: &mut V, expr: &Call) {
+    visitor.visit_expression(&expr.function);
+    visitor.visit_token(&expr.token);
+    for argument in &expr.arguments {
+        visitor.visit_expression(argument);
+    }
+}
+
+pub fn walk_array<V: Visitor + ?Sized>(visitor: &mut V, expr: &ArrayLiteral) {
+    visitor.visit_token(&expr.token);
+    for element in &expr.elements {
+        visitor.visit_expression(element);
+    }
+}
+
+pub fn walk_hash<V: Visitor + ?Sized>(visitor: &mut V, expr: &HashLiteral) {
+    visitor.visit_token(&expr.token);
+    for (key, value) in &expr.pairs {
+        visitor.visit_expression(key);
+        visitor.visit_expression(value);
+    }
+}
+
+pub fn walk_index<V: Visitor + ?Sized>(visitor: &mut V, expr: &Index) {
+    visitor.visit_expression(&expr.left);
+    visitor.visit_token(&expr.token);
+    visitor.visit_expression(&expr.index);
+}
+
+pub fn walk_type_annotation<V: Visitor + ?Sized>(visitor: &mut V, annotation: &TypeAnnotation) {
+    match annotation {
+        TypeAnnotation::Named(token) => visitor.visit_token(token),
+        TypeAnnotation::Array(a) => {
+            visitor.visit_token(&a.token);
+            visitor.visit_type_annotation(&a.element);
+        }
+        TypeAnnotation::Function(f) => {
+            visitor.visit_token(&f.token);
+            for parameter in &f.parameters {
+                visitor.visit_type_annotation(parameter);
+            }
+            visitor.visit_type_annotation(&f.result);
+        }
+    }
+}
+
+pub trait VisitorMut {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        walk_program_mut(self, program)
+    }
+
+    fn visit_statement_mut(&mut self, stmt: &mut Statement) {
+        walk_statement_mut(self, stmt)
+    }
+
+    fn visit_let_mut(&mut self, stmt: &mut Let) {
+        walk_let_mut(self, stmt)
+    }
+
+    fn visit_return_mut(&mut self, stmt: &mut Return) {
+        walk_return_mut(self, stmt)
+    }
+
+    fn visit_throw_mut(&mut self, stmt: &mut Throw) {
+        walk_throw_mut(self, stmt)
+    }
+
+    fn visit_while_mut(&mut self, stmt: &mut While) {
+        walk_while_mut(self, stmt)
+    }
+
+    fn visit_for_mut(&mut self, stmt: &mut For) {
+        walk_for_mut(self, stmt)
+    }
+
+    fn visit_break_mut(&mut self, token: &mut Token) {
+        self.visit_token_mut(token)
+    }
+
+    fn visit_continue_mut(&mut self, token: &mut Token) {
+        self.visit_token_mut(token)
+    }
+
+    fn visit_expression_statement_mut(&mut self, stmt: &mut ExpressionStatement) {
+        walk_expression_statement_mut(self, stmt)
+    }
+
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        walk_block_mut(self, block)
+    }
+
+    fn visit_expression_mut(&mut self, expr: &mut Expression) {
+        walk_expression_mut(self, expr)
+    }
+
+    fn visit_identifier_mut(&mut self, token: &mut Token) {
+        self.visit_token_mut(token)
+    }
+
+    fn visit_integer_mut(&mut self, token: &mut Token) {
+        self.visit_token_mut(token)
+    }
+
+    fn visit_boolean_mut(&mut self, token: &mut Token) {
+        self.visit_token_mut(token)
+    }
+
+    fn visit_string_mut(&mut self, token: &mut Token) {
+        self.visit_token_mut(token)
+    }
+
+    fn visit_prefix_mut(&mut self, expr: &mut Prefix) {
+        walk_prefix_mut(self, expr)
+    }
+
+    fn visit_infix_mut(&mut self, expr: &mut Infix) {
+        walk_infix_mut(self, expr)
+    }
+
+    fn visit_if_mut(&mut self, expr: &mut If) {
+        walk_if_mut(self, expr)
+    }
+
+    fn visit_try_mut(&mut self, expr: &mut Try) {
+        walk_try_mut(self, expr)
+    }
+
+    fn visit_function_mut(&mut self, expr: &mut FunctionLiteral) {
+        walk_function_mut(self, expr)
+    }
+
+    fn visit_call_mut(&mut self, expr: &mut Call) {
+        walk_call_mut(self, expr)
+    }
+
+    fn visit_array_mut(&mut self, expr: &mut ArrayLiteral) {
+        walk_array_mut(self, expr)
+    }
+
+    fn visit_hash_mut(&mut self, expr: &mut HashLiteral) {
+        walk_hash_mut(self, expr)
+    }
+
+    fn visit_index_mut(&mut self, expr: &mut Index) {
+        walk_index_mut(self, expr)
+    }
+
+    fn visit_type_annotation_mut(&mut self, annotation: &mut TypeAnnotation) {
+        walk_type_annotation_mut(self, annotation)
+    }
+
+    fn visit_token_mut(&mut self, _token: &mut Token) {}
+}
+
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(visitor: &mut V, program: &mut Program) {
+    for stmt in &mut program.statements {
+        visitor.visit_statement_mut(stmt);
+    }
+}
+
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut Statement) {
+    match stmt {
+        Statement::Let(l) => visitor.visit_let_mut(l),
+        Statement::Return(r) => visitor.visit_return_mut(r),
+        Statement::Throw(t) => visitor.visit_throw_mut(t),
+        Statement::While(w) => visitor.visit_while_mut(w),
+        Statement::For(f) => visitor.visit_for_mut(f),
+        Statement::Break(t) => visitor.visit_break_mut(t),
+        Statement::Continue(t) => visitor.visit_continue_mut(t),
+        Statement::Expression(e) => visitor.visit_expression_statement_mut(e),
+    }
+}
+
+pub fn walk_let_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut Let) {
+    visitor.visit_token_mut(&mut stmt.token);
+    visitor.visit_expression_mut(&mut stmt.identifier);
+    if let Some(annotation) = &mut stmt.annotation {
+        visitor.visit_type_annotation_mut(annotation);
+    }
+    visitor.visit_expression_mut(&mut stmt.value);
+}
+
+pub fn walk_return_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut Return) {
+    visitor.visit_token_mut(&mut stmt.token);
+    visitor.visit_expression_mut(&mut stmt.return_value);
+}
+
+pub fn walk_throw_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut Throw) {
+    visitor.visit_token_mut(&mut stmt.token);
+    visitor.visit_expression_mut(&mut stmt.value);
+}
+
+pub fn walk_while_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut While) {
+    visitor.visit_token_mut(&mut stmt.token);
+    visitor.visit_expression_mut(&mut stmt.condition);
+    visitor.visit_block_mut(&mut stmt.body);
+}
+
+pub fn walk_for_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut For) {
+    visitor.visit_token_mut(&mut stmt.token);
+    visitor.visit_expression_mut(&mut stmt.variable);
+    visitor.visit_expression_mut(&mut stmt.iterable);
+    visitor.visit_block_mut(&mut stmt.body);
+}
+
+pub fn walk_expression_statement_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    stmt: &mut ExpressionStatement,
+) {
+    visitor.visit_token_mut(&mut stmt.token);
+    visitor.visit_expression_mut(&mut stmt.expression);
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, block: &mut Block) {
+    visitor.visit_token_mut(&mut block.token);
+    for stmt in &mut block.statements {
+        visitor.visit_statement_mut(stmt);
+    }
+}
+
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Expression) {
+    match expr {
+        Expression::Identifier(t) => visitor.visit_identifier_mut(t),
+        Expression::Integer(t) => visitor.visit_integer_mut(t),
+        Expression::Boolean(t) => visitor.visit_boolean_mut(t),
+        Expression::String(t) => visitor.visit_string_mut(t),
+        Expression::Prefix(p) => visitor.visit_prefix_mut(p),
+        Expression::Infix(i) => visitor.visit_infix_mut(i),
+        Expression::If(i) => visitor.visit_if_mut(i),
+        Expression::Try(t) => visitor.visit_try_mut(t),
+        Expression::Function(f) => visitor.visit_function_mut(f),
+        Expression::Call(c) => visitor.visit_call_mut(c),
+        Expression::Array(a) => visitor.visit_array_mut(a),
+        Expression::Hash(h) => visitor.visit_hash_mut(h),
+        Expression::Index(i) => visitor.visit_index_mut(i),
+    }
+}
+
+pub fn walk_prefix_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Prefix) {
+    visitor.visit_token_mut(&mut expr.token);
+    visitor.visit_expression_mut(&mut expr.right);
+}
+
+pub fn walk_infix_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Infix) {
+    visitor.visit_expression_mut(&mut expr.left);
+    visitor.visit_token_mut(&mut expr.token);
+    visitor.visit_expression_mut(&mut expr.right);
+}
+
+pub fn walk_if_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut If) {
+    visitor.visit_token_mut(&mut expr.token);
+    visitor.visit_expression_mut(&mut expr.condition);
+    visitor.visit_block_mut(&mut expr.consequence);
+    if let Some(alternative) = &mut expr.alternative {
+        visitor.visit_block_mut(alternative);
+    }
+}
+
+pub fn walk_try_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Try) {
+    visitor.visit_token_mut(&mut expr.token);
+    visitor.visit_block_mut(&mut expr.body);
+    visitor.visit_expression_mut(&mut expr.parameter);
+    visitor.visit_block_mut(&mut expr.handler);
+}
+
+pub fn walk_function_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut FunctionLiteral) {
+    visitor.visit_token_mut(&mut expr.token);
+    for (i, parameter) in expr.parameters.iter_mut().enumerate() {
+        visitor.visit_expression_mut(parameter);
+        if let Some(Some(annotation)) = expr.parameter_types.get_mut(i) {
+            visitor.visit_type_annotation_mut(annotation);
+        }
+    }
+    if let Some(annotation) = &mut expr.return_type {
+        visitor.visit_type_annotation_mut(annotation);
+    }
+    visitor.visit_block_mut(&mut expr.body);
+}
+
+pub fn walk_call_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Call) {
+    visitor.visit_expression_mut(&mut expr.function);
+    visitor.visit_token_mut(&mut expr.token);
+    for argument in &mut expr.arguments {
+        visitor.visit_expression_mut(argument);
+    }
+}
+
+pub fn walk_array_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut ArrayLiteral) {
+    visitor.visit_token_mut(&mut expr.token);
+    for element in &mut expr.elements {
+        visitor.visit_expression_mut(element);
+    }
+}
+
+pub fn walk_hash_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut HashLiteral) {
+    visitor.visit_token_mut(&mut expr.token);
+    for (key, value) in &mut expr.pairs {
+        visitor.visit_expression_mut(key);
+        visitor.visit_expression_mut(value);
+    }
+}
+
+pub fn walk_index_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Index) {
+    visitor.visit_expression_mut(&mut expr.left);
+    visitor.visit_token_mut(&mut expr.token);
+    visitor.visit_expression_mut(&mut expr.index);
+}
+
+pub fn walk_type_annotation_mut<V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    annotation: &mut TypeAnnotation,
+) {
+    match annotation {
+        TypeAnnotation::Named(token) => visitor.visit_token_mut(token),
+        TypeAnnotation::Array(a) => {
+            visitor.visit_token_mut(&mut a.token);
+            visitor.visit_type_annotation_mut(&mut a.element);
+        }
+        TypeAnnotation::Function(f) => {
+            visitor.visit_token_mut(&mut f.token);
+            for parameter in &mut f.parameters {
+                visitor.visit_type_annotation_mut(parameter);
+            }
+            visitor.visit_type_annotation_mut(&mut f.result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lexer::{tokens::Token, Lexer};
+
+    use super::*;
+    use crate::Parser;
+
+    fn parse(input: &str) -> Program<'_> {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program().expect("Failed to parse program");
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        program
+    }
+
+    #[derive(Default)]
+    struct Counter {
+        identifiers: Vec<String>,
+        functions: usize,
+        jumps: Vec<String>,
+        tokens: usize,
+    }
+
+    impl Visitor for Counter {
+        fn visit_identifier(&mut self, token: &Token) {
+            self.identifiers.push(token.to_string());
+            self.visit_token(token);
+        }
+
+        fn visit_function(&mut self, expr: &FunctionLiteral) {
+            self.functions += 1;
+            walk_function(self, expr);
+        }
+
+        fn visit_break(&mut self, token: &Token) {
+            self.jumps.push(token.to_string());
+            self.visit_token(token);
+        }
+
+        fn visit_continue(&mut self, token: &Token) {
+            self.jumps.push(token.to_string());
+            self.visit_token(token);
+        }
+
+        fn visit_token(&mut self, _token: &Token) {
+            self.tokens += 1;
+        }
+    }
+
+    #[test]
+    fn test_visitor_reaches_every_node() {
+        let program = parse(
+            "let add = fn(x, y) { x + y };
+if (add(1, -two) > 2) { return fn() { z }; } else { !true };
+[w, \"s\"][i];
+{k: v};
+try { throw e; } catch (c) { c };
+while (b) { break; continue };
+for ([p, q] in 0..r) { p }",
+        );
+        let mut counter = Counter::default();
+        counter.visit_program(&program);
+
+        assert_eq!(
+            counter.identifiers,
+            [
+                "add", "x", "y", "x", "y", "add", "two", "z", "w", "i", "k", "v", "e", "c", "c",
+                "b", "p", "q", "r", "p"
+            ]
+        );
+        assert_eq!(counter.functions, 2);
+        assert_eq!(counter.jumps, ["break", "continue"]);
+        // expression statements repeat the first token of their expression;
+        // parentheses, commas, semicolons, `else`, `catch`, `in` and `}`
+        // are not stored
+        assert_eq!(counter.tokens, 63);
+    }
+
+    /// Collects the tokens of type annotations.
+    #[derive(Default)]
+    struct Annotations {
+        depth: usize,
+        tokens: Vec<String>,
+    }
+
+    impl Visitor for Annotations {
+        fn visit_type_annotation(&mut self, annotation: &TypeAnnotation) {
+            self.depth += 1;
+            walk_type_annotation(self, annotation);
+            self.depth -= 1;
+        }
+
+        fn visit_token(&mut self, token: &Token) {
+            if self.depth > 0 {
+                self.tokens.push(token.to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn test_visitor_reaches_type_annotations() {
+        let program = parse(
+            "let x: [int] = 1; let g = fn(a: int, b, c: fn(bool) -> [string]) -> null { let y: bool = a; };",
+        );
+        let mut annotations = Annotations::default();
+        annotations.visit_program(&program);
+        assert_eq!(
+            annotations.tokens,
+            ["[", "int", "int", "fn", "bool", "[", "string", "null", "bool"]
+        );
+    }
+
+    /// Replaces `left + right` on two integer literals with their sum.
+    struct FoldAdditions;
+
+    impl VisitorMut for FoldAdditions {
+        fn visit_expression_mut(&mut self, expr: &mut Expression) {
+            walk_expression_mut(self, expr);
+            if let Expression::Infix(Infix {
+                token: token @ Token::Plus { .. },
+                left,
+                right,
+            }) = expr
+            {
+                if let (
+                    Expression::Integer(Token::Int { value: a, .. }),
+                    Expression::Integer(Token::Int { value: b, .. }),
+                ) = (left.as_ref(), right.as_ref())
+                {
+                    *expr = Expression::Integer(Token::Int {
+                        location: token.location().unwrap_or_default(),
+                        raw: (a + b).to_string().into(),
+                        value: a + b,
+                    });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_visitor_mut_rewrites_nodes() {
+        let mut program = parse(
+            "let x = 1 + 2 + 3; f(4 + 5, x + 1); fn() { 2 + 2 * 3 }; [1 + 1][0 + 0]; {1 + 2: 3}; throw 1 + 1; while (1 + 1) { break; } for (x in 1 + 1..3) {}",
+        );
+        FoldAdditions.visit_program_mut(&mut program);
+
+        assert_eq!(
+            program.to_string(),
+            "let x = 6;\nf(9, (x + 1));\nfn() { (2 + (2 * 3)); };\n([2][0]);\n{3: 3};\nthrow 2;\nwhile (2) { break; }\nfor (x in (2 .. 3)) { }"
+        );
+    }
+}
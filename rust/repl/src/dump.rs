@@ -0,0 +1,433 @@
+use lexer::{lines::LineIndex, tokens::Token, Lexer};
+use parser::ast::{Block, Expression, Program, Statement, TypeAnnotation};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl Format {
+    pub fn parse(raw: &str) -> Option<Format> {
+        match raw {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Splits a token into its variant name and the payload it carries, if any.
+fn describe(token: &Token) -> (&'static str, Option<String>) {
+    match token {
+        Token::Illegal { raw, .. } => ("Illegal", Some(raw.to_string())),
+        Token::EOF => ("EOF", None),
+        Token::Comment { raw, .. } => ("Comment", Some(raw.to_string())),
+        Token::Ident { raw, .. } => ("Ident", Some(raw.to_string())),
+        Token::Int { value, .. } => ("Int", Some(value.to_string())),
+        Token::String { raw, .. } => ("String", Some(raw.to_string())),
+        Token::Assign { .. } => ("Assign", None),
+        Token::Plus { .. } => ("Plus", None),
+        Token::Minus { .. } => ("Minus", None),
+        Token::Multiply { .. } => ("Multiply", None),
+        Token::Divide { .. } => ("Divide", None),
+        Token::Negation { .. } => ("Negation", None),
+        Token::Equals { .. } => ("Equals", None),
+        Token::NotEquals { .. } => ("NotEquals", None),
+        Token::LessThan { .. } => ("LessThan", None),
+        Token::GreaterThan { .. } => ("GreaterThan", None),
+        Token::DotDot { .. } => ("DotDot", None),
+        Token::DotDotEquals { .. } => ("DotDotEquals", None),
+        Token::Comma { .. } => ("Comma", None),
+        Token::Semicolon { .. } => ("Semicolon", None),
+        Token::Colon { .. } => ("Colon", None),
+        Token::Arrow { .. } => ("Arrow", None),
+        Token::LeftParen { .. } => ("LeftParen", None),
+        Token::RightParen { .. } => ("RightParen", None),
+        Token::LeftBrace { .. } => ("LeftBrace", None),
+        Token::RightBrace { .. } => ("RightBrace", None),
+        Token::LeftBracket { .. } => ("LeftBracket", None),
+        Token::RightBracket { .. } => ("RightBracket", None),
+        Token::Function { .. } => ("Function", None),
+        Token::Let { .. } => ("Let", None),
+        Token::True { .. } => ("True", None),
+        Token::False { .. } => ("False", None),
+        Token::If { .. } => ("If", None),
+        Token::Else { .. } => ("Else", None),
+        Token::Return { .. } => ("Return", None),
+        Token::Throw { .. } => ("Throw", None),
+        Token::Try { .. } => ("Try", None),
+        Token::Catch { .. } => ("Catch", None),
+        Token::While { .. } => ("While", None),
+        Token::Break { .. } => ("Break", None),
+        Token::Continue { .. } => ("Continue", None),
+        Token::For { .. } => ("For", None),
+        Token::In { .. } => ("In", None),
+    }
+}
+
+pub fn tokens(source: &str, format: Format) -> String {
+    let index = LineIndex::new(source);
+    let mut lines = Vec::new();
+    for token in Lexer::new(source) {
+        let (kind, payload) = describe(&token);
+        let position = token
+            .span()
+            .map(|span| (span.end, index.line_col(span.start)));
+        lines.push(match format {
+            Format::Text => {
+                let at = match position {
+                    Some((_, (line, col))) => format!("{}:{}", line, col),
+                    None => String::from("-"),
+                };
+                match payload {
+                    Some(p) => format!("{}\t{}\t{}", at, kind, p),
+                    None => format!("{}\t{}", at, kind),
+                }
+            }
+            Format::Json => {
+                let mut value = serde_json::to_value(token).expect("tokens serialize to JSON");
+                if let (Some(fields), Some((end, (line, col)))) = (value.as_object_mut(), position)
+                {
+                    fields.insert(String::from("end"), end.into());
+                    fields.insert(String::from("line"), line.into());
+                    fields.insert(String::from("column"), col.into());
+                }
+                value.to_string()
+            }
+        });
+    }
+    match format {
+        Format::Text => lines.join("\n"),
+        Format::Json => format!("[{}]", lines.join(",")),
+    }
+}
+
+/// The AST flattened into labelled nodes for the indented text dump.
+struct Tree {
+    label: String,
+    location: Option<usize>,
+    children: Vec<Tree>,
+}
+
+impl Tree {
+    fn leaf(label: String, location: Option<usize>) -> Self {
+        Tree {
+            label,
+            location,
+            children: Vec::new(),
+        }
+    }
+
+    fn render_text(&self, index: &LineIndex, depth: usize, out: &mut Vec<String>) {
+        let at = match self.location {
+            Some(l) => {
+                let (line, col) = index.line_col(l);
+                format!(" @{}:{}", line, col)
+            }
+            None => String::new(),
+        };
+        out.push(format!("{}{}{}", "  ".repeat(depth), self.label, at));
+        for child in &self.children {
+            child.render_text(index, depth + 1, out);
+        }
+    }
+}
+
+fn token_tree(field: &str, node: &str, token: &Token) -> Tree {
+    Tree::leaf(format!("{}: {} {}", field, node, token), token.location())
+}
+
+fn node_tree(field: &str, node: &str, token: &Token, children: Vec<Tree>) -> Tree {
+    Tree {
+        label: format!("{}: {}", field, node),
+        location: token.location(),
+        children,
+    }
+}
+
+fn block_tree(field: &str, block: &Block) -> Tree {
+    Tree {
+        label: format!("{}: Block", field),
+        location: block.token.location(),
+        children: block.statements.iter().map(statement_tree).collect(),
+    }
+}
+
+fn annotation_tree(field: &str, annotation: &TypeAnnotation) -> Tree {
+    let token = match annotation {
+        TypeAnnotation::Named(token) => token,
+        TypeAnnotation::Array(a) => &a.token,
+        TypeAnnotation::Function(f) => &f.token,
+    };
+    Tree::leaf(format!("{}: Type {}", field, annotation), token.location())
+}
+
+fn list_tree(field: &str, items: &[Expression]) -> Tree {
+    Tree {
+        label: format!("{}: [{}]", field, items.len()),
+        location: None,
+        children: items.iter().map(|e| expression_tree("-", e)).collect(),
+    }
+}
+
+fn expression_tree(field: &str, expr: &Expression) -> Tree {
+    match expr {
+        Expression::Identifier(token) => token_tree(field, "Identifier", token),
+        Expression::Integer(token) => token_tree(field, "Integer", token),
+        Expression::Boolean(token) => token_tree(field, "Boolean", token),
+        Expression::String(token) => token_tree(field, "String", token),
+        Expression::Prefix(p) => node_tree(
+            field,
+            &format!("Prefix {}", p.token),
+            &p.token,
+            vec![expression_tree("right", &p.right)],
+        ),
+        Expression::Infix(i) => node_tree(
+            field,
+            &format!("Infix {}", i.token),
+            &i.token,
+            vec![
+                expression_tree("left", &i.left),
+                expression_tree("right", &i.right),
+            ],
+        ),
+        Expression::If(i) => {
+            let mut children = vec![
+                expression_tree("condition", &i.condition),
+                block_tree("consequence", &i.consequence),
+            ];
+            if let Some(alternative) = &i.alternative {
+                children.push(block_tree("alternative", alternative));
+            }
+            node_tree(field, "If", &i.token, children)
+        }
+        Expression::Try(t) => node_tree(
+            field,
+            "Try",
+            &t.token,
+            vec![
+                block_tree("body", &t.body),
+                expression_tree("parameter", &t.parameter),
+                block_tree("handler", &t.handler),
+            ],
+        ),
+        Expression::Function(f) => {
+            let mut children = vec![list_tree("parameters", &f.parameters)];
+            if !f.parameter_types.is_empty() {
+                children.push(Tree {
+                    label: format!("parameter_types: [{}]", f.parameter_types.len()),
+                    location: None,
+                    children: f
+                        .parameter_types
+                        .iter()
+                        .map(|ty| match ty {
+                            Some(ty) => annotation_tree("-", ty),
+                            None => Tree::leaf(String::from("-: None"), None),
+                        })
+                        .collect(),
+                });
+            }
+            if let Some(ty) = &f.return_type {
+                children.push(annotation_tree("return_type", ty));
+            }
+            children.push(block_tree("body", &f.body));
+            node_tree(field, "Function", &f.token, children)
+        }
+        Expression::Call(c) => node_tree(
+            field,
+            "Call",
+            &c.token,
+            vec![
+                expression_tree("function", &c.function),
+                list_tree("arguments", &c.arguments),
+            ],
+        ),
+        Expression::Array(a) => node_tree(
+            field,
+            "Array",
+            &a.token,
+            vec![list_tree("elements", &a.elements)],
+        ),
+        Expression::Hash(h) => node_tree(
+            field,
+            "Hash",
+            &h.token,
+            vec![Tree {
+                label: format!("pairs: [{}]", h.pairs.len()),
+                location: None,
+                children: h
+                    .pairs
+                    .iter()
+                    .map(|(key, value)| Tree {
+                        label: String::from("-: Pair"),
+                        location: None,
+                        children: vec![
+                            expression_tree("key", key),
+                            expression_tree("value", value),
+                        ],
+                    })
+                    .collect(),
+            }],
+        ),
+        Expression::Index(i) => node_tree(
+            field,
+            "Index",
+            &i.token,
+            vec![
+                expression_tree("left", &i.left),
+                expression_tree("index", &i.index),
+            ],
+        ),
+    }
+}
+
+fn statement_tree(stmt: &Statement) -> Tree {
+    match stmt {
+        Statement::Let(l) => {
+            let mut children = vec![expression_tree("identifier", &l.identifier)];
+            if let Some(annotation) = &l.annotation {
+                children.push(annotation_tree("annotation", annotation));
+            }
+            children.push(expression_tree("value", &l.value));
+            Tree {
+                label: String::from("Let"),
+                location: l.token.location(),
+                children,
+            }
+        }
+        Statement::Return(r) => Tree {
+            label: String::from("Return"),
+            location: r.token.location(),
+            children: vec![expression_tree("return_value", &r.return_value)],
+        },
+        Statement::Throw(t) => Tree {
+            label: String::from("Throw"),
+            location: t.token.location(),
+            children: vec![expression_tree("value", &t.value)],
+        },
+        Statement::While(w) => Tree {
+            label: String::from("While"),
+            location: w.token.location(),
+            children: vec![
+                expression_tree("condition", &w.condition),
+                block_tree("body", &w.body),
+            ],
+        },
+        Statement::For(f) => Tree {
+            label: String::from("For"),
+            location: f.token.location(),
+            children: vec![
+                expression_tree("variable", &f.variable),
+                expression_tree("iterable", &f.iterable),
+                block_tree("body", &f.body),
+            ],
+        },
+        Statement::Break(t) => Tree::leaf(String::from("Break"), t.location()),
+        Statement::Continue(t) => Tree::leaf(String::from("Continue"), t.location()),
+        Statement::Expression(e) => Tree {
+            label: String::from("Expression"),
+            location: e.token.location(),
+            children: vec![expression_tree("expression", &e.expression)],
+        },
+    }
+}
+
+pub fn ast(source: &str, program: &Program, format: Format) -> String {
+    let tree = Tree {
+        label: String::from("Program"),
+        location: None,
+        children: program.statements.iter().map(statement_tree).collect(),
+    };
+    match format {
+        Format::Text => {
+            let mut out = Vec::new();
+            tree.render_text(&LineIndex::new(source), 0, &mut out);
+            out.join("\n")
+        }
+        Format::Json => serde_json::to_string(program).expect("programs serialize to JSON"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::Parser;
+
+    #[test]
+    fn test_token_dump() {
+        let text = tokens("let x = 5;", Format::Text);
+        assert_eq!(
+            text,
+            "1:1\tLet\n1:5\tIdent\tx\n1:7\tAssign\n1:9\tInt\t5\n1:10\tSemicolon\n-\tEOF"
+        );
+
+        let json = tokens("x\n\"", Format::Json);
+        assert_eq!(
+            json,
+            r#"[{"column":1,"end":1,"kind":"Ident","line":1,"location":0,"raw":"x"},{"column":1,"end":3,"kind":"Illegal","line":2,"location":2,"raw":"\""},{"kind":"EOF"}]"#
+        );
+    }
+
+    #[test]
+    fn test_ast_dump() {
+        let input = "let x: int = 5;\nreturn fn(a: [int], b) -> int { a };\n{\"k\": 1};\ntry { throw 1; } catch (e) {}\nwhile (x) { break; continue; }\nfor (c in 0..3) {}";
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program().expect("Failed to parse program");
+
+        assert_eq!(
+            ast(input, &program, Format::Text),
+            "Program
+  Let @1:1
+    identifier: Identifier x @1:5
+    annotation: Type int @1:8
+    value: Integer 5 @1:14
+  Return @2:1
+    return_value: Function @2:8
+      parameters: [2]
+        -: Identifier a @2:11
+        -: Identifier b @2:21
+      parameter_types: [2]
+        -: Type [int] @2:14
+        -: None
+      return_type: Type int @2:27
+      body: Block @2:31
+        Expression @2:33
+          expression: Identifier a @2:33
+  Expression @3:1
+    expression: Hash @3:1
+      pairs: [1]
+        -: Pair
+          key: String \"k\" @3:2
+          value: Integer 1 @3:7
+  Expression @4:1
+    expression: Try @4:1
+      body: Block @4:5
+        Throw @4:7
+          value: Integer 1 @4:13
+      parameter: Identifier e @4:25
+      handler: Block @4:28
+  While @5:1
+    condition: Identifier x @5:8
+    body: Block @5:11
+      Break @5:13
+      Continue @5:20
+  For @6:1
+    variable: Identifier c @6:6
+    iterable: Infix .. @6:12
+      left: Integer 0 @6:11
+      right: Integer 3 @6:14
+    body: Block @6:17"
+        );
+        let json = ast(input, &program, Format::Json);
+        let decoded: Program = serde_json::from_str(&json).expect("Failed to deserialize");
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", program));
+        assert!(
+            json.contains(
+                r#""body":{"token":{"kind":"LeftBrace","location":140},"statements":[],"end":142}"#
+            ),
+            "{}",
+            json
+        );
+    }
+}
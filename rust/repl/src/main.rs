@@ -1,31 +1,385 @@
-use lexer::tokens::Token;
-use lexer::Lexer;
-use std::io::{stdin, stdout, Write};
+mod dump;
+mod repl;
 
-fn main() {
-    println!("Welcome to the Monkey-Lang REPL");
-    loop {
-        let mut input = String::new();
-        print!(">> ");
-        let _ = stdout().flush();
-        stdin().read_line(&mut input).expect("Input error!");
-        if let Some('\n') = input.chars().next_back() {
-            input.pop();
-        }
-        if let Some('\r') = input.chars().next_back() {
-            input.pop();
-        }
-
-        let mut tok = Lexer::new(&input);
-        loop {
-            match tok.next_token() {
-                Token::EOF => {
-                    break;
-                }
-                a => {
-                    println!("{:?}", a);
+use std::{
+    env, fs,
+    io::{stderr, stdout, IsTerminal},
+    path::PathBuf,
+    process, thread,
+};
+
+use dump::Format;
+use evaluator::{limits::DEFAULT_MAX_STACK, Builtins, EvalError, Evaluator, Frame, Limits};
+use highlighter::diagnostic::{token_span, Diagnostic};
+use lexer::{lines::LineIndex, Lexer};
+use linter::{Config, Linter};
+use parser::{
+    ast::{Expression, Program, Statement},
+    Parser, ParserError,
+};
+use resolver::Resolver;
+
+const USAGE: &str = "usage: monkey [COMMAND FILE... [OPTIONS]]
+
+With no command, starts the interactive REPL.
+
+Commands:
+    run FILE       evaluate FILE
+    tokens FILE    print every token of FILE with its location
+    ast FILE       print the parsed syntax tree of FILE
+    fmt FILE...    rewrite each FILE in the canonical layout
+    highlight FILE print FILE with syntax highlighting
+    check FILE     report undefined, shadowed and unused names in FILE
+    lint FILE      report suspicious code in FILE
+    typecheck FILE report type errors in FILE, or print the type of each top-level let
+
+Options:
+    --format text|json    output format for tokens and ast (default: text)
+    --check               fmt: list unformatted files and exit 1 instead of rewriting
+    --html                highlight: print a standalone HTML page instead of ANSI colors
+    --config PATH         lint: rule settings to use instead of the nearest .monkeylint";
+
+/// Stack for the thread commands run on, so scripts can recurse much deeper
+/// than the main thread's stack would allow.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// An evaluator that may use the stack commands run on, less the room the
+/// default limit leaves for everything else.
+fn evaluator() -> Evaluator {
+    let mut evaluator = Evaluator::new();
+    evaluator.set_limits(Limits {
+        max_stack: STACK_SIZE - DEFAULT_MAX_STACK,
+        ..Limits::default()
+    });
+    evaluator
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(2);
+}
+
+struct Args {
+    command: String,
+    files: Vec<String>,
+    format: Option<Format>,
+    check: bool,
+    html: bool,
+    config: Option<String>,
+}
+
+impl Args {
+    fn parse(command: String, raw: &[String]) -> Self {
+        let mut args = Args {
+            command,
+            files: Vec::new(),
+            format: None,
+            check: false,
+            html: false,
+            config: None,
+        };
+        let mut rest = raw.iter();
+        while let Some(arg) = rest.next() {
+            let raw_format = if arg == "--format" {
+                rest.next().map(String::as_str)
+            } else if let Some(raw) = arg.strip_prefix("--format=") {
+                Some(raw)
+            } else if arg == "--check" {
+                args.check = true;
+                continue;
+            } else if arg == "--html" {
+                args.html = true;
+                continue;
+            } else if arg == "--config" || arg.starts_with("--config=") {
+                let path = match arg.strip_prefix("--config=") {
+                    Some(path) => Some(path.to_string()),
+                    None => rest.next().cloned(),
+                };
+                args.config = Some(path.unwrap_or_else(|| fail("--config expects a PATH")));
+                continue;
+            } else if arg.starts_with("--") {
+                fail(&format!("unknown option '{}'", arg));
+            } else {
+                args.files.push(arg.clone());
+                continue;
+            };
+            args.format = Some(
+                raw_format
+                    .and_then(Format::parse)
+                    .unwrap_or_else(|| fail("--format expects 'text' or 'json'")),
+            );
+        }
+
+        if args.files.is_empty() {
+            fail(&format!("'{}' expects a FILE argument", args.command));
+        }
+        if args.command != "fmt" && args.check {
+            fail("--check is only supported by 'fmt'");
+        }
+        if matches!(
+            args.command.as_str(),
+            "fmt" | "highlight" | "check" | "lint" | "typecheck" | "run"
+        ) && args.format.is_some()
+        {
+            fail(&format!("--format is not supported by '{}'", args.command));
+        }
+        if args.command != "highlight" && args.html {
+            fail("--html is only supported by 'highlight'");
+        }
+        if args.command != "lint" && args.config.is_some() {
+            fail("--config is only supported by 'lint'");
+        }
+        if args.command != "fmt" && args.files.len() > 1 {
+            fail(&format!("'{}' expects a single FILE", args.command));
+        }
+        args
+    }
+}
+
+fn read(file: &str) -> String {
+    fs::read_to_string(file).unwrap_or_else(|e| {
+        eprintln!("could not read {}: {}", file, e);
+        process::exit(1);
+    })
+}
+
+/// Prints each error quoting the source line it is about, then exits 1.
+fn report(file: &str, source: &str, errors: &[ParserError]) -> ! {
+    let color = stderr().is_terminal();
+    for error in errors {
+        let span = token_span(source, error.location());
+        let diagnostic = Diagnostic::error(error.message(), span);
+        eprintln!("{}\n", diagnostic.render(file, source, color));
+    }
+    process::exit(1);
+}
+
+/// The program in `source`, or reports its syntax errors and exits 1.
+fn parse<'a>(file: &str, source: &'a str) -> Program<'a> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse_program().expect("Failed to parse program");
+    let errors = parser.errors();
+    if !errors.is_empty() {
+        report(file, source, &errors);
+    }
+    program
+}
+
+/// Reports every name resolution problem; exits 1 if any is an error.
+fn check(file: &str) {
+    let source = read(file);
+    let program = parse(file, &source);
+    let color = stderr().is_terminal();
+    let builtins = Builtins::standard();
+    let problems = Resolver::with_globals(builtins.names()).resolve(&program);
+    for problem in &problems {
+        let diagnostic = if problem.is_error() {
+            Diagnostic::error(problem.to_string(), problem.span.clone())
+        } else {
+            Diagnostic::warning(problem.to_string(), problem.span.clone())
+        };
+        eprintln!("{}\n", diagnostic.render(file, &source, color));
+    }
+    if problems.iter().any(|p| p.is_error()) {
+        process::exit(1);
+    }
+}
+
+/// The `.monkeylint` in the directory of `file` or the closest parent.
+fn find_lint_config(file: &str) -> Option<PathBuf> {
+    let file = fs::canonicalize(file).ok()?;
+    file.ancestors()
+        .skip(1)
+        .map(|dir| dir.join(".monkeylint"))
+        .find(|path| path.is_file())
+}
+
+/// Prints every lint as a warning; exits 1 if there are any.
+fn lint(args: &Args) {
+    let file = &args.files[0];
+    let source = read(file);
+    let config_path = args
+        .config
+        .as_ref()
+        .map(PathBuf::from)
+        .or_else(|| find_lint_config(file));
+    let config = match config_path {
+        Some(path) => {
+            let text = read(&path.to_string_lossy());
+            Config::parse(&text).unwrap_or_else(|e| {
+                eprintln!("{}: {}", path.display(), e);
+                process::exit(1);
+            })
+        }
+        None => Config::default(),
+    };
+
+    let program = parse(file, &source);
+    let color = stderr().is_terminal();
+    let lints = Linter::new(&config).check(&source, &program);
+    for lint in &lints {
+        let message = format!("{} [{}]", lint.message, lint.rule);
+        let span = token_span(&source, Some(lint.location));
+        eprintln!(
+            "{}\n",
+            Diagnostic::warning(message, span).render(file, &source, color)
+        );
+    }
+    if !lints.is_empty() {
+        process::exit(1);
+    }
+}
+
+/// Evaluates `file`; exits 1 if evaluation fails.
+fn run(file: &str) {
+    let source = read(file);
+    let program = parse(file, &source);
+    if let Err(error) = evaluator().eval(&program) {
+        let diagnostic = runtime_diagnostic(file, &source, &error);
+        eprintln!(
+            "{}",
+            diagnostic.render(file, &source, stderr().is_terminal())
+        );
+        process::exit(1);
+    }
+}
+
+/// One line for each call `error` happened in, innermost first, ending
+/// with what `call_site` says about where the call was. A run of calls
+/// from the same place, as in a recursion, gets a single line.
+fn call_notes(error: &EvalError, call_site: impl Fn(&Frame) -> String) -> Vec<String> {
+    let mut notes = Vec::new();
+    let mut frames = error.trace.iter().peekable();
+    while let Some(frame) = frames.next() {
+        let mut repeated = 0;
+        while frames.next_if_eq(&frame).is_some() {
+            repeated += 1;
+        }
+        let function = frame
+            .function
+            .map_or("an anonymous function".to_string(), |name| {
+                format!("'{}'", name)
+            });
+        let mut note = format!("in {}{}", function, call_site(frame));
+        if repeated > 0 {
+            note.push_str(&format!(" ({} more times)", repeated));
+        }
+        notes.push(note);
+    }
+    notes
+}
+
+/// Points at the expression `error` is about, with a note for each call it
+/// happened in.
+fn runtime_diagnostic(file: &str, source: &str, error: &EvalError) -> Diagnostic {
+    let index = LineIndex::new(source);
+    let notes = call_notes(error, |frame| {
+        let (line, column) = index.line_col(frame.call_site.start);
+        format!(", called at {}:{}:{}", file, line, column)
+    });
+    notes.into_iter().fold(
+        Diagnostic::error(error.message.clone(), error.span.clone()),
+        Diagnostic::with_note,
+    )
+}
+
+/// Reports every type error and exits 1, or prints the type of each
+/// top-level `let` if there are none.
+fn typecheck(file: &str) {
+    let source = read(file);
+    let program = parse(file, &source);
+    let typed = typecheck::check(&source, &program);
+    if !typed.errors.is_empty() {
+        let color = stderr().is_terminal();
+        for error in &typed.errors {
+            let diagnostic = Diagnostic::error(error.message.clone(), error.span.clone());
+            eprintln!("{}\n", diagnostic.render(file, &source, color));
+        }
+        process::exit(1);
+    }
+    for stmt in &program.statements {
+        if let Statement::Let(stmt) = stmt {
+            if let Expression::Identifier(name) = &stmt.identifier {
+                if let Some(ty) = name.location().and_then(|at| typed.type_at(at)) {
+                    println!("{}: {}", name, ty);
                 }
             }
         }
     }
 }
+
+fn fmt(args: &Args) {
+    let mut unformatted = false;
+    for file in &args.files {
+        let source = read(file);
+        let formatted = match formatter::format(&source) {
+            Ok(formatted) => formatted,
+            Err(errors) => report(file, &source, &errors),
+        };
+        if formatted == source {
+            continue;
+        }
+        if args.check {
+            println!("{}", file);
+            unformatted = true;
+        } else if let Err(e) = fs::write(file, formatted) {
+            eprintln!("could not write {}: {}", file, e);
+            process::exit(1);
+        }
+    }
+    if unformatted {
+        process::exit(1);
+    }
+}
+
+fn main() {
+    let commands = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(command)
+        .expect("Failed to start a thread");
+    if commands.join().is_err() {
+        process::exit(101);
+    }
+}
+
+fn command() {
+    let mut raw: Vec<String> = env::args().skip(1).collect();
+    if raw.is_empty() {
+        repl::start();
+        return;
+    }
+    let command = raw.remove(0);
+    if command == "-h" || command == "--help" {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let args = Args::parse(command, &raw);
+    let format = args.format.unwrap_or(Format::Text);
+    match args.command.as_str() {
+        "run" => run(&args.files[0]),
+        "tokens" => println!("{}", dump::tokens(&read(&args.files[0]), format)),
+        "ast" => {
+            let file = &args.files[0];
+            let source = read(file);
+            let program = parse(file, &source);
+            println!("{}", dump::ast(&source, &program, format));
+        }
+        "fmt" => fmt(&args),
+        "check" => check(&args.files[0]),
+        "lint" => lint(&args),
+        "typecheck" => typecheck(&args.files[0]),
+        "highlight" => {
+            let file = &args.files[0];
+            let source = read(file);
+            if args.html {
+                print!("{}", highlighter::html_page(&source, file));
+            } else if stdout().is_terminal() {
+                print!("{}", highlighter::ansi(&source));
+            } else {
+                print!("{}", source);
+            }
+        }
+        other => fail(&format!("unknown command '{}'", other)),
+    }
+}
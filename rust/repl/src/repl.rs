@@ -0,0 +1,51 @@
+use std::io::{stdin, stdout, IsTerminal, Write};
+
+use lexer::Lexer;
+use parser::{ast::Statement, Parser};
+
+pub fn start() {
+    println!("Welcome to the Monkey-Lang REPL");
+    let color = stdout().is_terminal();
+    let mut evaluator = crate::evaluator();
+    loop {
+        let mut input = String::new();
+        print!(">> ");
+        let _ = stdout().flush();
+        if stdin().read_line(&mut input).expect("Input error!") == 0 {
+            println!();
+            return;
+        }
+        if let Some('\n') = input.chars().next_back() {
+            input.pop();
+        }
+        if let Some('\r') = input.chars().next_back() {
+            input.pop();
+        }
+
+        if color {
+            println!("{}", highlighter::ansi(&input));
+        }
+        let mut parser = Parser::new(Lexer::new(&input));
+        let program = parser.parse_program().expect("Failed to parse program");
+        let errors = parser.errors();
+        if !errors.is_empty() {
+            for error in errors {
+                println!("error: {}", error);
+            }
+            continue;
+        }
+        match evaluator.eval(&program) {
+            // a `let` has no value worth showing
+            Ok(_) if matches!(program.statements.last(), Some(Statement::Let(_)) | None) => {}
+            Ok(value) => println!("{:?}", value),
+            Err(error) => {
+                println!("error: {}", error);
+                // the calls may be to functions entered on earlier lines, so
+                // only their names are shown
+                for note in crate::call_notes(&error, |_| String::new()) {
+                    println!("  {}", note);
+                }
+            }
+        }
+    }
+}
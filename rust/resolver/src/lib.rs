@@ -0,0 +1,427 @@
+//! Static name resolution over a parsed program.
+//!
+//! `let`s are visible from the statement after them, except that a `let`
+//! whose value is a function literal is visible inside it so the function
+//! can recurse. Only functions open scopes, as at runtime: a `let` in a
+//! block binds its name in the enclosing function, or at the top level, and
+//! it stays visible after the block, as do a `catch` parameter and the
+//! variables of a `for`. A name used inside a
+//! function may also refer to a `let` further down an enclosing scope, since
+//! it is only looked up once the function is called.
+//!
+//! Bindings whose name starts with `_` are never reported as unused.
+
+use std::{fmt, ops::Range};
+
+use lexer::{symbol::Symbol, tokens::Token};
+use parser::{
+    ast::{Expression, For, FunctionLiteral, Let, Program, Statement, Try},
+    visit::Visitor,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BindingKind {
+    Let,
+    Parameter,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProblemKind {
+    /// A name used where nothing by that name is in scope.
+    Undefined,
+    /// A binding hiding another one, defined at `previous`.
+    Shadowed {
+        previous: Range<usize>,
+    },
+    Unused(BindingKind),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+    pub kind: ProblemKind,
+    pub name: Symbol,
+    /// The identifier the problem is about.
+    pub span: Range<usize>,
+}
+
+impl Problem {
+    /// Undefined names are errors; everything else is a warning.
+    pub fn is_error(&self) -> bool {
+        self.kind == ProblemKind::Undefined
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ProblemKind::Undefined => write!(f, "Undefined identifier '{}'", self.name),
+            ProblemKind::Shadowed { .. } => {
+                write!(f, "'{}' shadows an earlier binding", self.name)
+            }
+            ProblemKind::Unused(BindingKind::Let) => write!(f, "Unused binding '{}'", self.name),
+            ProblemKind::Unused(BindingKind::Parameter) => {
+                write!(f, "Unused parameter '{}'", self.name)
+            }
+        }
+    }
+}
+
+struct Binding {
+    name: Symbol,
+    kind: BindingKind,
+    span: Range<usize>,
+    used: bool,
+}
+
+/// A name not found when it was used, waiting for its scope to close.
+struct Pending {
+    name: Symbol,
+    span: Range<usize>,
+    /// How many functions the use is nested in.
+    functions: usize,
+}
+
+struct Scope {
+    bindings: Vec<usize>,
+    pending: Vec<Pending>,
+    functions: usize,
+}
+
+pub struct Resolver {
+    globals: Vec<Symbol>,
+    bindings: Vec<Binding>,
+    scopes: Vec<Scope>,
+    functions: usize,
+    problems: Vec<Problem>,
+}
+
+fn identifier(expr: &Expression) -> Option<(Symbol, Range<usize>)> {
+    match expr {
+        Expression::Identifier(Token::Ident { location, raw }) => {
+            Some((*raw, *location..*location + raw.as_str().len()))
+        }
+        _ => None,
+    }
+}
+
+/// The problems in `program`, with no names predefined.
+pub fn resolve(program: &Program) -> Vec<Problem> {
+    Resolver::new().resolve(program)
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Resolver {
+            globals: Vec::new(),
+            bindings: Vec::new(),
+            scopes: Vec::new(),
+            functions: 0,
+            problems: Vec::new(),
+        }
+    }
+
+    /// A resolver that also accepts `names`, such as builtin functions.
+    pub fn with_globals<'n>(names: impl IntoIterator<Item = &'n str>) -> Self {
+        let mut resolver = Resolver::new();
+        resolver.globals = names.into_iter().map(Symbol::intern).collect();
+        resolver
+    }
+
+    /// The problems in `program`, ordered by where they start.
+    pub fn resolve(mut self, program: &Program) -> Vec<Problem> {
+        self.push_scope();
+        self.visit_program(program);
+        self.pop_scope();
+        self.problems.sort_by_key(|p| p.span.start);
+        self.problems
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Scope {
+            bindings: Vec::new(),
+            pending: Vec::new(),
+            functions: self.functions,
+        });
+    }
+
+    fn pop_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        for pending in scope.pending {
+            let later = scope
+                .bindings
+                .iter()
+                .copied()
+                .find(|b| self.bindings[*b].name == pending.name);
+            match (later, self.scopes.last_mut()) {
+                (Some(binding), _) if pending.functions > scope.functions => {
+                    self.bindings[binding].used = true;
+                }
+                (_, Some(parent)) => parent.pending.push(pending),
+                (_, None) => self.problems.push(Problem {
+                    kind: ProblemKind::Undefined,
+                    name: pending.name,
+                    span: pending.span,
+                }),
+            }
+        }
+        for binding in scope.bindings {
+            let binding = &self.bindings[binding];
+            if !binding.used && !binding.name.as_str().starts_with('_') {
+                self.problems.push(Problem {
+                    kind: ProblemKind::Unused(binding.kind),
+                    name: binding.name,
+                    span: binding.span.clone(),
+                });
+            }
+        }
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.bindings.iter().rev())
+            .copied()
+            .find(|b| self.bindings[*b].name == name)
+    }
+
+    fn define(&mut self, expr: &Expression, kind: BindingKind) {
+        let Some((name, span)) = identifier(expr) else {
+            return;
+        };
+        if let Some(previous) = self.lookup(name) {
+            self.problems.push(Problem {
+                kind: ProblemKind::Shadowed {
+                    previous: self.bindings[previous].span.clone(),
+                },
+                name,
+                span: span.clone(),
+            });
+        }
+        self.bindings.push(Binding {
+            name,
+            kind,
+            span,
+            used: false,
+        });
+        let index = self.bindings.len() - 1;
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.bindings.push(index);
+        }
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for stmt in statements {
+            self.visit_statement(stmt);
+        }
+    }
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Resolver::new()
+    }
+}
+
+impl Visitor for Resolver {
+    fn visit_let(&mut self, stmt: &Let) {
+        if matches!(stmt.value, Expression::Function(_)) {
+            self.define(&stmt.identifier, BindingKind::Let);
+            self.visit_expression(&stmt.value);
+        } else {
+            self.visit_expression(&stmt.value);
+            self.define(&stmt.identifier, BindingKind::Let);
+        }
+    }
+
+    fn visit_function(&mut self, expr: &FunctionLiteral) {
+        self.functions += 1;
+        self.push_scope();
+        for parameter in &expr.parameters {
+            self.define(parameter, BindingKind::Parameter);
+        }
+        // the body shares the parameters' scope
+        self.statements(&expr.body.statements);
+        self.pop_scope();
+        self.functions -= 1;
+    }
+
+    fn visit_try(&mut self, expr: &Try) {
+        self.visit_block(&expr.body);
+        self.define(&expr.parameter, BindingKind::Parameter);
+        self.visit_block(&expr.handler);
+    }
+
+    fn visit_for(&mut self, stmt: &For) {
+        self.visit_expression(&stmt.iterable);
+        match &stmt.variable {
+            Expression::Array(pattern) => {
+                for name in &pattern.elements {
+                    self.define(name, BindingKind::Let);
+                }
+            }
+            variable => self.define(variable, BindingKind::Let),
+        }
+        self.visit_block(&stmt.body);
+    }
+
+    fn visit_identifier(&mut self, token: &Token) {
+        let Some((name, span)) = identifier(&Expression::Identifier(token.clone())) else {
+            return;
+        };
+        if let Some(binding) = self.lookup(name) {
+            self.bindings[binding].used = true;
+        } else if !self.globals.contains(&name) {
+            let functions = self.functions;
+            if let Some(scope) = self.scopes.last_mut() {
+                scope.pending.push(Pending {
+                    name,
+                    span,
+                    functions,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lexer::Lexer;
+    use parser::Parser;
+
+    fn check(source: &str) -> Vec<(String, &str)> {
+        check_with(source, Resolver::new())
+    }
+
+    fn check_with(source: &str, resolver: Resolver) -> Vec<(String, &str)> {
+        let mut parser = Parser::new(Lexer::new(source));
+        let program = parser.parse_program().unwrap();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        resolver
+            .resolve(&program)
+            .into_iter()
+            .map(|p| (p.to_string(), &source[p.span]))
+            .collect()
+    }
+
+    #[test]
+    fn test_undefined() {
+        assert_eq!(
+            check("let x = y + 1; x; z(x);"),
+            vec![
+                ("Undefined identifier 'y'".to_string(), "y"),
+                ("Undefined identifier 'z'".to_string(), "z"),
+            ]
+        );
+        assert!(check_with("puts(len(1));", Resolver::with_globals(["len", "puts"])).is_empty());
+        // a let is not visible in its own value unless that is a function
+        assert_eq!(
+            check("let n = n; n;"),
+            vec![("Undefined identifier 'n'".to_string(), "n")]
+        );
+        assert!(check("let f = fn(n) { f(n) }; f(1);").is_empty());
+    }
+
+    #[test]
+    fn test_later_bindings() {
+        // functions look names up when called
+        assert!(check("let f = fn() { g() }; let g = fn() { 1 }; f();").is_empty());
+        // other expressions run straight away
+        assert_eq!(
+            check("x; let x = 1; x;"),
+            vec![("Undefined identifier 'x'".to_string(), "x")]
+        );
+        assert_eq!(
+            check("let f = fn() { let a = b; let b = 1; a }; f();"),
+            vec![
+                ("Undefined identifier 'b'".to_string(), "b"),
+                ("Unused binding 'b'".to_string(), "b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blocks() {
+        // blocks do not open scopes
+        assert!(check("if (true) { let a = 1; a } else { a }; a").is_empty());
+        assert_eq!(
+            check("try { e } catch (e) { 0 }; try { 2 } catch (unused) {}; e;"),
+            vec![
+                ("Undefined identifier 'e'".to_string(), "e"),
+                ("Unused parameter 'unused'".to_string(), "unused"),
+            ]
+        );
+        assert_eq!(
+            check("while (c) { let b = 1; if (b) { break; } }; b"),
+            vec![("Undefined identifier 'c'".to_string(), "c")]
+        );
+        assert_eq!(
+            check("let h = {}; for ([k, v] in h) { k }; for (x in [k, y]) {}; x"),
+            vec![
+                ("Unused binding 'v'".to_string(), "v"),
+                ("Undefined identifier 'y'".to_string(), "y"),
+            ]
+        );
+    }
+
+    /// Programs that run, and programs that fail on an undefined name.
+    #[test]
+    fn test_matches_evaluation() {
+        let tests = [
+            ("if (true) { let a = 1; }; puts(a);", true),
+            ("let f = fn() { let a = 1; }; f(); puts(a);", false),
+            ("try { throw 1; } catch (e) { 0 }; puts(e);", true),
+            ("puts(e); try { throw 1; } catch (e) { 0 };", false),
+            (
+                "for (x in [1, 2]) { let inner = x; }; puts(x); puts(inner);",
+                true,
+            ),
+            ("for (x in [x]) {}", false),
+        ];
+        for (source, runs) in tests {
+            let mut parser = Parser::new(Lexer::new(source));
+            let program = parser.parse_program().unwrap();
+            let builtins = evaluator::Builtins::standard();
+            let problems = Resolver::with_globals(builtins.names()).resolve(&program);
+            let result = evaluator::Evaluator::with_output(std::io::sink()).eval(&program);
+            assert_eq!(result.is_ok(), runs, "source: {}", source);
+            assert_eq!(
+                !problems.iter().any(Problem::is_error),
+                runs,
+                "source: {}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_shadowing_and_unused() {
+        let source = "let x = 1;
+let f = fn(x, y, _z) { let x = x + 1; x };
+f(x, 2);";
+        let problems = check(source);
+        assert_eq!(
+            problems,
+            vec![
+                ("'x' shadows an earlier binding".to_string(), "x"),
+                ("Unused parameter 'y'".to_string(), "y"),
+                ("'x' shadows an earlier binding".to_string(), "x"),
+            ]
+        );
+
+        let mut parser = Parser::new(Lexer::new(source));
+        let program = parser.parse_program().unwrap();
+        let problems = resolve(&program);
+        assert_eq!(problems[0].span, 22..23);
+        assert_eq!(problems[0].kind, ProblemKind::Shadowed { previous: 4..5 });
+        assert!(!problems[0].is_error());
+
+        assert_eq!(
+            check("let unused = 1; let _ignored = 2;"),
+            vec![("Unused binding 'unused'".to_string(), "unused")]
+        );
+    }
+}
@@ -0,0 +1,910 @@
+//! Hindley–Milner type inference for Monkey programs.
+//!
+//! Every expression gets a type from `int`, `bool`, `string`, `null`,
+//! arrays, hashes and functions. The keys of a hash all have one type, and
+//! so do its values. `let` bindings are generalized, so
+//! `let id = fn(x) { x };` can be used at several types. Monkey itself is
+//! dynamically typed; where it is looser than a type system can follow, the
+//! checker picks a rule:
+//!
+//! - `if` and `while` conditions and `!` accept any type, as truthiness
+//!   does at runtime
+//! - `+` adds integers or joins strings; operands whose type is not known
+//!   yet are taken to be integers
+//! - an `if` without `else` is `null`, and so are loops
+//! - a range `a..b` is an array of ints
+//! - `for` takes the keys of a hash, or with `[k, v]` its keys and values,
+//!   and the characters of a string; a value not known to be either is
+//!   taken to be an array, whose items `[a, b]` takes apart as arrays too;
+//!   the variables stay bound after the loop
+//! - a `try` and its `catch` have the same type; what is caught may be any
+//!   value, so the `catch` parameter gets a fresh type, and like a `let` it
+//!   stays bound after the `catch`
+//! - only functions open scopes, as at runtime, so a `let` in a block is
+//!   visible after it
+//! - names with no binding in scope get a fresh type and no error, since a
+//!   function may use a `let` defined after it; see the `resolver` crate
+//!   for undefined names
+//!
+//! Type annotations (`let x: int = 5;`, `fn(a: int) -> int { a }`) are
+//! checked here; the annotated type is used in place of the inferred one,
+//! so a value that does not fit is a mismatch.
+
+mod types;
+
+use std::ops::Range;
+
+use lexer::{
+    symbol::Symbol,
+    tokens::{Token, TokenKind},
+    Lexer,
+};
+use parser::ast::{Block, Expression, For, Let, Program, Statement, TypeAnnotation};
+
+pub use types::{Scheme, Type};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TypeError {
+    pub message: String,
+    /// The first token of the expression with the wrong type.
+    pub span: Range<usize>,
+}
+
+/// The result of checking a program.
+pub struct Typed {
+    /// The type of every identifier, where it is bound and where it is
+    /// used, in source order.
+    pub types: Vec<(Range<usize>, Type)>,
+    pub errors: Vec<TypeError>,
+}
+
+impl Typed {
+    /// The type of the identifier at `offset`, counting the position just
+    /// after it as on it.
+    pub fn type_at(&self, offset: usize) -> Option<&Type> {
+        self.types
+            .iter()
+            .find(|(span, _)| span.start <= offset && offset <= span.end)
+            .map(|(_, ty)| ty)
+    }
+}
+
+enum Mismatch {
+    Types,
+    Arity(usize, usize),
+    Infinite,
+}
+
+pub struct Checker<'s> {
+    source: &'s str,
+    /// What each type variable has been unified with, if anything.
+    bindings: Vec<Option<Type>>,
+    scopes: Vec<Vec<(Symbol, Scheme)>>,
+    /// Result types of the functions being checked, innermost last.
+    returns: Vec<Type>,
+    types: Vec<(Range<usize>, Type)>,
+    errors: Vec<TypeError>,
+}
+
+/// Checks `program`, parsed from `source`.
+pub fn check(source: &str, program: &Program) -> Typed {
+    Checker::new(source).check(program)
+}
+
+impl<'s> Checker<'s> {
+    pub fn new(source: &'s str) -> Self {
+        Checker {
+            source,
+            bindings: Vec::new(),
+            scopes: vec![Vec::new()],
+            returns: Vec::new(),
+            types: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Makes `name` available with type `ty`, generalized over all of its
+    /// type variables. Use `fresh` for the variables.
+    pub fn declare(&mut self, name: &str, ty: Type) {
+        let mut vars = Vec::new();
+        ty.vars(&mut vars);
+        self.scopes[0].push((Symbol::intern(name), Scheme { vars, ty }));
+    }
+
+    /// A type variable not used anywhere else.
+    pub fn fresh(&mut self) -> Type {
+        self.bindings.push(None);
+        Type::Var(self.bindings.len() as u32 - 1)
+    }
+
+    pub fn check(mut self, program: &Program) -> Typed {
+        self.statements(&program.statements);
+        let mut types = std::mem::take(&mut self.types);
+        for (_, ty) in &mut types {
+            *ty = self.resolve(ty);
+        }
+        types.sort_by_key(|(span, _)| span.start);
+        Typed {
+            types,
+            errors: self.errors,
+        }
+    }
+
+    fn span(&self, location: Option<usize>) -> Range<usize> {
+        let Some(location) = location else {
+            return self.source.len()..self.source.len();
+        };
+        let mut lex = Lexer::new(self.source);
+        lex.seek(location);
+        lex.next_token();
+        location..lex.position().clamp(location, self.source.len())
+    }
+
+    fn error(&mut self, location: Option<usize>, message: String) {
+        let span = self.span(location);
+        self.errors.push(TypeError { message, span });
+    }
+
+    /// `ty` with every bound variable replaced by what it is bound to.
+    fn resolve(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(v) => match &self.bindings[*v as usize] {
+                Some(bound) => self.resolve(bound),
+                None => ty.clone(),
+            },
+            Type::Array(element) => Type::array(self.resolve(element)),
+            Type::Hash(key, value) => Type::hash(self.resolve(key), self.resolve(value)),
+            Type::Function(parameters, result) => Type::function(
+                parameters.iter().map(|p| self.resolve(p)).collect(),
+                self.resolve(result),
+            ),
+            Type::Int | Type::Bool | Type::String | Type::Null => ty.clone(),
+        }
+    }
+
+    /// Follows bound variables until a constructor or a free variable.
+    fn shallow(&self, ty: &Type) -> Type {
+        let mut ty = ty.clone();
+        while let Type::Var(v) = ty {
+            match &self.bindings[v as usize] {
+                Some(bound) => ty = bound.clone(),
+                None => break,
+            }
+        }
+        ty
+    }
+
+    fn occurs(&self, var: u32, ty: &Type) -> bool {
+        let mut vars = Vec::new();
+        self.resolve(ty).vars(&mut vars);
+        vars.contains(&var)
+    }
+
+    fn unify_types(&mut self, a: &Type, b: &Type) -> Result<(), Mismatch> {
+        match (self.shallow(a), self.shallow(b)) {
+            (Type::Var(x), Type::Var(y)) if x == y => Ok(()),
+            (Type::Var(v), other) | (other, Type::Var(v)) => {
+                if self.occurs(v, &other) {
+                    return Err(Mismatch::Infinite);
+                }
+                self.bindings[v as usize] = Some(other);
+                Ok(())
+            }
+            (Type::Array(x), Type::Array(y)) => self.unify_types(&x, &y),
+            (Type::Hash(k1, v1), Type::Hash(k2, v2)) => {
+                self.unify_types(&k1, &k2)?;
+                self.unify_types(&v1, &v2)
+            }
+            (Type::Function(p1, r1), Type::Function(p2, r2)) => {
+                if p1.len() != p2.len() {
+                    return Err(Mismatch::Arity(p1.len(), p2.len()));
+                }
+                for (x, y) in p1.iter().zip(&p2) {
+                    self.unify_types(x, y)?;
+                }
+                self.unify_types(&r1, &r2)
+            }
+            (x, y) if x == y => Ok(()),
+            _ => Err(Mismatch::Types),
+        }
+    }
+
+    /// Unifies the type an expression was `expected` to have with the one
+    /// it was `found` to have, reporting a mismatch at `location`.
+    fn unify(&mut self, expected: &Type, found: &Type, location: Option<usize>) {
+        let message = match self.unify_types(expected, found) {
+            Ok(()) => return,
+            Err(Mismatch::Types) => format!(
+                "Type mismatch: expected {}, found {}",
+                self.resolve(expected),
+                self.resolve(found)
+            ),
+            Err(Mismatch::Arity(expected, found)) => format!(
+                "Expected a function of {} parameters, found one of {}",
+                expected, found
+            ),
+            Err(Mismatch::Infinite) => format!(
+                "Type mismatch: {} would have to contain itself",
+                self.resolve(expected)
+            ),
+        };
+        self.error(location, message);
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<&Scheme> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(n, _)| *n == name)
+            .map(|(_, scheme)| scheme)
+    }
+
+    fn bind(&mut self, name: Symbol, scheme: Scheme) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push((name, scheme));
+        }
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let fresh: Vec<(u32, Type)> = scheme.vars.iter().map(|v| (*v, self.fresh())).collect();
+        substitute(&self.resolve(&scheme.ty), &fresh)
+    }
+
+    /// `ty` generalized over the variables no binding in scope mentions.
+    fn generalize(&self, ty: &Type) -> Scheme {
+        let ty = self.resolve(ty);
+        let mut in_scope = Vec::new();
+        for (_, scheme) in self.scopes.iter().flatten() {
+            let mut vars = Vec::new();
+            self.resolve(&scheme.ty).vars(&mut vars);
+            in_scope.extend(vars.into_iter().filter(|v| !scheme.vars.contains(v)));
+        }
+        let mut vars = Vec::new();
+        ty.vars(&mut vars);
+        vars.retain(|v| !in_scope.contains(v));
+        Scheme { vars, ty }
+    }
+
+    fn record(&mut self, token: &Token, ty: &Type) {
+        if let Token::Ident { location, raw } = token {
+            let span = *location..*location + raw.as_str().len();
+            self.types.push((span, ty.clone()));
+        }
+    }
+
+    /// The type of the last statement, which is what a block evaluates to.
+    fn statements(&mut self, statements: &[Statement]) -> Type {
+        let mut ty = Type::Null;
+        for stmt in statements {
+            ty = match stmt {
+                Statement::Let(l) => {
+                    self.let_statement(l);
+                    Type::Null
+                }
+                Statement::Return(r) => {
+                    let value = self.expression(&r.return_value);
+                    if let Some(expected) = self.returns.last().cloned() {
+                        self.unify(&expected, &value, Some(r.return_value.span().start));
+                    }
+                    // control does not go on, so the statement fits any type
+                    self.fresh()
+                }
+                Statement::Throw(t) => {
+                    self.expression(&t.value);
+                    self.fresh()
+                }
+                Statement::While(w) => {
+                    self.expression(&w.condition);
+                    self.block(&w.body);
+                    Type::Null
+                }
+                Statement::For(f) => {
+                    self.for_statement(f);
+                    Type::Null
+                }
+                Statement::Break(_) | Statement::Continue(_) => self.fresh(),
+                Statement::Expression(e) => self.expression(&e.expression),
+            };
+        }
+        ty
+    }
+
+    fn block(&mut self, block: &Block) -> Type {
+        self.statements(&block.statements)
+    }
+
+    fn for_statement(&mut self, stmt: &For) {
+        let iterable = self.expression(&stmt.iterable);
+        let at = Some(stmt.iterable.span().start);
+        let names = match &stmt.variable {
+            Expression::Array(pattern) => pattern.elements.iter().collect(),
+            variable => vec![variable],
+        };
+        let destructured = matches!(stmt.variable, Expression::Array(_));
+        let types: Vec<Type> = match self.shallow(&iterable) {
+            Type::Hash(key, value) if destructured => {
+                let mut types = vec![*key, *value];
+                types.extend(names.iter().skip(2).map(|_| self.fresh()));
+                types
+            }
+            Type::Hash(key, _) => vec![*key],
+            Type::String => vec![Type::String; names.len()],
+            ty @ (Type::Array(_) | Type::Var(_)) => {
+                let item = self.fresh();
+                self.unify(&Type::array(item.clone()), &ty, at);
+                if destructured {
+                    let element = self.fresh();
+                    self.unify(&Type::array(element.clone()), &item, at);
+                    vec![element; names.len()]
+                } else {
+                    vec![item]
+                }
+            }
+            other => {
+                self.error(
+                    at,
+                    format!(
+                        "Cannot iterate over a value of type {}",
+                        self.resolve(&other)
+                    ),
+                );
+                names.iter().map(|_| self.fresh()).collect()
+            }
+        };
+        for (name, ty) in names.into_iter().zip(types) {
+            if let Expression::Identifier(token @ Token::Ident { raw, .. }) = name {
+                self.record(token, &ty);
+                self.bind(*raw, Scheme::mono(ty));
+            }
+        }
+        self.block(&stmt.body);
+    }
+
+    fn let_statement(&mut self, stmt: &Let) {
+        let Expression::Identifier(token @ Token::Ident { raw: name, .. }) = &stmt.identifier
+        else {
+            self.expression(&stmt.value);
+            return;
+        };
+        let declared = stmt.annotation.as_ref().map(annotated);
+        let ty = if matches!(stmt.value, Expression::Function(_)) {
+            // visible in its own body, at a single type, so it can recurse
+            let own = declared.clone().unwrap_or_else(|| self.fresh());
+            self.scopes.push(vec![(*name, Scheme::mono(own.clone()))]);
+            let value = self.expression(&stmt.value);
+            self.scopes.pop();
+            self.unify(&own, &value, Some(stmt.value.span().start));
+            declared.unwrap_or(value)
+        } else {
+            let value = self.expression(&stmt.value);
+            match declared {
+                Some(declared) => {
+                    self.unify(&declared, &value, Some(stmt.value.span().start));
+                    declared
+                }
+                None => value,
+            }
+        };
+        self.record(token, &ty);
+        let scheme = self.generalize(&ty);
+        self.bind(*name, scheme);
+    }
+
+    fn expression(&mut self, expr: &Expression) -> Type {
+        match expr {
+            Expression::Identifier(token) => {
+                let Token::Ident { raw, .. } = token else {
+                    return self.fresh();
+                };
+                let ty = match self.lookup(*raw).cloned() {
+                    Some(scheme) => self.instantiate(&scheme),
+                    None => self.fresh(),
+                };
+                self.record(token, &ty);
+                ty
+            }
+            Expression::Integer(_) => Type::Int,
+            Expression::Boolean(_) => Type::Bool,
+            Expression::String(_) => Type::String,
+            Expression::Prefix(prefix) => {
+                let right = self.expression(&prefix.right);
+                if prefix.token.kind() == TokenKind::Minus {
+                    self.unify(&Type::Int, &right, Some(prefix.right.span().start));
+                    Type::Int
+                } else {
+                    Type::Bool
+                }
+            }
+            Expression::Infix(infix) => {
+                let left = self.expression(&infix.left);
+                let right = self.expression(&infix.right);
+                let (left_at, right_at) = (
+                    Some(infix.left.span().start),
+                    Some(infix.right.span().start),
+                );
+                match infix.token.kind() {
+                    TokenKind::Plus => {
+                        let operand = match self.shallow(&left) {
+                            Type::Var(_) => match self.shallow(&right) {
+                                Type::String => Type::String,
+                                _ => Type::Int,
+                            },
+                            Type::String => Type::String,
+                            _ => Type::Int,
+                        };
+                        self.unify(&operand, &left, left_at);
+                        self.unify(&operand, &right, right_at);
+                        operand
+                    }
+                    TokenKind::Minus | TokenKind::Multiply | TokenKind::Divide => {
+                        self.unify(&Type::Int, &left, left_at);
+                        self.unify(&Type::Int, &right, right_at);
+                        Type::Int
+                    }
+                    TokenKind::LessThan | TokenKind::GreaterThan => {
+                        self.unify(&Type::Int, &left, left_at);
+                        self.unify(&Type::Int, &right, right_at);
+                        Type::Bool
+                    }
+                    TokenKind::DotDot | TokenKind::DotDotEquals => {
+                        self.unify(&Type::Int, &left, left_at);
+                        self.unify(&Type::Int, &right, right_at);
+                        Type::array(Type::Int)
+                    }
+                    _ => {
+                        self.unify(&left, &right, right_at);
+                        Type::Bool
+                    }
+                }
+            }
+            Expression::If(expr) => {
+                self.expression(&expr.condition);
+                let consequence = self.block(&expr.consequence);
+                match &expr.alternative {
+                    Some(alternative) => {
+                        let ty = self.block(alternative);
+                        self.unify(&consequence, &ty, alternative.token.location());
+                        consequence
+                    }
+                    None => Type::Null,
+                }
+            }
+            Expression::Try(expr) => {
+                let body = self.block(&expr.body);
+                if let Expression::Identifier(token @ Token::Ident { raw, .. }) = &*expr.parameter {
+                    let ty = self.fresh();
+                    self.record(token, &ty);
+                    self.bind(*raw, Scheme::mono(ty));
+                }
+                let handler = self.block(&expr.handler);
+                self.unify(&body, &handler, expr.handler.token.location());
+                body
+            }
+            Expression::Function(function) => {
+                let mut scope = Vec::new();
+                let mut parameters = Vec::new();
+                for (i, parameter) in function.parameters.iter().enumerate() {
+                    let ty = match function.parameter_type(i) {
+                        Some(annotation) => annotated(annotation),
+                        None => self.fresh(),
+                    };
+                    if let Expression::Identifier(token @ Token::Ident { raw, .. }) = parameter {
+                        self.record(token, &ty);
+                        scope.push((*raw, Scheme::mono(ty.clone())));
+                    }
+                    parameters.push(ty);
+                }
+                let result = match &function.return_type {
+                    Some(annotation) => annotated(annotation),
+                    None => self.fresh(),
+                };
+                self.scopes.push(scope);
+                self.returns.push(result.clone());
+                let body = self.statements(&function.body.statements);
+                self.returns.pop();
+                self.scopes.pop();
+                self.unify(&result, &body, function.token.location());
+                Type::function(parameters, result)
+            }
+            Expression::Call(call) => {
+                let callee = self.expression(&call.function);
+                let arguments: Vec<Type> =
+                    call.arguments.iter().map(|a| self.expression(a)).collect();
+                let result = self.fresh();
+                match self.shallow(&callee) {
+                    Type::Function(parameters, ret) => {
+                        if parameters.len() != arguments.len() {
+                            self.error(
+                                call.token.location(),
+                                format!(
+                                    "Expected {} arguments, found {}",
+                                    parameters.len(),
+                                    arguments.len()
+                                ),
+                            );
+                            return self.resolve(&ret);
+                        }
+                        for ((parameter, argument), expr) in
+                            parameters.iter().zip(&arguments).zip(&call.arguments)
+                        {
+                            self.unify(parameter, argument, Some(expr.span().start));
+                        }
+                        *ret
+                    }
+                    Type::Var(_) => {
+                        let expected = Type::function(arguments, result.clone());
+                        self.unify(&callee, &expected, Some(call.function.span().start));
+                        result
+                    }
+                    other => {
+                        self.error(
+                            Some(call.function.span().start),
+                            format!("Cannot call a value of type {}", self.resolve(&other)),
+                        );
+                        result
+                    }
+                }
+            }
+            Expression::Array(array) => {
+                let element = self.fresh();
+                for item in &array.elements {
+                    let ty = self.expression(item);
+                    self.unify(&element, &ty, Some(item.span().start));
+                }
+                Type::array(element)
+            }
+            Expression::Hash(hash) => {
+                let (key, value) = (self.fresh(), self.fresh());
+                for (k, v) in &hash.pairs {
+                    let ty = self.expression(k);
+                    self.unify(&key, &ty, Some(k.span().start));
+                    let ty = self.expression(v);
+                    self.unify(&value, &ty, Some(v.span().start));
+                }
+                Type::hash(key, value)
+            }
+            Expression::Index(index) => {
+                let left = self.expression(&index.left);
+                let position = self.expression(&index.index);
+                let element = self.fresh();
+                // a value not known to be a hash is taken to be an array
+                if let Type::Hash(key, _) = self.shallow(&left) {
+                    let hash = Type::hash((*key).clone(), element.clone());
+                    self.unify(&hash, &left, Some(index.left.span().start));
+                    self.unify(&key, &position, Some(index.index.span().start));
+                } else {
+                    self.unify(
+                        &Type::array(element.clone()),
+                        &left,
+                        Some(index.left.span().start),
+                    );
+                    self.unify(&Type::Int, &position, Some(index.index.span().start));
+                }
+                element
+            }
+        }
+    }
+}
+
+/// The type an annotation stands for.
+fn annotated(annotation: &TypeAnnotation) -> Type {
+    match annotation {
+        TypeAnnotation::Named(Token::Ident { raw, .. }) => match raw.as_str() {
+            "int" => Type::Int,
+            "bool" => Type::Bool,
+            "string" => Type::String,
+            _ => Type::Null,
+        },
+        TypeAnnotation::Named(_) => Type::Null,
+        TypeAnnotation::Array(array) => Type::array(annotated(&array.element)),
+        TypeAnnotation::Function(function) => Type::function(
+            function.parameters.iter().map(annotated).collect(),
+            annotated(&function.result),
+        ),
+    }
+}
+
+fn substitute(ty: &Type, fresh: &[(u32, Type)]) -> Type {
+    match ty {
+        Type::Var(v) => fresh
+            .iter()
+            .find(|(old, _)| old == v)
+            .map_or_else(|| ty.clone(), |(_, new)| new.clone()),
+        Type::Array(element) => Type::array(substitute(element, fresh)),
+        Type::Hash(key, value) => Type::hash(substitute(key, fresh), substitute(value, fresh)),
+        Type::Function(parameters, result) => Type::function(
+            parameters.iter().map(|p| substitute(p, fresh)).collect(),
+            substitute(result, fresh),
+        ),
+        Type::Int | Type::Bool | Type::String | Type::Null => ty.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::Parser;
+
+    fn typed(source: &str) -> Typed {
+        let mut parser = Parser::new(Lexer::new(source));
+        let program = parser.parse_program().unwrap();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        check(source, &program)
+    }
+
+    /// The type of every `let` at the top of `source`.
+    fn lets(source: &str) -> Vec<String> {
+        let typed = typed(source);
+        assert!(typed.errors.is_empty(), "{:?}", typed.errors);
+        let mut parser = Parser::new(Lexer::new(source));
+        let program = parser.parse_program().unwrap();
+        program
+            .statements
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Statement::Let(Let {
+                    identifier: Expression::Identifier(Token::Ident { location, .. }),
+                    ..
+                }) => typed.type_at(*location).map(Type::to_string),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn errors(source: &str) -> Vec<(String, &str)> {
+        typed(source)
+            .errors
+            .into_iter()
+            .map(|e| (e.message, &source[e.span]))
+            .collect()
+    }
+
+    #[test]
+    fn test_inference() {
+        assert_eq!(
+            lets(
+                r#"let five = 5;
+let add = fn(x, y) { x + y };
+let greet = fn(name) { "hello " + name };
+let names = ["a", "b"];
+let first = fn(xs) { xs[0] };
+let apply = fn(f, x) { f(x) };
+let positive = fn(n) { if (n > 0) { true } else { false } };
+let ages = {"ann": 31, "bob": 27};
+let age = ages["ann"];"#
+            ),
+            vec![
+                "int",
+                "fn(int, int) -> int",
+                "fn(string) -> string",
+                "[string]",
+                "fn([a]) -> a",
+                "fn(fn(a) -> b, a) -> b",
+                "fn(int) -> bool",
+                "{string: int}",
+                "int",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_let_polymorphism() {
+        assert_eq!(
+            lets(
+                r#"let id = fn(x) { x };
+let n = id(1);
+let s = id("s");
+let pair = [id(true), !id(false)];"#
+            ),
+            vec!["fn(a) -> a", "int", "string", "[bool]"]
+        );
+        // parameters are not generalized
+        assert_eq!(
+            errors(r#"let f = fn(g) { [g(1), g("s")] };"#),
+            vec![(
+                "Type mismatch: expected int, found string".to_string(),
+                "\"s\""
+            )]
+        );
+    }
+
+    #[test]
+    fn test_recursion_and_returns() {
+        assert_eq!(
+            lets(
+                "let fib = fn(n) { if (n < 2) { return n; } fib(n - 1) + fib(n - 2) };
+let count = fn(n) { if (n == 0) { 0 } else { 1 + count(n - 1) } };"
+            ),
+            vec!["fn(int) -> int", "fn(int) -> int"]
+        );
+        assert_eq!(
+            lets(
+                r#"let parse = fn(s) { if (s == "") { throw "empty"; } s + "!" };
+let safe = fn(s) { try { parse(s) } catch (e) { e } };"#
+            ),
+            vec!["fn(string) -> string", "fn(string) -> string"]
+        );
+        assert_eq!(
+            lets("let drain = fn(n) { while (n) { if (n == 3) { break; } n - 1; continue; } };"),
+            vec!["fn(int) -> null"]
+        );
+        assert_eq!(
+            lets(
+                r#"let sum = fn(xs) { let total = 0; for (x in xs) { total + x; } total };
+let keys = fn(h) { for ([k, v] in {"a": h}) { k + "!"; v[0]; } };
+let chars = fn(s) { for (c in s + "") { c + "" } };
+let range = 0..=9;
+let pairs = fn(xs) { for ([a, b] in xs) { a + 1; b } };"#
+            ),
+            vec![
+                "fn([int]) -> int",
+                "fn([a]) -> null",
+                "fn(string) -> null",
+                "[int]",
+                "fn([[int]]) -> null",
+            ]
+        );
+        assert_eq!(
+            errors("for (x in 5) {} 0..true; for (c in \"s\") {} c + 1;"),
+            vec![
+                ("Cannot iterate over a value of type int".to_string(), "5"),
+                (
+                    "Type mismatch: expected int, found bool".to_string(),
+                    "true"
+                ),
+                ("Type mismatch: expected string, found int".to_string(), "1"),
+            ]
+        );
+        assert_eq!(
+            errors("while (1) { 1 + \"s\"; }"),
+            vec![(
+                "Type mismatch: expected int, found string".to_string(),
+                "\"s\""
+            )]
+        );
+        assert_eq!(
+            errors("let x = try { 1 } catch (e) { \"none\" };"),
+            vec![("Type mismatch: expected int, found string".to_string(), "{")]
+        );
+        assert_eq!(
+            errors("let f = fn(n) { if (n) { return 1; }; return true; };"),
+            vec![(
+                "Type mismatch: expected int, found bool".to_string(),
+                "true"
+            )]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            errors("5 + true; let x = 1; x(2); [1, \"two\"]; -\"s\"; 1 == \"1\";"),
+            vec![
+                (
+                    "Type mismatch: expected int, found bool".to_string(),
+                    "true"
+                ),
+                ("Cannot call a value of type int".to_string(), "x"),
+                (
+                    "Type mismatch: expected int, found string".to_string(),
+                    "\"two\""
+                ),
+                (
+                    "Type mismatch: expected int, found string".to_string(),
+                    "\"s\""
+                ),
+                (
+                    "Type mismatch: expected int, found string".to_string(),
+                    "\"1\""
+                ),
+            ]
+        );
+        assert_eq!(
+            errors("let add = fn(a, b) { a + b }; add(1); add(1, true);"),
+            vec![
+                ("Expected 2 arguments, found 1".to_string(), "("),
+                (
+                    "Type mismatch: expected int, found bool".to_string(),
+                    "true"
+                ),
+            ]
+        );
+        assert_eq!(
+            errors("let f = fn(x) { x(x) };"),
+            vec![(
+                "Type mismatch: a would have to contain itself".to_string(),
+                "x"
+            )]
+        );
+        assert_eq!(
+            errors("if (true) { let a = 1; }; a + \"s\";"),
+            vec![(
+                "Type mismatch: expected int, found string".to_string(),
+                "\"s\""
+            )]
+        );
+        assert_eq!(
+            errors("let x = if (true) { 1 }; x + 1;"),
+            vec![("Type mismatch: expected int, found null".to_string(), "x")]
+        );
+        assert_eq!(
+            errors(r#"let h = {"a": 1, "b": true}; h[0];"#),
+            vec![
+                (
+                    "Type mismatch: expected int, found bool".to_string(),
+                    "true"
+                ),
+                ("Type mismatch: expected string, found int".to_string(), "0"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_annotations() {
+        assert_eq!(
+            lets(
+                "let n: int = 5;
+let id = fn(x: [string]) -> [string] { x };
+let apply: fn(fn(int) -> bool, int) -> bool = fn(f, x) { f(x) };"
+            ),
+            vec![
+                "int",
+                "fn([string]) -> [string]",
+                "fn(fn(int) -> bool, int) -> bool"
+            ]
+        );
+        assert_eq!(
+            errors(
+                r#"let s: string = 1;
+let f = fn(a: int) -> bool { a };
+f("x");"#
+            ),
+            vec![
+                ("Type mismatch: expected string, found int".to_string(), "1"),
+                ("Type mismatch: expected bool, found int".to_string(), "fn"),
+                (
+                    "Type mismatch: expected int, found string".to_string(),
+                    "\"x\""
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_annotation_wins() {
+        // a value that does not fit is reported once, and the name keeps
+        // the type it was declared with
+        let source = "let x = 1; let y: bool = x; y + 1;";
+        let y = source.find("y:").unwrap();
+        assert_eq!(
+            typed(source).type_at(y).map(|t| t.to_string()),
+            Some("bool".to_string())
+        );
+        assert_eq!(
+            errors(source),
+            vec![
+                ("Type mismatch: expected bool, found int".to_string(), "x"),
+                ("Type mismatch: expected int, found bool".to_string(), "y"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_declare_and_hover() {
+        let source = "let n = len([1, 2]) + 1; n";
+        let mut parser = Parser::new(Lexer::new(source));
+        let program = parser.parse_program().unwrap();
+        let mut checker = Checker::new(source);
+        let element = checker.fresh();
+        checker.declare("len", Type::function(vec![Type::array(element)], Type::Int));
+        let typed = checker.check(&program);
+        assert!(typed.errors.is_empty(), "{:?}", typed.errors);
+        assert_eq!(
+            typed.type_at(8).map(Type::to_string).as_deref(),
+            Some("fn([int]) -> int")
+        );
+        assert_eq!(typed.type_at(25), Some(&Type::Int));
+        assert_eq!(typed.type_at(3), None);
+    }
+}
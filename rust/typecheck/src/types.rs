@@ -0,0 +1,143 @@
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Type {
+    Int,
+    Bool,
+    String,
+    /// The value of an `if` without `else` and of blocks that end in a
+    /// `let`.
+    Null,
+    Array(Box<Type>),
+    /// A hash from keys of the first type to values of the second.
+    Hash(Box<Type>, Box<Type>),
+    Function(Vec<Type>, Box<Type>),
+    /// A type not known yet, or any type in a generalized binding.
+    Var(u32),
+}
+
+impl Type {
+    pub fn function(parameters: Vec<Type>, result: Type) -> Self {
+        Type::Function(parameters, Box::new(result))
+    }
+
+    pub fn array(element: Type) -> Self {
+        Type::Array(Box::new(element))
+    }
+
+    pub fn hash(key: Type, value: Type) -> Self {
+        Type::Hash(Box::new(key), Box::new(value))
+    }
+
+    /// Type variables in order of first appearance.
+    pub(crate) fn vars(&self, out: &mut Vec<u32>) {
+        match self {
+            Type::Var(v) => {
+                if !out.contains(v) {
+                    out.push(*v);
+                }
+            }
+            Type::Array(element) => element.vars(out),
+            Type::Hash(key, value) => {
+                key.vars(out);
+                value.vars(out);
+            }
+            Type::Function(parameters, result) => {
+                for parameter in parameters {
+                    parameter.vars(out);
+                }
+                result.vars(out);
+            }
+            Type::Int | Type::Bool | Type::String | Type::Null => {}
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, names: &[u32]) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Bool => write!(f, "bool"),
+            Type::String => write!(f, "string"),
+            Type::Null => write!(f, "null"),
+            Type::Array(element) => {
+                write!(f, "[")?;
+                element.write(f, names)?;
+                write!(f, "]")
+            }
+            Type::Hash(key, value) => {
+                write!(f, "{{")?;
+                key.write(f, names)?;
+                write!(f, ": ")?;
+                value.write(f, names)?;
+                write!(f, "}}")
+            }
+            Type::Function(parameters, result) => {
+                write!(f, "fn(")?;
+                for (i, parameter) in parameters.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    parameter.write(f, names)?;
+                }
+                write!(f, ") -> ")?;
+                result.write(f, names)
+            }
+            Type::Var(v) => {
+                let index = names.iter().position(|n| n == v).unwrap_or_default();
+                let letter = (b'a' + (index % 26) as u8) as char;
+                match index / 26 {
+                    0 => write!(f, "{}", letter),
+                    n => write!(f, "{}{}", letter, n),
+                }
+            }
+        }
+    }
+}
+
+/// Type variables are named `a`, `b`, ... in order of appearance, so equal
+/// types print the same whatever their variables are numbered.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = Vec::new();
+        self.vars(&mut names);
+        self.write(f, &names)
+    }
+}
+
+/// A type generalized over `vars`, which stand for any type at each use.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Scheme {
+    pub vars: Vec<u32>,
+    pub ty: Type,
+}
+
+impl Scheme {
+    /// A type that means the same at every use.
+    pub fn mono(ty: Type) -> Self {
+        Scheme {
+            vars: Vec::new(),
+            ty,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let ty = Type::function(
+            vec![Type::Var(7), Type::array(Type::Var(3))],
+            Type::function(vec![Type::Var(7)], Type::Var(3)),
+        );
+        assert_eq!(ty.to_string(), "fn(a, [b]) -> fn(a) -> b");
+        assert_eq!(
+            Type::function(vec![], Type::array(Type::String)).to_string(),
+            "fn() -> [string]"
+        );
+        assert_eq!(
+            Type::hash(Type::String, Type::array(Type::Var(2))).to_string(),
+            "{string: [a]}"
+        );
+    }
+}